@@ -1,5 +1,6 @@
-use std::collections::{HashMap, VecDeque};
-use std::num::{NonZero, NonZeroUsize};
+use std::cmp::Reverse;
+use std::collections::{BTreeSet, BinaryHeap, HashMap, VecDeque};
+use std::num::NonZeroUsize;
 
 // =========================================================================
 // PARTEA 1: DEFINIȚIILE (Reconstruite din cerința temei)
@@ -19,6 +20,8 @@ pub struct ProcessInfo {
     pub pid: Pid,
     pub state: ProcessState,
     pub timings: (u128, u128, u128), // Simplificat pentru test
+    pub priority: Option<i8>,   // doar pentru scheduler-ele cu prioritati
+    pub vruntime: Option<u128>, // doar pentru CFS
 }
 
 #[derive(Debug)]
@@ -30,7 +33,7 @@ pub enum SchedulingDecision {
     Done,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum Syscall {
     Fork(i8),           // prioritate
     Sleep(NonZeroUsize),
@@ -50,6 +53,114 @@ pub enum SyscallResult {
     Pid(Pid),
     Success,
     NoRunningProcess,
+    // Fork respins: tabela de procese e plina (ca EAGAIN)
+    ForkFailed,
+}
+
+// Ce vede un tracer: fiecare decizie luata de next() si rezultatul
+// fiecarui stop(), impreuna cu procesul oprit
+#[derive(Debug)]
+pub enum TraceEvent<'a> {
+    Next(&'a SchedulingDecision),
+    Stop { pid: Pid, result: &'a SyscallResult },
+    // Un stop() a raportat mai mult timp ramas decat s-a alocat procesului
+    Overreported { pid: Pid, remaining: usize, allocated: usize },
+}
+
+pub type Tracer = Box<dyn FnMut(TraceEvent<'_>)>;
+
+// Apeleaza tracer-ul, daca exista; nu atinge starea scheduler-ului
+fn trace(tracer: &mut Option<Tracer>, event: TraceEvent<'_>) {
+    if let Some(tracer) = tracer {
+        tracer(event);
+    }
+}
+
+// Contoare agregate, utile ca verificare (fork-uri - exit-uri = procese ramase)
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct SchedulerStats {
+    pub forks: u64,
+    pub exits: u64,
+    pub timeslices: u64, // de cate ori next() a dat procesorul cuiva
+    pub sleeps: u64,     // de cate ori next() a ales Sleep
+    // stop()-uri care au raportat mai mult timp ramas decat s-a alocat
+    pub overreported: u64,
+}
+
+impl SchedulerStats {
+    fn record_next(&mut self, decision: &SchedulingDecision) {
+        match decision {
+            SchedulingDecision::Run { .. } => self.timeslices += 1,
+            SchedulingDecision::Sleep(_) => self.sleeps += 1,
+            _ => {}
+        }
+    }
+
+    // Un Exit conteaza doar daca procesul chiar exista
+    fn record_stop(&mut self, exited: bool, result: &SyscallResult) {
+        if let SyscallResult::Pid(_) = result {
+            self.forks += 1;
+        }
+        if exited {
+            self.exits += 1;
+        }
+    }
+}
+
+fn is_exit(reason: &StopReason) -> bool {
+    matches!(reason, StopReason::Syscall { syscall: Syscall::Exit, .. })
+}
+
+fn stop_pid(reason: &StopReason) -> Pid {
+    match reason {
+        StopReason::Syscall { pid, .. } | StopReason::Expired { pid } => *pid,
+    }
+}
+
+// Genereaza PID-uri noi. Cu reciclarea pornita, PID-urile proceselor
+// terminate ajung intr-un pool si sunt refolosite, cel mai mic intai.
+#[derive(Debug)]
+struct PidPool {
+    next: Pid,
+    free: BTreeSet<Pid>,
+    recycle: bool,
+}
+
+impl PidPool {
+    fn new() -> Self {
+        Self { next: 1, free: BTreeSet::new(), recycle: false }
+    }
+
+    fn set_recycling(&mut self, enabled: bool) {
+        self.recycle = enabled;
+        if !enabled {
+            self.free.clear();
+        }
+    }
+
+    // `in_use` spune daca PID-ul mai apare undeva in scheduler (de ex. o
+    // intrare ramasa intr-o coada); un astfel de PID nu se refoloseste inca
+    fn allocate(&mut self, in_use: impl Fn(Pid) -> bool) -> Pid {
+        if let Some(&pid) = self.free.iter().find(|&&pid| !in_use(pid)) {
+            self.free.remove(&pid);
+            return pid;
+        }
+        let pid = self.next;
+        self.next += 1;
+        pid
+    }
+
+    fn release(&mut self, pid: Pid) {
+        if self.recycle {
+            self.free.insert(pid);
+        }
+    }
+
+    // Reciclarea ramane cum a fost setata
+    fn reset(&mut self) {
+        self.next = 1;
+        self.free.clear();
+    }
 }
 
 // Aceasta este interfața pe care trebuie să o implementezi
@@ -57,6 +168,10 @@ pub trait Scheduler {
     fn next(&mut self) -> SchedulingDecision;
     fn stop(&mut self, reason: StopReason) -> SyscallResult;
     fn list(&mut self) -> Vec<ProcessInfo>;
+    fn stats(&self) -> SchedulerStats;
+    // Goleste tabela de procese si reporneste numerotarea PID-urilor de la 1,
+    // ca acelasi scheduler sa poata fi refolosit intre scenarii
+    fn reset(&mut self);
 }
 
 
@@ -69,7 +184,6 @@ struct CfsProcess {
 
 // Implementăm compararea doar pe baza vruntime-ului
 use std::cmp::Ordering;
-use std::os::unix::process;
 
 impl PartialEq for CfsProcess {
     fn eq(&self, other: &Self) -> bool {
@@ -94,21 +208,60 @@ struct MyProcess {
     pid: Pid,
     state: ProcessState,
     priority: i8,
+    boosted: bool, // RoundRobin: la expirare revine in fata cozii
     // Aici am putea stoca cât mai are de dormit etc.
 }
 
+const MAX_PRIORITY: i8 = 5;
+
+// Prioritatea ceruta la Fork poate fi orice i8, asa ca o saturam in 0..=5
+fn clamp_priority(prio: i8) -> i8 {
+    prio.clamp(0, MAX_PRIORITY)
+}
+
+// Indexul cozii pentru o prioritate; un i8 negativ convertit direct la
+// usize ar da un index urias si ar face panic
+fn queue_index(prio: i8) -> usize {
+    clamp_priority(prio) as usize
+}
+
 pub struct RoundRobin {
     processes: HashMap<Pid, MyProcess>,
     queue: VecDeque<Pid>,
+    max_processes: usize, // cate procese pot exista simultan
     timeslice: NonZeroUsize,
-    next_pid: Pid, // Counter pentru a genera PID-uri noi
+    pids: PidPool, // genereaza PID-uri noi
+    tracer: Option<Tracer>,
+    stats: SchedulerStats,
 }
 
 pub struct RobinPriority {
     processes: HashMap<Pid, MyProcess>,
     queues: [VecDeque<Pid>; 6],
+    events: HashMap<usize, Vec<Pid>>,
+    sleeping: HashMap<Pid, usize>,
+    max_processes: usize,
     timeslice: NonZeroUsize,
-    next_pid: Pid,
+    pids: PidPool,
+    tracer: Option<Tracer>,
+    stats: SchedulerStats,
+}
+
+// Ruleaza mereu procesul Ready cu prioritatea cea mai mare. Spre deosebire
+// de RobinPriority, prioritatile nu se schimba, iar un proces care devine
+// Ready cu prioritate mai mare il preia imediat pe cel care rula.
+pub struct PreemptivePriority {
+    processes: HashMap<Pid, MyProcess>,
+    queues: [VecDeque<Pid>; 6],
+    events: HashMap<usize, Vec<Pid>>,
+    sleeping: HashMap<Pid, usize>,
+    max_processes: usize,
+    timeslice: NonZeroUsize,
+    pids: PidPool,
+    interrupted: Option<Pid>, // procesul oprit de un Fork/Signal, care inca poate rula
+    preempted: bool,
+    tracer: Option<Tracer>,
+    stats: SchedulerStats,
 }
 
 impl RoundRobin {
@@ -116,8 +269,38 @@ impl RoundRobin {
         Self {
             processes: HashMap::new(),
             queue: VecDeque::new(),
+            max_processes: usize::MAX,
             timeslice,
-            next_pid: 1, 
+            pids: PidPool::new(),
+            tracer: None,
+            stats: SchedulerStats::default(),
+        }
+    }
+
+    // Ca new(), dar un Fork care ar depasi max_processes procese este respins
+    pub fn with_max_processes(timeslice: NonZeroUsize, max_processes: usize) -> Self {
+        Self { max_processes, ..Self::new(timeslice) }
+    }
+
+    // Porneste/opreste refolosirea PID-urilor proceselor terminate
+    pub fn set_pid_recycling(&mut self, enabled: bool) {
+        self.pids.set_recycling(enabled);
+    }
+
+    // Inregistreaza o functie apelata la fiecare decizie si la fiecare stop()
+    pub fn set_tracer(&mut self, tracer: impl FnMut(TraceEvent<'_>) + 'static) {
+        self.tracer = Some(Box::new(tracer));
+    }
+
+    // Un proces "boosted" care isi consuma timeslice-ul este pus inapoi in
+    // fata cozii, nu la coada. Intoarce false daca procesul nu exista.
+    pub fn set_boosted(&mut self, pid: Pid, boosted: bool) -> bool {
+        match self.processes.get_mut(&pid) {
+            Some(proc) => {
+                proc.boosted = boosted;
+                true
+            }
+            None => false,
         }
     }
 }
@@ -128,37 +311,229 @@ impl RobinPriority {
         Self {
             processes : HashMap :: new(),
             queues,
-            timeslice :  timeslice,
-            next_pid : 1
+            events: HashMap::new(),
+            sleeping: HashMap::new(),
+            max_processes : usize::MAX,
+            timeslice,
+            pids : PidPool::new(),
+            tracer: None,
+            stats: SchedulerStats::default(),
+        }
+    }
+
+    pub fn with_max_processes(timeslice: NonZeroUsize, max_processes: usize) -> Self {
+        Self { max_processes, ..Self::new(timeslice) }
+    }
+
+    // Porneste/opreste refolosirea PID-urilor proceselor terminate
+    pub fn set_pid_recycling(&mut self, enabled: bool) {
+        self.pids.set_recycling(enabled);
+    }
+
+    pub fn set_tracer(&mut self, tracer: impl FnMut(TraceEvent<'_>) + 'static) {
+        self.tracer = Some(Box::new(tracer));
+    }
+
+    // Un proces trezit intra in coada prioritatii pe care o are acum
+    fn make_ready(&mut self, pid: Pid) {
+        if let Some(proc) = self.processes.get_mut(&pid) {
+            proc.state = ProcessState::Ready;
+            self.queues[queue_index(proc.priority)].push_back(pid);
+        }
+    }
+
+    fn bump_priority(&mut self, pid: Pid) {
+        if let Some(proc) = self.processes.get_mut(&pid)
+            && proc.priority < MAX_PRIORITY
+        {
+            proc.priority += 1;
+        }
+    }
+
+    fn block(&mut self, pid: Pid) -> bool {
+        match self.processes.get_mut(&pid) {
+            Some(proc) => {
+                proc.state = ProcessState::Waiting;
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn advance_clock(&mut self, elapsed: usize) {
+        let mut woken = Vec::new();
+        self.sleeping.retain(|&pid, left| {
+            *left = left.saturating_sub(elapsed);
+            if *left == 0 {
+                woken.push(pid);
+            }
+            *left > 0
+        });
+        // Ordinea din HashMap nu e stabila, asa ca ii trezim dupa PID
+        woken.sort_unstable();
+        for pid in woken {
+            self.make_ready(pid);
+        }
+    }
+}
+impl PreemptivePriority {
+    pub fn new(timeslice: NonZeroUsize) -> Self {
+        Self {
+            processes: HashMap::new(),
+            queues: Default::default(),
+            events: HashMap::new(),
+            sleeping: HashMap::new(),
+            max_processes: usize::MAX,
+            timeslice,
+            pids: PidPool::new(),
+            interrupted: None,
+            preempted: false,
+            tracer: None,
+            stats: SchedulerStats::default(),
+        }
+    }
+
+    pub fn with_max_processes(timeslice: NonZeroUsize, max_processes: usize) -> Self {
+        Self { max_processes, ..Self::new(timeslice) }
+    }
+
+    // Porneste/opreste refolosirea PID-urilor proceselor terminate
+    pub fn set_pid_recycling(&mut self, enabled: bool) {
+        self.pids.set_recycling(enabled);
+    }
+
+    pub fn set_tracer(&mut self, tracer: impl FnMut(TraceEvent<'_>) + 'static) {
+        self.tracer = Some(Box::new(tracer));
+    }
+
+    // Spune daca ultimul next() a ales alt proces decat cel intrerupt de un
+    // Fork/Signal, desi acesta putea continua
+    pub fn was_preempted(&self) -> bool {
+        self.preempted
+    }
+
+    fn enqueue(&mut self, pid: Pid, front: bool) {
+        if let Some(proc) = self.processes.get_mut(&pid) {
+            proc.state = ProcessState::Ready;
+            let queue = &mut self.queues[queue_index(proc.priority)];
+            if front {
+                queue.push_front(pid);
+            } else {
+                queue.push_back(pid);
+            }
+        }
+    }
+
+    // Ca la RobinPriority: procesele trezite intra la coada prioritatii lor,
+    // iar unul mai prioritar decat cel care rula il preia la urmatorul next()
+    fn advance_clock(&mut self, elapsed: usize) {
+        let mut woken = Vec::new();
+        self.sleeping.retain(|&pid, left| {
+            *left = left.saturating_sub(elapsed);
+            if *left == 0 {
+                woken.push(pid);
+            }
+            *left > 0
+        });
+        woken.sort_unstable();
+        for pid in woken {
+            self.enqueue(pid, false);
         }
     }
 }
+
 pub struct CfsScheduler {
     processes: HashMap<Pid, CfsProcess>,
+    // Doar procesele Ready stau in heap, ordonate dupa (vruntime, pid).
+    // Reverse transforma max-heap-ul intr-un min-heap.
+    ready: BinaryHeap<Reverse<(u128, Pid)>>,
+    running: Option<Pid>,
+    events: HashMap<usize, Vec<Pid>>, // cine asteapta fiecare eveniment
+    sleeping: HashMap<Pid, usize>,    // cat mai are de dormit fiecare proces
+    max_processes: usize,
     cfs_base_time: NonZeroUsize, // De ex: 20
-    next_pid: Pid,
+    pids: PidPool,
+    tracer: Option<Tracer>,
+    stats: SchedulerStats,
 }
 
 impl CfsScheduler {
     pub fn new(base_time: NonZeroUsize) -> Self {
         Self {
             processes: HashMap::new(),
+            ready: BinaryHeap::new(),
+            running: None,
+            events: HashMap::new(),
+            sleeping: HashMap::new(),
+            max_processes: usize::MAX,
             cfs_base_time: base_time, 
-            next_pid: 1, 
+            pids: PidPool::new(),
+            tracer: None,
+            stats: SchedulerStats::default(),
+        }
+    }
+
+    pub fn with_max_processes(base_time: NonZeroUsize, max_processes: usize) -> Self {
+        Self { max_processes, ..Self::new(base_time) }
+    }
+
+    // Porneste/opreste refolosirea PID-urilor proceselor terminate
+    pub fn set_pid_recycling(&mut self, enabled: bool) {
+        self.pids.set_recycling(enabled);
+    }
+
+    pub fn set_tracer(&mut self, tracer: impl FnMut(TraceEvent<'_>) + 'static) {
+        self.tracer = Some(Box::new(tracer));
+    }
+
+    // Felia de timp a unui proces: timpul de baza impartit la procesele
+    // care pot rula (Ready + cel care ruleaza). Folosita si de next() la
+    // acordare, si de stop() la taxare, ca cele doua sa fie mereu egale.
+    fn timeslice(&self) -> usize {
+        let runnable = self.ready.len() + usize::from(self.running.is_some());
+        let slice = self.cfs_base_time.get() / runnable.max(1);
+        slice.max(1)
+    }
+
+    // Marcheaza procesul ca Ready si il pune in heap (o singura data)
+    fn make_ready(&mut self, pid: Pid) {
+        if let Some(proc) = self.processes.get_mut(&pid)
+            && proc.state != ProcessState::Ready
+        {
+            proc.state = ProcessState::Ready;
+            self.ready.push(Reverse((proc.vruntime, pid)));
+        }
+    }
+
+    // Trece timpul pentru procesele care dorm; cele al caror timp a expirat
+    // devin Ready
+    fn advance_clock(&mut self, elapsed: usize) {
+        let mut woken = Vec::new();
+        self.sleeping.retain(|&pid, left| {
+            *left = left.saturating_sub(elapsed);
+            if *left == 0 {
+                woken.push(pid);
+            }
+            *left > 0
+        });
+        for pid in woken {
+            self.make_ready(pid);
         }
     }
 }
 
-impl Scheduler for RoundRobin {
-    fn next(&mut self) -> SchedulingDecision {
-        if let Some(pid) = self.queue.pop_front() {
-            if let Some(proc) = self.processes.get_mut(&pid) {
-                proc.state = ProcessState::Running;
+// Logica propriu-zisa a scheduler-elor; next()/stop() din trait o
+// invelesc ca sa anunte tracer-ul
+impl RoundRobin {
+    fn pick_next(&mut self) -> SchedulingDecision {
+        if let Some(pid) = self.queue.pop_front()
+            && let Some(proc) = self.processes.get_mut(&pid)
+        {
+            proc.state = ProcessState::Running;
 
-                return SchedulingDecision::Run { 
-                    pid, 
-                    timeslice: self.timeslice };
-            };
+            return SchedulingDecision::Run {
+                pid,
+                timeslice: self.timeslice };
         }
 
         if self.queue.is_empty() {
@@ -168,24 +543,39 @@ impl Scheduler for RoundRobin {
         SchedulingDecision::Done
     }
 
-    fn stop(&mut self, reason: StopReason) -> SyscallResult {
+    fn handle_stop(&mut self, reason: StopReason) -> SyscallResult {
         match reason {
             StopReason::Expired { pid } => {
                 if let Some(proc) = self.processes.get_mut(&pid) {
                     proc.state = ProcessState::Ready;
-                    self.queue.push_back(pid);
+                    if proc.boosted {
+                        self.queue.push_front(pid);
+                    } else {
+                        self.queue.push_back(pid);
+                    }
                 }
-                return SyscallResult::Success;
+                SyscallResult::Success
             },
-            StopReason::Syscall { syscall, remaining, pid } => {
+            StopReason::Syscall { syscall, remaining: _, pid } => {
                 match syscall {
                     Syscall::Fork(prio) => {
-                        let child_pid = self.next_pid;
-                        self.next_pid += 1;
+                        // Tabela plina: copilul nu se creeaza, parintele ramane Ready
+                        if self.processes.len() >= self.max_processes {
+                            if let Some(parent) = self.processes.get_mut(&pid) {
+                                parent.state = ProcessState::Ready;
+                                self.queue.push_back(pid);
+                            }
+                            return SyscallResult::ForkFailed;
+                        }
+                        let child_pid = self.pids.allocate(|candidate| {
+                            self.processes.contains_key(&candidate)
+                                || self.queue.contains(&candidate)
+                        });
                         let new_proc = MyProcess {
                             pid : child_pid,
                             state : ProcessState::Ready,
-                            priority : prio,
+                            priority : clamp_priority(prio),
+                            boosted : false,
                         };
                         self.processes.insert(child_pid, new_proc);
                         self.queue.push_back(child_pid);
@@ -193,50 +583,91 @@ impl Scheduler for RoundRobin {
                             parent.state = ProcessState::Ready;
                             self.queue.push_back(pid);
                         }
-                        return SyscallResult::Pid(child_pid);
+                        SyscallResult::Pid(child_pid)
                     },
                     Syscall::Exit => {
-                        self.processes.remove(&pid);
-                        return SyscallResult::Success;
+                        if self.processes.remove(&pid).is_some() {
+                            self.pids.release(pid);
+                        }
+                        SyscallResult::Success
                     }
                     _ => {
                         if let Some(proc) = self.processes.get_mut(&pid) {
                             proc.state = ProcessState::Ready;
                             self.queue.push_back(pid);
                         }
-                        return SyscallResult::Success;
+                        SyscallResult::Success
                     }
                 }
             }
         }
     }
+}
+
+impl Scheduler for RoundRobin {
+    fn next(&mut self) -> SchedulingDecision {
+        let decision = self.pick_next();
+        self.stats.record_next(&decision);
+        trace(&mut self.tracer, TraceEvent::Next(&decision));
+        decision
+    }
+
+    fn stop(&mut self, reason: StopReason) -> SyscallResult {
+        let pid = stop_pid(&reason);
+        let exited = is_exit(&reason) && self.processes.contains_key(&pid);
+        let result = self.handle_stop(reason);
+        self.stats.record_stop(exited, &result);
+        trace(&mut self.tracer, TraceEvent::Stop { pid, result: &result });
+        result
+    }
 
     fn list(&mut self) -> Vec<ProcessInfo> {
         self.processes.values().map( |p| ProcessInfo {
             pid : p.pid,
             state : p.state.clone(),
-            timings : (0,0,0,)
+            timings : (0,0,0),
+            priority : Some(p.priority),
+            vruntime : None
         }).collect()
     }
+
+    fn stats(&self) -> SchedulerStats {
+        self.stats
+    }
+
+    fn reset(&mut self) {
+        self.stats = SchedulerStats::default();
+        self.processes.clear();
+        self.queue.clear();
+        self.pids.reset();
+    }
 }
 
-impl Scheduler for RobinPriority {
-    fn next(&mut self) -> SchedulingDecision {
+impl RobinPriority {
+    fn pick_next(&mut self) -> SchedulingDecision {
+        // Cozile sunt indexate dupa prioritate: indexul 5 este prioritatea
+        // cea mai mare, asa ca le parcurgem mereu de la 5 spre 0.
         for i in (0..6).rev() {
-            if !self.queues[i].is_empty() {
-                if let Some(pid) = self.queues[i].pop_front() {
-                    if let Some(proc) = self.processes.get_mut(&pid) {
-                        proc.state = ProcessState::Running;
-                        return SchedulingDecision::Run { pid, timeslice: (self.timeslice) };
-                    }
-
-                }
+            if let Some(pid) = self.queues[i].pop_front()
+                && let Some(proc) = self.processes.get_mut(&pid)
+            {
+                proc.state = ProcessState::Running;
+                return SchedulingDecision::Run { pid, timeslice: self.timeslice };
             }
-        }  
-        return SchedulingDecision::Sleep(NonZeroUsize::new(1).unwrap());
+        }
+        if let Some(&wait) = self.sleeping.values().min() {
+            self.advance_clock(wait);
+            return SchedulingDecision::Sleep(NonZeroUsize::new(wait).unwrap());
+        }
+        if !self.processes.is_empty()
+            && self.processes.values().all(|p| p.state == ProcessState::Waiting)
+        {
+            return SchedulingDecision::Deadlock;
+        }
+        SchedulingDecision::Sleep(NonZeroUsize::new(1).unwrap())
     }
 
-    fn stop(&mut self, reason: StopReason) -> SyscallResult {
+    fn handle_stop(&mut self, reason: StopReason) -> SyscallResult {
         match reason {
             StopReason::Expired { pid } => {
                 if let Some(proc) = self.processes.get_mut(&pid) {
@@ -244,164 +675,415 @@ impl Scheduler for RobinPriority {
                         proc.priority -= 1;
                     }
                     proc.state = ProcessState::Ready;
-                    self.queues[proc.priority as usize].push_back(pid);
+                    self.queues[queue_index(proc.priority)].push_back(pid);
                 }
-                return SyscallResult::Success;
+                self.advance_clock(self.timeslice.get());
+                SyscallResult::Success
             }
             StopReason::Syscall { syscall, remaining, pid } => {
+                self.advance_clock(self.timeslice.get().saturating_sub(remaining));
                 match syscall {
                     Syscall::Exit => {
-                        self.processes.remove(&pid);
-                        return SyscallResult::Success;
+                        if self.processes.remove(&pid).is_some() {
+                            self.pids.release(pid);
+                        }
+                        SyscallResult::Success
                     }
                     Syscall::Fork(prio) => {
-                            let childProc =  MyProcess {
-                                pid : self.next_pid,
+                            if self.processes.len() >= self.max_processes {
+                                self.bump_priority(pid);
+                                self.make_ready(pid);
+                                return SyscallResult::ForkFailed;
+                            }
+                            let child_pid = self.pids.allocate(|candidate| {
+                                self.processes.contains_key(&candidate)
+                                    || self.queues.iter().any(|queue| queue.contains(&candidate))
+                                    || self.sleeping.contains_key(&candidate)
+                                    || self.events.values().any(|w| w.contains(&candidate))
+                            });
+                            let child_proc =  MyProcess {
+                                pid : child_pid,
                                 state : ProcessState::Ready,
-                                priority : prio
+                                priority : clamp_priority(prio),
+                                boosted : false,
                             };
-                            self.next_pid += 1;
-                            self.queues[childProc.priority as usize].push_back(childProc.pid);
-                            let kpid = childProc.pid;
-                            self.processes.insert(childProc.pid, childProc);   
+                            self.queues[queue_index(child_proc.priority)].push_back(child_proc.pid);
+                            let kpid = child_proc.pid;
+                            self.processes.insert(child_proc.pid, child_proc);
                             if let Some(proc) = self.processes.get_mut(&pid) {
-                                if proc.priority < 5 {
+                                if proc.priority < MAX_PRIORITY {
                                     proc.priority += 1;
                                 }
                                 proc.state = ProcessState::Ready;
-                                self.queues[proc.priority as usize].push_back(pid);
+                                self.queues[queue_index(proc.priority)].push_back(pid);
                             }
-                            return  SyscallResult::Pid(kpid);
+                            SyscallResult::Pid(kpid)
                     }
-                    _ => {
-                        if let Some(proc) = self.processes.get_mut(&pid) {
-                            if (proc.priority < 5) {
-                                    proc.priority += 1;
-                            }
-                            proc.state = ProcessState::Ready;
-                            self.queues[proc.priority as usize].push_back(pid);
+                    // Si un proces care se blocheaza a cedat procesorul de bunavoie,
+                    // deci primeste aceeasi crestere de prioritate
+                    Syscall::Wait(event) => {
+                        self.bump_priority(pid);
+                        if self.block(pid) {
+                            self.events.entry(event).or_default().push(pid);
                         }
-                        return SyscallResult::Success;
+                        SyscallResult::Success
+                    }
+                    Syscall::Sleep(duration) => {
+                        self.bump_priority(pid);
+                        if self.block(pid) {
+                            self.sleeping.insert(pid, duration.get());
+                        }
+                        SyscallResult::Success
+                    }
+                    Syscall::Signal(event) => {
+                        for waiter in self.events.remove(&event).unwrap_or_default() {
+                            self.make_ready(waiter);
+                        }
+                        self.bump_priority(pid);
+                        self.make_ready(pid);
+                        SyscallResult::Success
                     }
                 }
             }
         }
     }
+}
+
+impl Scheduler for RobinPriority {
+    fn next(&mut self) -> SchedulingDecision {
+        let decision = self.pick_next();
+        self.stats.record_next(&decision);
+        trace(&mut self.tracer, TraceEvent::Next(&decision));
+        decision
+    }
+
+    fn stop(&mut self, reason: StopReason) -> SyscallResult {
+        let pid = stop_pid(&reason);
+        let exited = is_exit(&reason) && self.processes.contains_key(&pid);
+        let result = self.handle_stop(reason);
+        self.stats.record_stop(exited, &result);
+        trace(&mut self.tracer, TraceEvent::Stop { pid, result: &result });
+        result
+    }
 
     fn list(&mut self) -> Vec<ProcessInfo> {
-        self.processes.values().map(|p| ProcessInfo {
+        let mut infos: Vec<ProcessInfo> = self.processes.values().map(|p| ProcessInfo {
             pid : p.pid,
             state : p.state.clone(),
-            timings : (0,0,0)
-        }).collect()
+            timings : (0,0,0),
+            priority : Some(p.priority),
+            vruntime : None
+        }).collect();
+        // HashMap nu are o ordine stabila, asa ca sortam dupa PID
+        infos.sort_by_key(|p| p.pid);
+        infos
+    }
+
+    fn stats(&self) -> SchedulerStats {
+        self.stats
+    }
+
+    fn reset(&mut self) {
+        self.stats = SchedulerStats::default();
+        self.processes.clear();
+        self.queues.iter_mut().for_each(VecDeque::clear);
+        self.events.clear();
+        self.sleeping.clear();
+        self.pids.reset();
     }
 }
 
-impl Scheduler for CfsScheduler {
-    fn next(&mut self) -> SchedulingDecision {
-        let ready_pid : Vec<Pid> = self.processes
-        .iter()
-        .filter(|(_,p)| p.state == ProcessState::Ready)
-        .map(|(pid, _)| *pid)
-        .collect();
+impl PreemptivePriority {
+    fn pick_next(&mut self) -> SchedulingDecision {
+        let interrupted = self.interrupted.take();
+        self.preempted = false;
 
-        if ready_pid.len() == 0 {
-            if self.processes.is_empty() {
-                return SchedulingDecision::Done;
-            } else {
-                return SchedulingDecision::Sleep(NonZeroUsize::new(1).unwrap());
+        // Indexul 5 este prioritatea cea mai mare
+        for i in (0..6).rev() {
+            if let Some(pid) = self.queues[i].pop_front()
+                && let Some(proc) = self.processes.get_mut(&pid)
+            {
+                proc.state = ProcessState::Running;
+                self.preempted = interrupted.is_some_and(|p| p != pid);
+                return SchedulingDecision::Run { pid, timeslice: self.timeslice };
             }
         }
 
-        let base = self.cfs_base_time.get();
-        let slice_clc = base / ready_pid.len();
-        let final_slice = if slice_clc < 1 {1} else {slice_clc};
-        let timeslice = NonZeroUsize :: new(final_slice).unwrap();
-
-        let mut min_vruntime = u128::max_value();
-        let mut s_pid = 0;
+        if self.processes.is_empty() {
+            return SchedulingDecision::Done;
+        }
+        // Dormim exact pana se trezeste primul proces adormit
+        if let Some(&wait) = self.sleeping.values().min() {
+            self.advance_clock(wait);
+            return SchedulingDecision::Sleep(NonZeroUsize::new(wait).unwrap());
+        }
+        // Nimeni nu e Ready si nimeni nu doarme: toti asteapta un Signal
+        // pe care nu il mai poate da nimeni
+        SchedulingDecision::Deadlock
+    }
 
-        for pid in ready_pid {
-            if let Some(proc) = self.processes.get_mut(&pid) {
-                if proc.vruntime < min_vruntime {
-                    min_vruntime = proc.vruntime;
-                    s_pid = pid;
+    fn handle_stop(&mut self, reason: StopReason) -> SyscallResult {
+        self.interrupted = None;
+        match reason {
+            StopReason::Expired { pid } => {
+                self.enqueue(pid, false);
+                self.advance_clock(self.timeslice.get());
+                SyscallResult::Success
+            }
+            StopReason::Syscall { syscall, remaining, pid } => {
+                self.advance_clock(self.timeslice.get().saturating_sub(remaining));
+                match syscall {
+                    Syscall::Exit => {
+                        if self.processes.remove(&pid).is_some() {
+                            self.pids.release(pid);
+                        }
+                        SyscallResult::Success
+                    }
+                    Syscall::Fork(prio) => {
+                        if self.processes.len() >= self.max_processes {
+                            if self.processes.contains_key(&pid) {
+                                self.enqueue(pid, true);
+                                self.interrupted = Some(pid);
+                            }
+                            return SyscallResult::ForkFailed;
+                        }
+                        let child_pid = self.pids.allocate(|candidate| {
+                            self.processes.contains_key(&candidate)
+                                || self.queues.iter().any(|queue| queue.contains(&candidate))
+                                || self.sleeping.contains_key(&candidate)
+                                || self.events.values().any(|waiters| waiters.contains(&candidate))
+                        });
+                        self.processes.insert(child_pid, MyProcess {
+                            pid: child_pid,
+                            state: ProcessState::Ready,
+                            priority: clamp_priority(prio),
+                            boosted: false,
+                        });
+                        self.enqueue(child_pid, false);
+                        // Parintele isi pastreaza randul in fata cozii lui
+                        if self.processes.contains_key(&pid) {
+                            self.enqueue(pid, true);
+                            self.interrupted = Some(pid);
+                        }
+                        SyscallResult::Pid(child_pid)
+                    }
+                    Syscall::Wait(event) => {
+                        if let Some(proc) = self.processes.get_mut(&pid) {
+                            proc.state = ProcessState::Waiting;
+                            self.events.entry(event).or_default().push(pid);
+                        }
+                        SyscallResult::Success
+                    }
+                    Syscall::Signal(event) => {
+                        for waiter in self.events.remove(&event).unwrap_or_default() {
+                            self.enqueue(waiter, false);
+                        }
+                        if self.processes.contains_key(&pid) {
+                            self.enqueue(pid, true);
+                            self.interrupted = Some(pid);
+                        }
+                        SyscallResult::Success
+                    }
+                    Syscall::Sleep(duration) => {
+                        if let Some(proc) = self.processes.get_mut(&pid) {
+                            proc.state = ProcessState::Waiting;
+                            self.sleeping.insert(pid, duration.get());
+                        }
+                        SyscallResult::Success
+                    }
                 }
             }
         }
+    }
+}
+
+impl Scheduler for PreemptivePriority {
+    fn next(&mut self) -> SchedulingDecision {
+        let decision = self.pick_next();
+        self.stats.record_next(&decision);
+        trace(&mut self.tracer, TraceEvent::Next(&decision));
+        decision
+    }
+
+    fn stop(&mut self, reason: StopReason) -> SyscallResult {
+        let pid = stop_pid(&reason);
+        let exited = is_exit(&reason) && self.processes.contains_key(&pid);
+        let result = self.handle_stop(reason);
+        self.stats.record_stop(exited, &result);
+        trace(&mut self.tracer, TraceEvent::Stop { pid, result: &result });
+        result
+    }
+
+    fn list(&mut self) -> Vec<ProcessInfo> {
+        let mut infos: Vec<ProcessInfo> = self.processes.values().map(|p| ProcessInfo {
+            pid : p.pid,
+            state : p.state.clone(),
+            timings : (0,0,0),
+            priority : Some(p.priority),
+            vruntime : None
+        }).collect();
+        infos.sort_by_key(|p| p.pid);
+        infos
+    }
+
+    fn stats(&self) -> SchedulerStats {
+        self.stats
+    }
+
+    fn reset(&mut self) {
+        self.stats = SchedulerStats::default();
+        self.processes.clear();
+        self.queues.iter_mut().for_each(VecDeque::clear);
+        self.events.clear();
+        self.sleeping.clear();
+        self.pids.reset();
+        self.interrupted = None;
+        self.preempted = false;
+    }
+}
+
+impl CfsScheduler {
+    fn pick_next(&mut self) -> SchedulingDecision {
+        if self.ready.is_empty() {
+            if self.processes.is_empty() {
+                return SchedulingDecision::Done;
+            }
+            // Dormim exact pana se trezeste primul proces adormit
+            if let Some(&wait) = self.sleeping.values().min() {
+                self.advance_clock(wait);
+                return SchedulingDecision::Sleep(NonZeroUsize::new(wait).unwrap());
+            }
+            // Daca toate procesele asteapta un eveniment, nimeni nu mai poate
+            // da Signal, deci nu are rost sa dormim la nesfarsit
+            if self.processes.values().all(|p| p.state == ProcessState::Waiting) {
+                return SchedulingDecision::Deadlock;
+            }
+            return SchedulingDecision::Sleep(NonZeroUsize::new(1).unwrap());
+        }
+
+        let timeslice = NonZeroUsize :: new(self.timeslice()).unwrap();
+
+        // Varful heap-ului este procesul cu vruntime minim
+        let Reverse((_, s_pid)) = self.ready.pop().unwrap();
 
         if let Some(proc) = self.processes.get_mut(&s_pid) {
             proc.state = ProcessState::Running;
-            
+            self.running = Some(s_pid);
         }
 
-        return SchedulingDecision::Run {
+        SchedulingDecision::Run {
         pid: s_pid,
         timeslice,
-        };
+        }
 
     }
 
-    fn stop(&mut self, reason: StopReason) -> SyscallResult {
-
-        let count_ready = self.processes
-        .iter().
-        filter(|(_, proc)| proc.state != ProcessState::Waiting)
-        .count();
+    fn handle_stop(&mut self, reason: StopReason) -> SyscallResult {
 
-        let base = self.cfs_base_time.get();
-        let active_procs = self.processes.iter()
-        .filter(|(_, p)| p.state == ProcessState::Ready || p.state == ProcessState::Running)
-        .count();
-
-        let safe_count = if active_procs == 0 {1} else {active_procs};
-
-        let slice_calc = base / safe_count;
-        let allocated_time = if slice_calc < 1 { 1 } else { slice_calc };
+        let allocated_time = self.timeslice();
+        self.running = None;
 
         match reason {
             StopReason::Expired { pid  } => {
                 if let Some(proc) = self.processes.get_mut(&pid) {
                     proc.vruntime += allocated_time as u128;
-                    proc.state = ProcessState::Ready;
                 }
-                return SyscallResult::Success;
+                self.advance_clock(allocated_time);
+                self.make_ready(pid);
+                SyscallResult::Success
             }
             StopReason::Syscall { syscall, remaining, pid } => {
-                let executed = allocated_time - remaining;
+                // Un apelant inconsistent poate raporta mai mult timp ramas decat
+                // s-a alocat; il tratam ca pe un proces care n-a rulat deloc, il
+                // numaram in statistici si il semnalam tracer-ului
+                if remaining > allocated_time {
+                    self.stats.overreported += 1;
+                    let event =
+                        TraceEvent::Overreported { pid, remaining, allocated: allocated_time };
+                    trace(&mut self.tracer, event);
+                }
+                let executed = allocated_time.saturating_sub(remaining);
                 if let Some(proc) = self.processes.get_mut(&pid) {
                     proc.vruntime += executed as u128;
                 }
+                self.advance_clock(executed);
                 match syscall {
                     Syscall::Exit =>{
-                        self.processes.remove(&pid);
-                        return SyscallResult::Success;
+                        if let Some(proc) = self.processes.remove(&pid) {
+                            if proc.state == ProcessState::Ready {
+                                self.ready.retain(|Reverse((_, p))| *p != pid);
+                            }
+                            self.pids.release(pid);
+                        }
+                        SyscallResult::Success
                     }
-                    Syscall::Fork(prio) => {
+                    Syscall::Fork(_) => {
+                        if self.processes.len() >= self.max_processes {
+                            self.make_ready(pid);
+                            return SyscallResult::ForkFailed;
+                        }
                         let parent_runtime = self.processes.get_mut(&pid).map(|p|p.vruntime).unwrap_or(0);
+                        let k_pid = self.pids.allocate(|candidate| {
+                            self.processes.contains_key(&candidate)
+                                || self.ready.iter().any(|Reverse((_, p))| *p == candidate)
+                                || self.sleeping.contains_key(&candidate)
+                                || self.events.values().any(|waiters| waiters.contains(&candidate))
+                        });
                         let k_process = CfsProcess {
-                            pid : self.next_pid,
+                            pid : k_pid,
                             state : ProcessState::Ready,
                             vruntime : parent_runtime
                         };
-                        self.next_pid += 1;
-                        let k_pid = k_process.pid;
+                        self.ready.push(Reverse((k_process.vruntime, k_pid)));
                         self.processes.insert(k_pid, k_process);
+                        self.make_ready(pid);
+                        SyscallResult::Pid(k_pid)
+                    }
+                    // Procesele blocate nu stau in heap, deci nici nu intra
+                    // in impartirea timpului de baza
+                    Syscall::Wait(event) => {
                         if let Some(proc) = self.processes.get_mut(&pid) {
-                            proc.state = ProcessState::Ready;
+                            proc.state = ProcessState::Waiting;
+                            self.events.entry(event).or_default().push(pid);
                         }
-                        return SyscallResult::Pid(k_pid);
+                        SyscallResult::Success
                     }
-                    _ => {
+                    Syscall::Signal(event) => {
+                        for waiter in self.events.remove(&event).unwrap_or_default() {
+                            self.make_ready(waiter);
+                        }
+                        self.make_ready(pid);
+                        SyscallResult::Success
+                    }
+                    Syscall::Sleep(duration) => {
                         if let Some(proc) = self.processes.get_mut(&pid) {
-                            proc.state = ProcessState::Ready;
+                            proc.state = ProcessState::Waiting;
+                            self.sleeping.insert(pid, duration.get());
                         }
-                        return SyscallResult::Success;
+                        SyscallResult::Success
                     }
                 }
             }
         }
     }
+}
+
+impl Scheduler for CfsScheduler {
+    fn next(&mut self) -> SchedulingDecision {
+        let decision = self.pick_next();
+        self.stats.record_next(&decision);
+        trace(&mut self.tracer, TraceEvent::Next(&decision));
+        decision
+    }
+
+    fn stop(&mut self, reason: StopReason) -> SyscallResult {
+        let pid = stop_pid(&reason);
+        let exited = is_exit(&reason) && self.processes.contains_key(&pid);
+        let result = self.handle_stop(reason);
+        self.stats.record_stop(exited, &result);
+        trace(&mut self.tracer, TraceEvent::Stop { pid, result: &result });
+        result
+    }
 
     fn list(&mut self) -> Vec<ProcessInfo> {
         self.processes
@@ -409,90 +1091,637 @@ impl Scheduler for CfsScheduler {
         .map(|p|  ProcessInfo{
             pid : p.pid,
             state : p.state.clone(),
-            timings : (0,0,0)
+            timings : (0,0,0),
+            priority : None,
+            vruntime : Some(p.vruntime)
         }).collect()
     }
+
+    fn stats(&self) -> SchedulerStats {
+        self.stats
+    }
+
+    fn reset(&mut self) {
+        self.stats = SchedulerStats::default();
+        self.processes.clear();
+        self.ready.clear();
+        self.running = None;
+        self.events.clear();
+        self.sleeping.clear();
+        self.pids.reset();
+    }
+}
+
+// Construieste scheduler-ul dupa nume, ca simulatorul sa poata rula
+// oricare dintre algoritmi cu aceeasi bucla.
+pub fn make_scheduler(kind: &str, timeslice: NonZeroUsize) -> Result<Box<dyn Scheduler>, String> {
+    match kind {
+        "rr" => Ok(Box::new(RoundRobin::new(timeslice))),
+        "priority" => Ok(Box::new(RobinPriority::new(timeslice))),
+        "preemptive" => Ok(Box::new(PreemptivePriority::new(timeslice))),
+        "cfs" => Ok(Box::new(CfsScheduler::new(timeslice))),
+        _ => Err(format!("unknown scheduler kind '{}' (expected rr, priority, preemptive or cfs)", kind)),
+    }
 }
 
 // =========================================================================
 // PARTEA 3: SIMULATORUL (Scenariul de test)
 // =========================================================================
 
-fn main() {
-    // 1. Definim timpul de bază pentru CFS
-    let base_time = NonZeroUsize::new(20).unwrap(); // Timp total mai mare, ca să se împartă
-    
-    // 2. Inițializăm CFS Scheduler (NU RobinPriority)
-    println!("=== TEST CFS SCHEDULER ===");
-    let mut scheduler = CfsScheduler::new(base_time);
+// Ce se intampla la un pas al scenariului
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ScenarioAction {
+    // Daca pid primeste procesorul, face apelul de sistem dupa `used` unitati.
+    // La pasul 0 apelul se face inainte de primul next(), de ex. pentru
+    // primul Fork al lui PID 0.
+    Syscall { pid: Pid, syscall: Syscall, used: usize },
+    // Nu face nimic, doar prelungeste simularea pana la acest pas
+    End,
+}
 
-    println!("--- 1. Initializare: Sistemul porneste ---");
-    
-    // PID 0 crează PID 1
-    println!("[Simulator] Trimitem primul FORK...");
-    let result = scheduler.stop(StopReason::Syscall {
-        syscall: Syscall::Fork(0),
-        remaining: 0,
-        pid: 0, 
-    });
-    println!("[Simulator] Rezultat Fork initial: {:?}", result);
-
-    println!("\n--- 2. Incepem bucla de executie ---");
-
-    // Simulăm mai mulți pași
-    for pas in 1..=15 {
-        println!("\n>> PASUL {}", pas);
-        
-        // 1. NEXT
-        let decision = scheduler.next();
-        println!("[Scheduler] Decizie: {:?}", decision);
+#[derive(Debug)]
+pub struct SimulationStep {
+    pub step: usize,
+    pub decision: Option<SchedulingDecision>, // None la pasul 0
+    pub result: Option<SyscallResult>,         // None daca nu s-a apelat stop()
+}
 
-        match decision {
+#[derive(Debug, Default)]
+pub struct SimulationLog {
+    pub steps: Vec<SimulationStep>,
+}
+
+// Ruleaza scheduler-ul prin scenariu pana la ultimul pas din `events` sau
+// pana nu mai are ce rula. Procesul ales care nu are o actiune la pasul
+// curent isi consuma tot timeslice-ul.
+pub fn run_simulation(
+    scheduler: &mut dyn Scheduler,
+    events: &[(usize, ScenarioAction)],
+) -> SimulationLog {
+    let mut log = SimulationLog::default();
+    let last_step = events.iter().map(|&(step, _)| step).max().unwrap_or(0);
+
+    for &(step, action) in events {
+        if step == 0
+            && let ScenarioAction::Syscall { pid, syscall, used: _ } = action
+        {
+            let result = scheduler.stop(StopReason::Syscall { syscall, remaining: 0, pid });
+            log.steps.push(SimulationStep { step, decision: None, result: Some(result) });
+        }
+    }
+
+    for step in 1..=last_step {
+        let decision = scheduler.next();
+        let finished = matches!(
+            decision,
+            SchedulingDecision::Done | SchedulingDecision::Deadlock | SchedulingDecision::Panic
+        );
+        let result = match decision {
             SchedulingDecision::Run { pid, timeslice } => {
-                println!("[CPU] Ruleaza PID {} cu timeslice {}", pid, timeslice);
-                
-                // Scenariu:
-                // Pas 2: PID 1 face Fork -> Apare PID 2
-                // Pas 5: PID 2 face Fork -> Apare PID 3
-                // Pas 10: PID 1 face Exit
-                
-                if pas == 2 && pid == 1 {
-                    println!("      -> Face FORK!");
-                    scheduler.stop(StopReason::Syscall {
-                        syscall: Syscall::Fork(0),
-                        remaining: timeslice.get() - 2, // A consumat 2 unități
-                        pid,
-                    });
-                } else if pas == 5 && pid == 2 {
-                    println!("      -> Face FORK (PID 2 face copil)!");
-                    scheduler.stop(StopReason::Syscall {
-                        syscall: Syscall::Fork(0),
-                        remaining: timeslice.get() - 1,
-                        pid,
-                    });
-                } else if pas == 10 && pid == 1 {
-                    println!("      -> Face EXIT!");
-                    scheduler.stop(StopReason::Syscall {
-                        syscall: Syscall::Exit,
-                        remaining: 1,
+                let syscall = events.iter().find_map(|&(at, action)| match action {
+                    ScenarioAction::Syscall { pid: actor, syscall, used }
+                        if at == step && actor == pid =>
+                    {
+                        Some((syscall, used))
+                    }
+                    _ => None,
+                });
+                Some(match syscall {
+                    Some((syscall, used)) => scheduler.stop(StopReason::Syscall {
+                        syscall,
+                        remaining: timeslice.get().saturating_sub(used),
                         pid,
-                    });
-                } else {
-                    println!("      -> Expired (Consuma tot timpul)");
-                    scheduler.stop(StopReason::Expired { pid });
-                }
-            },
-            SchedulingDecision::Done => {
-                println!("[Simulator] Gata!");
-                break;
+                    }),
+                    None => scheduler.stop(StopReason::Expired { pid }),
+                })
             }
-            _ => { println!("Waiting..."); }
+            _ => None,
+        };
+        log.steps.push(SimulationStep { step, decision: Some(decision), result });
+        if finished {
+            break;
+        }
+    }
+    log
+}
+
+fn main() {
+    // 1. Definim timpul de bază (timeslice pentru RR, timp total pentru CFS)
+    let base_time = NonZeroUsize::new(20).unwrap(); // Timp total mai mare, ca să se împartă
+    
+    // 2. Alegem algoritmul din linia de comanda (implicit CFS)
+    let kind = std::env::args().nth(1).unwrap_or_else(|| String::from("cfs"));
+    let mut scheduler = match make_scheduler(&kind, base_time) {
+        Ok(scheduler) => scheduler,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    };
+    println!("=== TEST {} SCHEDULER ===", kind.to_uppercase());
+
+    // Scenariu:
+    // Pas 0: PID 0 creeaza PID 1
+    // Pas 2: PID 1 face Fork -> Apare PID 2
+    // Pas 5: PID 2 face Fork -> Apare PID 3
+    // Pas 10: PID 1 face Exit
+    // Restul pasilor: procesul ales consuma tot timpul, pana la pasul 15
+    let scenario = [
+        (0, ScenarioAction::Syscall { pid: 0, syscall: Syscall::Fork(0), used: 0 }),
+        (2, ScenarioAction::Syscall { pid: 1, syscall: Syscall::Fork(0), used: 2 }),
+        (5, ScenarioAction::Syscall { pid: 2, syscall: Syscall::Fork(0), used: 1 }),
+        (10, ScenarioAction::Syscall { pid: 1, syscall: Syscall::Exit, used: 1 }),
+        (15, ScenarioAction::End),
+    ];
+    let log = run_simulation(scheduler.as_mut(), &scenario);
+
+    for entry in &log.steps {
+        println!("\n>> PASUL {}", entry.step);
+        if let Some(decision) = &entry.decision {
+            println!("[Scheduler] Decizie: {:?}", decision);
+        }
+        if let Some(result) = &entry.result {
+            println!("[Simulator] Rezultat: {:?}", result);
         }
     }
     
     println!("\n--- Stare finala (Vruntime check) ---");
-    // Nu putem vedea vruntime prin list() standard, dar vedem PID-urile rămase
     for p in scheduler.list() {
-        println!("PID: {}, Stare: {:?}", p.pid, p.state);
+        println!("PID: {}, Stare: {:?}, Prioritate: {:?}, Vruntime: {:?}", p.pid, p.state, p.priority, p.vruntime);
     }
-}
\ No newline at end of file
+    println!("Statistici: {:?}", scheduler.stats());
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fork(scheduler: &mut dyn Scheduler, parent: Pid, prio: i8) -> Pid {
+        match scheduler.stop(StopReason::Syscall {
+            syscall: Syscall::Fork(prio),
+            remaining: 0,
+            pid: parent,
+        }) {
+            SyscallResult::Pid(pid) => pid,
+            other => panic!("fork failed: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn robin_priority_list_is_sorted_by_pid() {
+        let mut scheduler = RobinPriority::new(NonZeroUsize::new(5).unwrap());
+        for prio in [3, 0, 5, 1, 4, 2, 5, 0] {
+            fork(&mut scheduler, 0, prio);
+        }
+
+        let pids: Vec<Pid> = scheduler.list().iter().map(|p| p.pid).collect();
+        assert_eq!(pids, (1..=8).collect::<Vec<Pid>>());
+    }
+
+    #[test]
+    fn robin_priority_runs_highest_queue_first() {
+        let mut scheduler = RobinPriority::new(NonZeroUsize::new(5).unwrap());
+        fork(&mut scheduler, 0, 1);
+        let high = fork(&mut scheduler, 0, 5);
+
+        match scheduler.next() {
+            SchedulingDecision::Run { pid, .. } => assert_eq!(pid, high),
+            other => panic!("unexpected decision: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn cfs_heap_picks_minimum_vruntime_with_many_processes() {
+        let mut scheduler = CfsScheduler::new(NonZeroUsize::new(1_000_000).unwrap());
+        for _ in 0..3000 {
+            fork(&mut scheduler, 0, 0);
+        }
+
+        // Fiecare proces consuma o cantitate diferita de timp, ca sa avem
+        // vruntime-uri amestecate
+        let mut seed: usize = 7;
+        for _ in 0..5000 {
+            let expected = scheduler
+                .processes
+                .values()
+                .filter(|p| p.state == ProcessState::Ready)
+                .map(|p| (p.vruntime, p.pid))
+                .min()
+                .unwrap();
+
+            let (pid, timeslice) = match scheduler.next() {
+                SchedulingDecision::Run { pid, timeslice } => (pid, timeslice),
+                other => panic!("unexpected decision: {:?}", other),
+            };
+            assert_eq!(pid, expected.1);
+
+            seed = seed.wrapping_mul(1103515245).wrapping_add(12345) % 2147483648;
+            scheduler.stop(StopReason::Syscall {
+                syscall: Syscall::Sleep(NonZeroUsize::new(1).unwrap()),
+                remaining: seed % timeslice.get(),
+                pid,
+            });
+        }
+    }
+
+    #[test]
+    fn make_scheduler_selects_by_name() {
+        let timeslice = NonZeroUsize::new(5).unwrap();
+        for kind in ["rr", "priority", "cfs"] {
+            let mut scheduler = make_scheduler(kind, timeslice).unwrap();
+            assert_eq!(fork(scheduler.as_mut(), 0, 0), 1);
+        }
+        assert!(make_scheduler("fifo", timeslice).is_err());
+    }
+
+    fn run(scheduler: &mut dyn Scheduler) -> Pid {
+        match scheduler.next() {
+            SchedulingDecision::Run { pid, .. } => pid,
+            other => panic!("unexpected decision: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn preemptive_priority_switches_to_higher_priority_child() {
+        let mut scheduler = PreemptivePriority::new(NonZeroUsize::new(5).unwrap());
+        let parent = fork(&mut scheduler, 0, 1);
+        assert_eq!(run(&mut scheduler), parent);
+
+        // Un copil cu aceeasi prioritate nu il intrerupe pe parinte
+        fork(&mut scheduler, parent, 1);
+        assert_eq!(run(&mut scheduler), parent);
+        assert!(!scheduler.was_preempted());
+
+        let high = fork(&mut scheduler, parent, 4);
+        assert_eq!(run(&mut scheduler), high);
+        assert!(scheduler.was_preempted());
+    }
+
+    #[test]
+    fn preemptive_priority_signal_wakes_higher_priority_waiter() {
+        let mut scheduler = PreemptivePriority::new(NonZeroUsize::new(5).unwrap());
+        let low = fork(&mut scheduler, 0, 0);
+        let high = fork(&mut scheduler, 0, 5);
+
+        assert_eq!(run(&mut scheduler), high);
+        scheduler.stop(StopReason::Syscall { syscall: Syscall::Wait(1), remaining: 3, pid: high });
+        assert_eq!(run(&mut scheduler), low);
+        assert!(!scheduler.was_preempted());
+
+        scheduler.stop(StopReason::Syscall { syscall: Syscall::Signal(1), remaining: 2, pid: low });
+        assert_eq!(run(&mut scheduler), high);
+        assert!(scheduler.was_preempted());
+    }
+
+    #[test]
+    fn preemptive_priority_sleep_parks_the_process() {
+        let mut scheduler = PreemptivePriority::new(NonZeroUsize::new(5).unwrap());
+        let high = fork(&mut scheduler, 0, 4);
+        let low = fork(&mut scheduler, 0, 1);
+        let sleep = |units| Syscall::Sleep(NonZeroUsize::new(units).unwrap());
+
+        // Cat timp doarme, high nu mai e ales, desi are prioritate mai mare
+        assert_eq!(run(&mut scheduler), high);
+        scheduler.stop(StopReason::Syscall { syscall: sleep(3), remaining: 4, pid: high });
+        assert_eq!(run(&mut scheduler), low);
+        scheduler.stop(StopReason::Expired { pid: low });
+        assert_eq!(run(&mut scheduler), high);
+
+        // Cand dorm toti, planificatorul doarme pana la primul care se trezeste
+        scheduler.stop(StopReason::Syscall { syscall: sleep(2), remaining: 5, pid: high });
+        assert_eq!(run(&mut scheduler), low);
+        scheduler.stop(StopReason::Syscall { syscall: sleep(4), remaining: 5, pid: low });
+        assert!(matches!(scheduler.next(), SchedulingDecision::Sleep(units) if units.get() == 2));
+        assert_eq!(run(&mut scheduler), high);
+    }
+
+    #[test]
+    fn reset_clears_processes_and_pids() {
+        let timeslice = NonZeroUsize::new(5).unwrap();
+        for kind in ["rr", "priority", "preemptive", "cfs"] {
+            let mut scheduler = make_scheduler(kind, timeslice).unwrap();
+            fork(scheduler.as_mut(), 0, 2);
+            fork(scheduler.as_mut(), 0, 3);
+            run(scheduler.as_mut());
+
+            scheduler.reset();
+            assert!(scheduler.list().is_empty(), "{} still has processes", kind);
+            assert_eq!(fork(scheduler.as_mut(), 0, 0), 1, "{} did not reset pids", kind);
+            assert_eq!(run(scheduler.as_mut()), 1);
+        }
+    }
+
+    #[test]
+    fn list_reports_priority_and_vruntime() {
+        let mut priority = RobinPriority::new(NonZeroUsize::new(5).unwrap());
+        fork(&mut priority, 0, 3);
+        let info = &priority.list()[0];
+        assert_eq!((info.priority, info.vruntime), (Some(3), None));
+
+        let mut cfs = CfsScheduler::new(NonZeroUsize::new(20).unwrap());
+        let pid = fork(&mut cfs, 0, 0);
+        run(&mut cfs);
+        cfs.stop(StopReason::Expired { pid });
+        let info = &cfs.list()[0];
+        assert_eq!((info.priority, info.vruntime), (None, Some(20)));
+    }
+
+    #[test]
+    fn fork_priority_is_clamped() {
+        let timeslice = NonZeroUsize::new(5).unwrap();
+        for kind in ["rr", "priority"] {
+            let mut scheduler = make_scheduler(kind, timeslice).unwrap();
+            fork(scheduler.as_mut(), 0, -3);
+            fork(scheduler.as_mut(), 0, 99);
+            let mut priorities: Vec<Option<i8>> = scheduler.list().iter().map(|p| p.priority).collect();
+            priorities.sort();
+            assert_eq!(priorities, vec![Some(0), Some(5)], "{}", kind);
+        }
+    }
+
+    #[test]
+    fn robin_priority_negative_fork_priority_does_not_panic() {
+        let mut scheduler = RobinPriority::new(NonZeroUsize::new(5).unwrap());
+        let pid = fork(&mut scheduler, 0, -1);
+        assert_eq!(run(&mut scheduler), pid);
+
+        // Expirarea la prioritatea 0 trebuie sa ramana in coada 0
+        scheduler.stop(StopReason::Expired { pid });
+        assert_eq!(scheduler.queues[0].front(), Some(&pid));
+        assert_eq!(run(&mut scheduler), pid);
+    }
+
+    #[test]
+    fn cfs_charges_the_granted_timeslice() {
+        let mut scheduler = CfsScheduler::new(NonZeroUsize::new(20).unwrap());
+        for _ in 0..3 {
+            fork(&mut scheduler, 0, 0);
+        }
+
+        let (pid, granted) = match scheduler.next() {
+            SchedulingDecision::Run { pid, timeslice } => (pid, timeslice.get()),
+            other => panic!("unexpected decision: {:?}", other),
+        };
+        scheduler.stop(StopReason::Expired { pid });
+
+        assert_eq!(granted, 6);
+        assert_eq!(scheduler.processes[&pid].vruntime, granted as u128);
+    }
+
+    #[test]
+    fn cfs_reports_deadlock_when_everyone_waits() {
+        let mut scheduler = CfsScheduler::new(NonZeroUsize::new(20).unwrap());
+        let first = fork(&mut scheduler, 0, 0);
+        let second = fork(&mut scheduler, 0, 0);
+
+        // Fiecare proces asteapta un eveniment pe care doar celalalt l-ar semnala
+        assert_eq!(run(&mut scheduler), first);
+        scheduler.stop(StopReason::Syscall { syscall: Syscall::Wait(1), remaining: 5, pid: first });
+        assert_eq!(run(&mut scheduler), second);
+        scheduler.stop(StopReason::Syscall { syscall: Syscall::Wait(2), remaining: 5, pid: second });
+
+        assert!(matches!(scheduler.next(), SchedulingDecision::Deadlock));
+    }
+
+    #[test]
+    fn tracer_records_decisions_and_stops() {
+        use std::cell::RefCell;
+        use std::rc::Rc;
+
+        let log = Rc::new(RefCell::new(Vec::new()));
+        let mut scheduler = RoundRobin::new(NonZeroUsize::new(5).unwrap());
+        let sink = Rc::clone(&log);
+        scheduler.set_tracer(move |event| sink.borrow_mut().push(format!("{:?}", event)));
+
+        let pid = fork(&mut scheduler, 0, 0);
+        assert_eq!(run(&mut scheduler), pid);
+        scheduler.stop(StopReason::Syscall { syscall: Syscall::Exit, remaining: 1, pid });
+
+        assert_eq!(*log.borrow(), vec![
+            "Stop { pid: 0, result: Pid(1) }",
+            "Next(Run { pid: 1, timeslice: 5 })",
+            "Stop { pid: 1, result: Success }",
+        ]);
+        // Tracer-ul doar observa: scheduler-ul a ajuns in aceeasi stare
+        assert!(scheduler.list().is_empty());
+    }
+
+    #[test]
+    fn stats_count_forks_exits_and_decisions() {
+        let timeslice = NonZeroUsize::new(5).unwrap();
+        for kind in ["rr", "priority", "preemptive", "cfs"] {
+            let mut scheduler = make_scheduler(kind, timeslice).unwrap();
+            let first = fork(scheduler.as_mut(), 0, 1);
+            fork(scheduler.as_mut(), first, 1);
+            fork(scheduler.as_mut(), first, 1);
+
+            let pid = run(scheduler.as_mut());
+            scheduler.stop(StopReason::Syscall { syscall: Syscall::Exit, remaining: 1, pid });
+            // Un Exit pentru un proces inexistent nu se numara
+            scheduler.stop(StopReason::Syscall { syscall: Syscall::Exit, remaining: 1, pid: 42 });
+
+            let stats = scheduler.stats();
+            assert_eq!((stats.forks, stats.exits, stats.timeslices), (3, 1, 1), "{}", kind);
+            assert_eq!(stats.forks - stats.exits, scheduler.list().len() as u64, "{}", kind);
+
+            scheduler.reset();
+            assert_eq!(scheduler.stats(), SchedulerStats::default(), "{}", kind);
+        }
+    }
+
+    #[test]
+    fn cfs_wait_signal_blocks_and_resumes() {
+        let mut scheduler = CfsScheduler::new(NonZeroUsize::new(10).unwrap());
+        let waiter = fork(&mut scheduler, 0, 0);
+        let signaler = fork(&mut scheduler, waiter, 0);
+
+        assert_eq!(run(&mut scheduler), waiter);
+        scheduler.stop(StopReason::Syscall { syscall: Syscall::Wait(7), remaining: 5, pid: waiter });
+
+        // Cat timp asteapta, waiter nu mai e ales si nu mai imparte timpul de baza
+        for _ in 0..3 {
+            match scheduler.next() {
+                SchedulingDecision::Run { pid, timeslice } => {
+                    assert_eq!((pid, timeslice.get()), (signaler, 10));
+                }
+                other => panic!("unexpected decision: {:?}", other),
+            }
+            scheduler.stop(StopReason::Expired { pid: signaler });
+        }
+
+        assert_eq!(run(&mut scheduler), signaler);
+        scheduler.stop(StopReason::Syscall { syscall: Syscall::Signal(7), remaining: 9, pid: signaler });
+        // waiter are vruntime mai mic, deci ruleaza imediat dupa trezire
+        assert_eq!(run(&mut scheduler), waiter);
+        scheduler.stop(StopReason::Syscall { syscall: Syscall::Exit, remaining: 1, pid: waiter });
+
+        // Un proces adormit lasa planificatorul sa doarma exact cat trebuie
+        assert_eq!(run(&mut scheduler), signaler);
+        let sleep = Syscall::Sleep(NonZeroUsize::new(3).unwrap());
+        scheduler.stop(StopReason::Syscall { syscall: sleep, remaining: 8, pid: signaler });
+        assert!(matches!(scheduler.next(), SchedulingDecision::Sleep(units) if units.get() == 3));
+        assert_eq!(run(&mut scheduler), signaler);
+    }
+
+    #[test]
+    fn robin_priority_wait_signal_requeues_at_current_priority() {
+        let mut scheduler = RobinPriority::new(NonZeroUsize::new(5).unwrap());
+        let high = fork(&mut scheduler, 0, 4);
+        let low = fork(&mut scheduler, 0, 1);
+        let priorities = |scheduler: &mut RobinPriority| -> Vec<Option<i8>> {
+            scheduler.list().iter().map(|p| p.priority).collect()
+        };
+
+        assert_eq!(run(&mut scheduler), high);
+        scheduler.stop(StopReason::Syscall { syscall: Syscall::Wait(3), remaining: 2, pid: high });
+        // Wait este tot o cedare voluntara, deci prioritatea creste
+        assert_eq!(priorities(&mut scheduler), [Some(5), Some(1)]);
+
+        // Desi are prioritate mai mare, high nu e ales cat timp asteapta
+        assert_eq!(run(&mut scheduler), low);
+        scheduler.stop(StopReason::Expired { pid: low });
+        assert_eq!(run(&mut scheduler), low);
+        scheduler.stop(StopReason::Syscall { syscall: Syscall::Signal(3), remaining: 4, pid: low });
+        assert_eq!(priorities(&mut scheduler), [Some(5), Some(1)]);
+
+        assert_eq!(run(&mut scheduler), high);
+        assert_eq!(run(&mut scheduler), low);
+    }
+
+    #[test]
+    fn cfs_remaining_larger_than_the_slice_does_not_underflow() {
+        use std::cell::RefCell;
+        use std::rc::Rc;
+
+        let mut scheduler = CfsScheduler::new(NonZeroUsize::new(10).unwrap());
+        let pid = fork(&mut scheduler, 0, 0);
+        let log = Rc::new(RefCell::new(Vec::new()));
+        let sink = Rc::clone(&log);
+        scheduler.set_tracer(move |event| {
+            if let TraceEvent::Overreported { .. } = event {
+                sink.borrow_mut().push(format!("{:?}", event));
+            }
+        });
+        assert_eq!(run(&mut scheduler), pid);
+        let result = scheduler.stop(StopReason::Syscall {
+            syscall: Syscall::Fork(0),
+            remaining: 1000,
+            pid,
+        });
+        assert!(matches!(result, SyscallResult::Pid(2)));
+        let vruntimes: Vec<Option<u128>> = scheduler.list().iter().map(|p| p.vruntime).collect();
+        assert_eq!(vruntimes, [Some(0), Some(0)]);
+        assert_eq!(scheduler.stats().overreported, 1);
+        assert_eq!(*log.borrow(), ["Overreported { pid: 1, remaining: 1000, allocated: 10 }"]);
+    }
+
+    #[test]
+    fn round_robin_requeues_boosted_process_at_the_front() {
+        let mut scheduler = RoundRobin::new(NonZeroUsize::new(5).unwrap());
+        let first = fork(&mut scheduler, 0, 0);
+        let second = fork(&mut scheduler, 0, 0);
+        assert!(scheduler.set_boosted(first, true));
+        assert!(!scheduler.set_boosted(42, true));
+
+        assert_eq!(run(&mut scheduler), first);
+        scheduler.stop(StopReason::Expired { pid: first });
+        assert_eq!(run(&mut scheduler), first);
+
+        scheduler.set_boosted(first, false);
+        scheduler.stop(StopReason::Expired { pid: first });
+        assert_eq!(run(&mut scheduler), second);
+    }
+
+    #[test]
+    fn fork_past_the_process_limit_is_rejected() {
+        let timeslice = NonZeroUsize::new(5).unwrap();
+        let schedulers: [Box<dyn Scheduler>; 4] = [
+            Box::new(RoundRobin::with_max_processes(timeslice, 2)),
+            Box::new(RobinPriority::with_max_processes(timeslice, 2)),
+            Box::new(PreemptivePriority::with_max_processes(timeslice, 2)),
+            Box::new(CfsScheduler::with_max_processes(timeslice, 2)),
+        ];
+        for mut scheduler in schedulers {
+            let parent = fork(scheduler.as_mut(), 0, 1);
+            fork(scheduler.as_mut(), parent, 1);
+
+            let pid = run(scheduler.as_mut());
+            let result = scheduler.stop(StopReason::Syscall {
+                syscall: Syscall::Fork(1),
+                remaining: 1,
+                pid,
+            });
+            assert!(matches!(result, SyscallResult::ForkFailed));
+            assert_eq!(scheduler.list().len(), 2);
+            assert_eq!(scheduler.stats().forks, 2);
+            // Parintele a ramas Ready si poate fi planificat din nou
+            assert!(scheduler.list().iter().all(|p| p.state == ProcessState::Ready));
+        }
+    }
+
+    #[test]
+    fn exited_pids_are_recycled_lowest_first_when_enabled() {
+        let timeslice = NonZeroUsize::new(5).unwrap();
+        let mut rr = RoundRobin::new(timeslice);
+        let mut priority = RobinPriority::new(timeslice);
+        let mut preemptive = PreemptivePriority::new(timeslice);
+        let mut cfs = CfsScheduler::new(timeslice);
+        rr.set_pid_recycling(true);
+        priority.set_pid_recycling(true);
+        preemptive.set_pid_recycling(true);
+        cfs.set_pid_recycling(true);
+        let schedulers: [Box<dyn Scheduler>; 5] = [
+            Box::new(rr),
+            Box::new(priority),
+            Box::new(preemptive),
+            Box::new(cfs),
+            Box::new(RoundRobin::new(timeslice)),
+        ];
+        for (i, mut scheduler) in schedulers.into_iter().enumerate() {
+            fork(scheduler.as_mut(), 0, 1);
+            fork(scheduler.as_mut(), 0, 1);
+            assert_eq!(run(scheduler.as_mut()), 1);
+            scheduler.stop(StopReason::Syscall { syscall: Syscall::Exit, remaining: 1, pid: 1 });
+
+            let expected = if i < 4 { 1 } else { 3 };
+            assert_eq!(fork(scheduler.as_mut(), 0, 1), expected, "scheduler {}", i);
+        }
+    }
+
+    #[test]
+    fn recycled_pid_is_not_reused_while_still_queued() {
+        let mut scheduler = RoundRobin::new(NonZeroUsize::new(5).unwrap());
+        scheduler.set_pid_recycling(true);
+        fork(&mut scheduler, 0, 0);
+        fork(&mut scheduler, 0, 0);
+        // PID 2 iese fara sa fi rulat, deci ramane o intrare veche in coada
+        scheduler.stop(StopReason::Syscall { syscall: Syscall::Exit, remaining: 1, pid: 2 });
+        assert_eq!(fork(&mut scheduler, 0, 0), 3);
+    }
+
+    #[test]
+    fn run_simulation_follows_the_scenario_until_done() {
+        let scenario = [
+            (0, ScenarioAction::Syscall { pid: 0, syscall: Syscall::Fork(0), used: 0 }),
+            (1, ScenarioAction::Syscall { pid: 1, syscall: Syscall::Fork(0), used: 1 }),
+            (2, ScenarioAction::Syscall { pid: 1, syscall: Syscall::Exit, used: 1 }),
+            (3, ScenarioAction::Syscall { pid: 2, syscall: Syscall::Exit, used: 1 }),
+            (10, ScenarioAction::End),
+        ];
+        for kind in ["rr", "priority", "preemptive", "cfs"] {
+            let mut scheduler = make_scheduler(kind, NonZeroUsize::new(5).unwrap()).unwrap();
+            let log = run_simulation(scheduler.as_mut(), &scenario);
+            assert!(matches!(log.steps[1].result, Some(SyscallResult::Pid(2))), "{}", kind);
+            assert!(log.steps.len() <= 11, "{}", kind);
+        }
+
+        // CFS raporteaza Done, deci simularea se opreste inainte de pasul 10
+        let mut cfs = make_scheduler("cfs", NonZeroUsize::new(5).unwrap()).unwrap();
+        let log = run_simulation(cfs.as_mut(), &scenario);
+        let runs: Vec<Option<Pid>> = log.steps.iter().map(|entry| match entry.decision {
+            Some(SchedulingDecision::Run { pid, .. }) => Some(pid),
+            _ => None,
+        }).collect();
+        assert_eq!(runs, [None, Some(1), Some(1), Some(2), None]);
+        assert!(matches!(log.steps[4].decision, Some(SchedulingDecision::Done)));
+    }
+}
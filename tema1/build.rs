@@ -0,0 +1,17 @@
+use std::process::Command;
+
+// Exposes the short git hash of the checkout (empty outside a git tree)
+// as RUSTYBOX_GIT_HASH for the `version` command.
+fn main() {
+    let hash = Command::new("git")
+        .args(["rev-parse", "--short", "HEAD"])
+        .output()
+        .ok()
+        .filter(|output| output.status.success())
+        .map(|output| String::from_utf8_lossy(&output.stdout).trim().to_string())
+        .unwrap_or_default();
+
+    println!("cargo:rustc-env=RUSTYBOX_GIT_HASH={}", hash);
+    println!("cargo:rerun-if-changed=../.git/HEAD");
+    println!("cargo:rerun-if-changed=../.git/refs");
+}
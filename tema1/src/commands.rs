@@ -1,47 +1,253 @@
 use anyhow::{anyhow, Result};
+use std::collections::HashMap;
 use std::fs;
-use std::io::{self, Read};
-use std::os::unix::fs::{PermissionsExt, symlink}; // Import symlink here
+use std::io::{self, BufRead, IsTerminal, Read, Seek, SeekFrom, Write};
+use std::os::unix::fs::{DirBuilderExt, FileTypeExt, MetadataExt, OpenOptionsExt, PermissionsExt, symlink}; // Import symlink here
 use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{LazyLock, Mutex, Once};
 use filetime::{set_file_times, FileTime};
+use crate::glob;
+use crate::shell;
+use crate::timestamp;
+use rand::distr::{Alphanumeric, SampleString};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
 
 
+/// When set, destructive commands only print what they would do.
+#[derive(Debug, Clone, Copy)]
+pub struct DryRun(pub bool);
+
+/// How much commands report on stderr. `Quiet` drops the per-file error
+/// messages; exit codes are the same either way.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Verbosity {
+    Quiet,
+    #[default]
+    Normal,
+}
+
+impl Verbosity {
+    // Prints one per-item error unless running quietly
+    fn error(self, message: std::fmt::Arguments<'_>) {
+        if self != Verbosity::Quiet {
+            eprintln!("{}", message);
+        }
+    }
+}
+
+/// The exit status for a command that does not exist, as shells use it.
+pub const COMMAND_NOT_FOUND: i32 = 127;
+
+/// The error `dispatch_command` returns for a name that is not a command.
+#[derive(Debug)]
+pub struct UnknownCommand(pub String);
+
+impl std::fmt::Display for UnknownCommand {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Invalid command: {}", self.0)
+    }
+}
+
+impl std::error::Error for UnknownCommand {}
+
+/// The exit status for a command that failed with an error instead of
+/// returning one: `COMMAND_NOT_FOUND` for an unknown command, -1 otherwise.
+pub fn error_exit_code(error: &anyhow::Error) -> i32 {
+    if error.is::<UnknownCommand>() {
+        COMMAND_NOT_FOUND
+    } else {
+        -1
+    }
+}
+
 pub fn dispatch_command(command_name: &str, args: &[String]) -> Result<i32> {
-    match command_name {
-        "pwd" => handle_pwd(args),
-        "echo" => handle_echo(args),
-        "cat" => handle_cat(args),
-        "mkdir" => handle_mkdir(args),
-        "mv" => handle_mv(args),
-        "ln" => handle_ln(args),
-        "rmdir" => handle_rmdir(args),
-        "rm" => handle_rm(args),
-        "ls" => handle_ls(args),
-        "cp" => handle_cp(args),
-        "touch" => handle_touch(args),
-        "chmod" => handle_chmod(args),
-        _ => Err(anyhow!("Invalid command: {}", command_name)),
-    }
-}
-
-fn handle_pwd(args: &[String]) -> Result<i32> {
+    let mut command_name = command_name;
+    let mut args = args;
+    let mut dry_run = DryRun(false);
+    let mut verbosity = Verbosity::Normal;
+    let mut expand_globs = false;
+
+    // Global flags come before the command name
+    while command_name.starts_with("--") || command_name == "-q" {
+        match command_name {
+            "--dry-run" => dry_run = DryRun(true),
+            "-q" | "--quiet" => verbosity = Verbosity::Quiet,
+            "--glob" => expand_globs = true,
+            _ => break,
+        }
+        match args.split_first() {
+            Some((next, rest)) => {
+                command_name = next;
+                args = rest;
+            }
+            None => return Err(anyhow!("missing command after {}", command_name)),
+        }
+    }
+
+    // Expand wildcards ourselves when the caller asks for it, for contexts
+    // where no shell did it first
+    let expanded: Vec<String>;
+    if expand_globs {
+        expanded = args
+            .iter()
+            .flat_map(|arg| if arg.starts_with('-') { vec![arg.clone()] } else { glob::expand(arg) })
+            .collect();
+        args = &expanded;
+    }
+
+    // `--version`, `--time` and `--shell` are commands spelled like global flags
+    let command_name = match command_name {
+        "--version" => "version",
+        "--time" => "time",
+        "--shell" => "shell",
+        name => name,
+    };
+
+    match COMMANDS.iter().find(|(name, _)| *name == command_name) {
+        Some((_, handler)) => handler(args, dry_run, verbosity),
+        None => Err(UnknownCommand(command_name.to_string()).into()),
+    }
+}
+
+// Runs a command on behalf of another one (find -exec, xargs, the shell, ...),
+// passing on the global flags the outer command was given
+pub fn dispatch_nested(words: &[String], dry_run: DryRun, verbosity: Verbosity) -> Result<i32> {
+    let mut flags = Vec::new();
+    if dry_run.0 {
+        flags.push("--dry-run".to_string());
+    }
+    if verbosity == Verbosity::Quiet {
+        flags.push("--quiet".to_string());
+    }
+    let words = [flags, words.to_vec()].concat();
+    match words.split_first() {
+        Some((command, args)) => dispatch_command(command, args),
+        None => Err(anyhow!("missing command")),
+    }
+}
+
+type Handler = fn(&[String], DryRun, Verbosity) -> Result<i32>;
+
+/// Every command `dispatch_command` knows, in the order `commands` lists them.
+const COMMANDS: &[(&str, Handler)] = &[
+    ("pwd", |args, _, _| handle_pwd(args, &mut io::stdout().lock())),
+    ("echo", |args, _, _| handle_echo(args, &mut io::stdout().lock())),
+    ("cat", |args, _, _| on_std_streams(handle_cat, args)),
+    ("mkdir", |args, dry_run, _| handle_mkdir(args, dry_run)),
+    ("mv", handle_mv),
+    ("ln", |args, _, _| handle_ln(args)),
+    ("rmdir", handle_rmdir),
+    ("rm", handle_rm),
+    ("ls", |args, _, verbosity| {
+        let stdout = io::stdout();
+        handle_ls(args, verbosity, &mut stdout.lock(), stdout.is_terminal())
+    }),
+    ("cp", handle_cp),
+    ("touch", |args, _, _| handle_touch(args)),
+    ("chmod", handle_chmod),
+    ("mktemp", |args, _, _| handle_mktemp(args)),
+    ("shuf", |args, _, _| handle_shuf(args)),
+    ("date", |args, _, _| handle_date(args)),
+    ("stat", |args, _, _| handle_stat(args)),
+    ("wc", |args, _, _| on_std_streams(handle_wc, args)),
+    ("basename", |args, _, _| handle_basename(args)),
+    ("du", |args, _, _| handle_du(args)),
+    ("grep", |args, _, _| on_std_streams(handle_grep, args)),
+    ("find", handle_find),
+    ("sort", |args, _, _| handle_sort(args)),
+    ("tr", |args, _, _| handle_tr(args)),
+    ("printf", |args, _, _| handle_printf(args)),
+    ("xargs", handle_xargs),
+    ("head", |args, _, _| handle_head(args)),
+    ("tail", |args, _, _| handle_tail(args)),
+    ("watch", handle_watch),
+    ("dd", |args, dry_run, _| handle_dd(args, dry_run)),
+    ("time", handle_time),
+    ("shell", shell::handle_shell),
+    ("commands", |args, _, _| handle_commands(args)),
+    ("version", |args, _, _| handle_version(args)),
+];
+
+/// A command that reads and writes the streams it is given instead of the
+/// process's own, so the shell can connect it to others in a pipeline.
+pub type StreamHandler = fn(&[String], &mut dyn BufRead, &mut dyn Write) -> Result<i32>;
+
+/// The commands that can take part in a pipeline. Those that only write
+/// ignore their input, and ls never sees a terminal.
+const STREAM_COMMANDS: &[(&str, StreamHandler)] = &[
+    ("pwd", |args, _, out| handle_pwd(args, out)),
+    ("echo", |args, _, out| handle_echo(args, out)),
+    ("cat", handle_cat),
+    ("ls", |args, _, out| handle_ls(args, Verbosity::Normal, out, false)),
+    ("grep", handle_grep),
+    ("wc", handle_wc),
+];
+
+pub fn stream_command(command_name: &str) -> Option<StreamHandler> {
+    STREAM_COMMANDS
+        .iter()
+        .find(|(name, _)| *name == command_name)
+        .map(|(_, handler)| *handler)
+}
+
+// Outside a pipeline a stream command gets the process's stdin and stdout
+fn on_std_streams(handler: StreamHandler, args: &[String]) -> Result<i32> {
+    handler(args, &mut io::stdin().lock(), &mut io::stdout().lock())
+}
+
+static COMMAND_NAMES: LazyLock<Vec<&'static str>> =
+    LazyLock::new(|| COMMANDS.iter().map(|(name, _)| *name).collect());
+
+/// Names of all supported commands, e.g. for shell completion.
+pub fn supported_commands() -> &'static [&'static str] {
+    &COMMAND_NAMES
+}
+
+fn handle_commands(args: &[String]) -> Result<i32> {
+    if !args.is_empty() {
+        return Err(anyhow!("commands: too many arguments"));
+    }
+    for name in supported_commands() {
+        println!("{}", name);
+    }
+    Ok(0)
+}
+
+const VERSION: &str = env!("CARGO_PKG_VERSION");
+const GIT_HASH: &str = env!("RUSTYBOX_GIT_HASH");
+
+fn handle_version(args: &[String]) -> Result<i32> {
+    if !args.is_empty() {
+        return Err(anyhow!("version: too many arguments"));
+    }
+    if GIT_HASH.is_empty() {
+        println!("{} {}", env!("CARGO_PKG_NAME"), VERSION);
+    } else {
+        println!("{} {} ({})", env!("CARGO_PKG_NAME"), VERSION, GIT_HASH);
+    }
+    Ok(0)
+}
+
+fn handle_pwd(args: &[String], out: &mut dyn Write) -> Result<i32> {
     if !args.is_empty() {
         return Err(anyhow!("pwd: too many arguments"));
     }
     match std::env::current_dir() {
         Ok(path) => {
-            println!("{}", path.display());
+            writeln!(out, "{}", path.display())?;
             Ok(0)
         }
         Err(e) => Err(anyhow!("pwd: failed to get current directory: {}", e)),
     }
 }
 
-fn handle_echo(args: &[String]) -> Result<i32> {
+fn handle_echo(args: &[String], out: &mut dyn Write) -> Result<i32> {
     let mut no_newline = false;
     let mut print_args_start_index = 0;
 
-    if let Some(arg) = args.get(0) {
+    if let Some(arg) = args.first() {
         if arg == "-n" {
             no_newline = true;
             print_args_start_index = 1;
@@ -51,69 +257,456 @@ fn handle_echo(args: &[String]) -> Result<i32> {
     let to_print = args[print_args_start_index..].join(" ");
 
     if no_newline {
-        print!("{}", to_print);
+        write!(out, "{}", to_print)?;
     } else {
-        println!("{}", to_print);
+        writeln!(out, "{}", to_print)?;
     }
 
     Ok(0)
 }
 
-fn handle_cat(args: &[String]) -> Result<i32> {
-    if args.is_empty() {
-        return Err(anyhow!("cat: missing file operand"));
+#[derive(Debug, Default, Clone, Copy)]
+struct CatOptions {
+    show_nonprinting: bool,
+    show_tabs: bool,
+    show_ends: bool,
+    number: bool,
+    crlf: bool,
+}
+
+/// Line numbering carried from one `cat -n` input to the next: a file that
+/// ends without a newline leaves the next file's first line unnumbered.
+#[derive(Debug, Default)]
+struct CatLines {
+    numbered: u64,
+    mid_line: bool,
+}
+
+fn handle_cat(args: &[String], input: &mut dyn BufRead, out: &mut dyn Write) -> Result<i32> {
+    let mut options = CatOptions::default();
+    let mut output: Option<&str> = None;
+    // `None` stands for the input stream
+    let mut files: Vec<Option<&str>> = Vec::new();
+    let mut options_ended = false;
+    let mut i = 0;
+    while i < args.len() {
+        let arg = &args[i];
+        i += 1;
+        // After `--` every argument is a file, even one named `-`
+        if options_ended {
+            files.push(Some(arg));
+            continue;
+        }
+        match arg.as_str() {
+            "--" => options_ended = true,
+            "-" => files.push(None),
+            "-o" | "--output" => {
+                let path = args
+                    .get(i)
+                    .ok_or_else(|| anyhow!("cat: option requires an argument -- 'o'"))?;
+                output = Some(path);
+                i += 1;
+            }
+            "-v" => options.show_nonprinting = true,
+            "-T" => options.show_tabs = true,
+            "-E" => options.show_ends = true,
+            "-n" | "--number" => options.number = true,
+            "--crlf" => options.crlf = true,
+            "-A" => {
+                options.show_nonprinting = true;
+                options.show_tabs = true;
+                options.show_ends = true;
+            }
+            _ => files.push(Some(arg)),
+        }
     }
 
-    for file_path in args {
-        match fs::File::open(file_path) {
-            Ok(mut file) => {
-                let mut content = String::new();
-                if let Err(e) = file.read_to_string(&mut content) {
-                    eprintln!("cat: {}: {}", file_path, e);
-                    return Ok(-20);
+    // Like cat, no operands means the input alone
+    if files.is_empty() {
+        files.push(None);
+    }
+
+    let mut file_out;
+    let out: &mut dyn Write = match output {
+        Some(output) => {
+            // Truncating the output first would destroy an input that is the same file
+            if let Ok(target) = fs::metadata(output) {
+                for file_path in files.iter().flatten() {
+                    if let Ok(input) = fs::metadata(file_path) {
+                        if (input.dev(), input.ino()) == (target.dev(), target.ino()) {
+                            eprintln!("cat: {}: input file is output file", file_path);
+                            return Ok(-20);
+                        }
+                    }
                 }
-                print!("{}", content);
             }
-            Err(e) => {
-                eprintln!("cat: {}: {}", file_path, e);
-                return Ok(-20);
+            let file = fs::OpenOptions::new().write(true).create(true).truncate(true).open(output);
+            match file {
+                Ok(file) => {
+                    file_out = io::BufWriter::new(file);
+                    &mut file_out
+                }
+                Err(e) => {
+                    eprintln!("cat: {}: {}", output, e);
+                    return Ok(-20);
+                }
             }
         }
+        None => out,
+    };
+    let mut lines = CatLines::default();
+    for file_path in files {
+        let catted = match file_path {
+            None => cat_reader(&mut *input, options, &mut lines, &mut *out),
+            Some(path) => cat_file(Path::new(path), options, &mut lines, &mut *out),
+        };
+        if let Err(e) = catted {
+            eprintln!("cat: {}: {}", file_path.unwrap_or("-"), e);
+            return Ok(-20);
+        }
     }
+    out.flush()?;
     Ok(0)
 }
 
-fn handle_mkdir(args: &[String]) -> Result<i32> {
-    if args.is_empty() {
+fn cat_file(
+    path: &Path,
+    options: CatOptions,
+    lines: &mut CatLines,
+    out: &mut (impl Write + ?Sized),
+) -> io::Result<()> {
+    let file = fs::File::open(path)?;
+    cat_reader(io::BufReader::new(file), options, lines, out)
+}
+
+// Streams one input to `out`; only the flag-driven modes look at individual lines
+fn cat_reader(
+    mut reader: impl BufRead,
+    options: CatOptions,
+    lines: &mut CatLines,
+    out: &mut (impl Write + ?Sized),
+) -> io::Result<()> {
+    let render = options.show_nonprinting || options.show_tabs || options.show_ends;
+    if !render && !options.number && !options.crlf {
+        io::copy(&mut reader, out)?;
+        return Ok(());
+    }
+
+    let mut line = Vec::new();
+    while reader.read_until(b'\n', &mut line)? > 0 {
+        if options.crlf {
+            strip_crlf(&mut line);
+        }
+        if options.number && !lines.mid_line {
+            lines.numbered += 1;
+            write!(out, "{:>6}\t", lines.numbered)?;
+        }
+        lines.mid_line = !line.ends_with(b"\n");
+        if render {
+            out.write_all(&cat_render(&line, options))?;
+        } else {
+            out.write_all(&line)?;
+        }
+        line.clear();
+    }
+    Ok(())
+}
+
+// With --crlf a "\r\n" ending is a single line terminator, written back as
+// a plain '\n'. Any other '\r' is data, including one left at the very end
+// of an input that has no final newline.
+fn strip_crlf(line: &mut Vec<u8>) {
+    if line.ends_with(b"\r\n") {
+        line.remove(line.len() - 2);
+    }
+}
+
+// Renders raw bytes the way `cat -v/-T/-E` shows them: control characters as
+// ^X, bytes above 127 as M-, tabs as ^I and line ends marked with $
+fn cat_render(content: &[u8], options: CatOptions) -> Vec<u8> {
+    let mut rendered = Vec::with_capacity(content.len());
+    for &byte in content {
+        match byte {
+            b'\n' => {
+                if options.show_ends {
+                    rendered.push(b'$');
+                }
+                rendered.push(b'\n');
+            }
+            b'\t' if options.show_tabs => rendered.extend_from_slice(b"^I"),
+            b'\t' => rendered.push(b'\t'),
+            _ if options.show_nonprinting => {
+                let mut byte = byte;
+                if byte >= 128 {
+                    rendered.extend_from_slice(b"M-");
+                    byte -= 128;
+                }
+                match byte {
+                    0..=31 => rendered.extend_from_slice(&[b'^', byte + 64]),
+                    127 => rendered.extend_from_slice(b"^?"),
+                    _ => rendered.push(byte),
+                }
+            }
+            _ => rendered.push(byte),
+        }
+    }
+    rendered
+}
+
+fn handle_mkdir(args: &[String], dry_run: DryRun) -> Result<i32> {
+    let mut verbose = false;
+    let mut mode: Option<u32> = None;
+    let mut dirs = Vec::new();
+    let mut i = 0;
+    while i < args.len() {
+        let arg = &args[i];
+        i += 1;
+        match arg.as_str() {
+            // Missing parents are always created, so -p changes nothing
+            "-p" | "--parents" => {}
+            "-v" | "--verbose" => verbose = true,
+            "-m" | "--mode" => {
+                let value = args
+                    .get(i)
+                    .ok_or_else(|| anyhow!("mkdir: option requires an argument -- 'm'"))?;
+                i += 1;
+                // Symbolic modes start from a=rwx, as in coreutils
+                let parsed = chmod_apply(value, 0o777, true)
+                    .map_err(|_| anyhow!("mkdir: invalid mode '{}'", value))?;
+                mode = Some(parsed);
+            }
+            _ => dirs.push(arg),
+        }
+    }
+
+    if dirs.is_empty() {
         return Err(anyhow!("mkdir: missing operand"));
     }
 
-    for dir_path in args {
-        if let Err(e) = fs::create_dir_all(dir_path) {
-            eprintln!("mkdir: cannot create directory '{}': {}", dir_path, e);
-            return Ok(-30);
+    for dir_path in dirs {
+        let path = Path::new(dir_path);
+        // Every missing level is created on its own so that -v can report
+        // exactly the ones that are new. A directory that already exists is
+        // not an error.
+        let mut levels: Vec<&Path> = path
+            .ancestors()
+            .filter(|level| !level.as_os_str().is_empty())
+            .take_while(|level| !level.is_dir())
+            .collect();
+        levels.reverse();
+
+        for level in levels {
+            if dry_run.0 {
+                println!("would create directory '{}'", level.display());
+                continue;
+            }
+            // -m only sets the mode of the directory that was asked for, and
+            // exactly, without the umask. Parents get the umask's default,
+            // but always keep u+wx so that the next level can be made.
+            let created = fs::create_dir(level).and_then(|_| {
+                if level != path {
+                    let current = fs::metadata(level)?.permissions().mode() & 0o7777;
+                    if current & 0o300 == 0o300 {
+                        return Ok(());
+                    }
+                    fs::set_permissions(level, fs::Permissions::from_mode(current | 0o300))
+                } else if let Some(mode) = mode {
+                    fs::set_permissions(level, fs::Permissions::from_mode(mode))
+                } else {
+                    Ok(())
+                }
+            });
+            if let Err(e) = created {
+                eprintln!("mkdir: cannot create directory '{}': {}", level.display(), e);
+                return Ok(-30);
+            }
+            if verbose {
+                println!("mkdir: created directory '{}'", level.display());
+            }
         }
     }
     Ok(0)
 }
 
-fn handle_mv(args: &[String]) -> Result<i32> {
-    if args.len() != 2 {
-        return Err(anyhow!("mv: missing file operand or too many arguments"));
+/// What `mv` does when the destination already exists.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Overwrite {
+    Always,
+    Never,
+    Prompt,
+}
+
+fn handle_mv(args: &[String], dry_run: DryRun, verbosity: Verbosity) -> Result<i32> {
+    let mut verbose = false;
+    let mut overwrite = Overwrite::Always;
+    let mut backup_mode = None;
+    let mut suffix = None;
+    let mut operands = Vec::new();
+
+    let mut i = 0;
+    while i < args.len() {
+        let arg = &args[i];
+        match arg.as_str() {
+            "-v" | "--verbose" => verbose = true,
+            // Like coreutils, the last of -n/-i/-f given wins
+            "-n" | "--no-clobber" => overwrite = Overwrite::Never,
+            "-i" | "--interactive" => overwrite = Overwrite::Prompt,
+            "-f" | "--force" => overwrite = Overwrite::Always,
+            "-b" | "--backup" => {
+                backup_mode = default_backup_mode().map_err(|e| anyhow!("mv: {}", e))?;
+            }
+            _ if arg.starts_with("--backup=") => {
+                let control = &arg["--backup=".len()..];
+                backup_mode = parse_backup_control(control).map_err(|e| anyhow!("mv: {}", e))?;
+            }
+            "-S" | "--suffix" => {
+                i += 1;
+                let value = args
+                    .get(i)
+                    .ok_or_else(|| anyhow!("mv: option '--suffix' requires an argument"))?;
+                suffix = Some(value.clone());
+            }
+            _ if arg.starts_with("--suffix=") => {
+                suffix = Some(arg["--suffix=".len()..].to_string());
+            }
+            _ => operands.push(arg),
+        }
+        i += 1;
+    }
+
+    if operands.len() < 2 {
+        return Err(anyhow!("mv: missing file operand"));
+    }
+
+    let suffix = suffix.unwrap_or_else(default_backup_suffix);
+    let backup = backup_mode.map(|mode| Backup { mode, suffix: &suffix });
+    if overwrite == Overwrite::Never && backup.is_some() {
+        return Err(anyhow!("mv: options --backup and --no-clobber are mutually exclusive"));
     }
 
-    let source = Path::new(&args[0]);
-    let destination = Path::new(&args[1]);
+    let (sources, destination) = operands.split_at(operands.len() - 1);
+    let destination = Path::new(destination[0]);
+
+    if sources.len() > 1 && !destination.is_dir() {
+        verbosity.error(format_args!("mv: target '{}' is not a directory", destination.display()));
+        return Ok(-40);
+    }
+
+    let mut encountered_error = false;
+    for source in sources {
+        let source = Path::new(source);
+        let target = match source.file_name() {
+            Some(file_name) if destination.is_dir() => destination.join(file_name),
+            _ => destination.to_path_buf(),
+        };
+
+        if names_missing_directory(&target) && !source.is_dir() {
+            verbosity.error(format_args!(
+                "mv: cannot move '{}' to '{}': Not a directory",
+                source.display(),
+                target.display()
+            ));
+            encountered_error = true;
+            continue;
+        }
+
+        // Renaming a directory below itself would detach the whole subtree
+        let source_is_dir = source.symlink_metadata().is_ok_and(|metadata| metadata.is_dir());
+        if source_is_dir && is_same_or_descendant(source, &target) {
+            verbosity.error(format_args!(
+                "mv: cannot move '{}' to a subdirectory of itself, '{}'",
+                source.display(),
+                target.display()
+            ));
+            encountered_error = true;
+            continue;
+        }
+
+        if target.symlink_metadata().is_ok() {
+            match overwrite {
+                Overwrite::Always => {}
+                Overwrite::Never => continue,
+                Overwrite::Prompt => {
+                    if !dry_run.0 && !confirm(&format!("mv: overwrite '{}'? ", target.display()))? {
+                        continue;
+                    }
+                }
+            }
+        }
+
+        // Each destination is backed up just before it is replaced
+        if let Some(backup) = backup {
+            if let Err(e) = make_backup(&target, backup, dry_run) {
+                verbosity.error(format_args!("mv: cannot back up '{}': {}", target.display(), e));
+                encountered_error = true;
+                continue;
+            }
+        }
+
+        if dry_run.0 {
+            println!("would rename '{}' -> '{}'", source.display(), target.display());
+            continue;
+        }
+
+        if let Err(e) = fs::rename(source, &target) {
+            verbosity.error(format_args!(
+                "mv: cannot move '{}' to '{}': {}",
+                source.display(),
+                target.display(),
+                e
+            ));
+            encountered_error = true;
+            continue;
+        }
+        if verbose {
+            println!("renamed '{}' -> '{}'", source.display(), target.display());
+        }
+    }
 
-    if let Err(e) = fs::rename(source, destination) {
-        eprintln!("mv: cannot move '{}' to '{}': {}", source.display(), destination.display(), e);
+    if encountered_error {
         return Ok(-40);
     }
     Ok(0)
 }
 
+// A destination written with a trailing slash has to be a directory, so it
+// must not silently turn into a file of that name
+fn names_missing_directory(destination: &Path) -> bool {
+    destination.as_os_str().as_encoded_bytes().ends_with(b"/") && !destination.is_dir()
+}
+
+// Whether `target` is `source` itself or lies somewhere below it. Paths are
+// compared canonicalized, so `.`, `..` and symlinked parents can't hide the
+// overlap; only the target's parent is resolved, since the target itself
+// usually doesn't exist yet and a symlink there would be replaced, not
+// followed.
+fn is_same_or_descendant(source: &Path, target: &Path) -> bool {
+    let Ok(source) = fs::canonicalize(source) else {
+        return false;
+    };
+    let target = match (target.parent(), target.file_name()) {
+        (Some(parent), Some(name)) => {
+            let parent = if parent.as_os_str().is_empty() { Path::new(".") } else { parent };
+            fs::canonicalize(parent).map(|parent| parent.join(name))
+        }
+        _ => fs::canonicalize(target),
+    };
+    target.is_ok_and(|target| target.starts_with(&source))
+}
+
+// Asks a yes/no question on stderr and reads the answer from stdin
+fn confirm(prompt: &str) -> io::Result<bool> {
+    eprint!("{}", prompt);
+    io::stderr().flush()?;
+    let mut answer = String::new();
+    io::stdin().lock().read_line(&mut answer)?;
+    Ok(matches!(answer.trim_start().chars().next(), Some('y' | 'Y')))
+}
+
 fn handle_ln(args: &[String]) -> Result<i32> {
     let mut symbolic = false;
+    let mut force = false;
+    let mut relative = false;
     let mut path_args = Vec::new();
 
     let mut i = 0;
@@ -122,6 +715,12 @@ fn handle_ln(args: &[String]) -> Result<i32> {
             "-s" | "--symbolic" => {
                 symbolic = true;
             }
+            "-f" | "--force" => {
+                force = true;
+            }
+            "-r" | "--relative" => {
+                relative = true;
+            }
             _ => {
                 path_args.push(&args[i]);
             }
@@ -129,43 +728,219 @@ fn handle_ln(args: &[String]) -> Result<i32> {
         i += 1;
     }
 
-    if path_args.len() != 2 {
-        return Err(anyhow!("ln: missing file operand or too many arguments"));
+    if path_args.len() < 2 {
+        return Err(anyhow!("ln: missing file operand"));
+    }
+
+    if relative && !symbolic {
+        return Err(anyhow!("ln: cannot do --relative without --symbolic"));
+    }
+
+    // Like cp and mv, a final directory receives one link per target,
+    // named after the target
+    let (targets, destination) = path_args.split_at(path_args.len() - 1);
+    let destination = Path::new(destination[0]);
+    let into_directory = destination.is_dir();
+    if targets.len() > 1 && !into_directory {
+        eprintln!("ln: target '{}' is not a directory", destination.display());
+        return Ok(-50);
+    }
+
+    let mut encountered_error = false;
+    for source in targets {
+        let source = Path::new(source);
+        let link_name = match source.file_name() {
+            Some(file_name) if into_directory => destination.join(file_name),
+            _ => destination.to_path_buf(),
+        };
+        if !ln_one(source, &link_name, symbolic, force, relative)? {
+            encountered_error = true;
+        }
+    }
+
+    if encountered_error {
+        Ok(-50)
+    } else {
+        Ok(0)
     }
+}
+
+// Creates a single link, reporting failures; returns false if it failed
+fn ln_one(
+    source: &Path,
+    link_name: &Path,
+    symbolic: bool,
+    force: bool,
+    relative: bool,
+) -> Result<bool> {
+    // With -r the link stores the target relative to the link's own directory
+    let relative_source;
+    let source = if relative {
+        let link_dir = absolute_path(link_name.parent().unwrap_or(Path::new("")))?;
+        relative_source = relative_path(&link_dir, &absolute_path(source)?);
+        relative_source.as_path()
+    } else {
+        source
+    };
 
-    let source = Path::new(path_args[0]);
-    let link_name = Path::new(path_args[1]);
+    if force {
+        if let Err(e) = remove_link_destination(source, link_name, symbolic) {
+            eprintln!("ln: cannot replace '{}': {}", link_name.display(), e);
+            return Ok(false);
+        }
+    }
 
-    if symbolic { // Given the problem description, we only care about symbolic links.
+    if symbolic {
         if let Err(e) = symlink(source, link_name) { // Call symlink directly
             eprintln!("ln: failed to create symbolic link '{}' to '{}': {}", link_name.display(), source.display(), e);
-            return Ok(-50);
+            return Ok(false);
         }
-    } else {
-        eprintln!("ln: only symbolic links are supported. Use -s or --symbolic.");
-        return Ok(-50);
+    } else if let Err(e) = fs::hard_link(source, link_name) {
+        eprintln!("ln: failed to create hard link '{}' => '{}': {}", link_name.display(), source.display(), e);
+        return Ok(false);
     }
 
-    Ok(0)
+    Ok(true)
 }
 
-fn handle_rmdir(args: &[String]) -> Result<i32> {
-    if args.is_empty() {
+// Makes a path absolute without requiring it to exist: the parent directory
+// is canonicalized when possible, the last component is kept as-is (so a
+// symlink target is not resolved), and `.`/`..` are folded lexically.
+fn absolute_path(path: &Path) -> Result<PathBuf> {
+    let joined = std::env::current_dir()?.join(path);
+    let (parent, name) = match (joined.parent(), joined.file_name()) {
+        (Some(parent), Some(name)) => (parent, Some(name)),
+        _ => (joined.as_path(), None),
+    };
+    let base = fs::canonicalize(parent).unwrap_or_else(|_| parent.to_path_buf());
+    let full = match name {
+        Some(name) => base.join(name),
+        None => base,
+    };
+
+    let mut normalized = PathBuf::new();
+    for component in full.components() {
+        match component {
+            std::path::Component::CurDir => {}
+            std::path::Component::ParentDir => {
+                normalized.pop();
+            }
+            other => normalized.push(other),
+        }
+    }
+    Ok(normalized)
+}
+
+// Path from directory `from` to `to`: one `..` per component of `from` past
+// their common ancestor, followed by the rest of `to`
+fn relative_path(from: &Path, to: &Path) -> PathBuf {
+    let from: Vec<_> = from.components().collect();
+    let to: Vec<_> = to.components().collect();
+    let common = from.iter().zip(&to).take_while(|(a, b)| a == b).count();
+
+    let mut relative = PathBuf::new();
+    for _ in common..from.len() {
+        relative.push("..");
+    }
+    for component in &to[common..] {
+        relative.push(component);
+    }
+    if relative.as_os_str().is_empty() {
+        relative.push(".");
+    }
+    relative
+}
+
+// Clears the way for `ln -f`. The existing entry is inspected without
+// following it, so a symlink (even one pointing at a directory) is removed
+// itself, while real directories are never removed.
+fn remove_link_destination(source: &Path, link_name: &Path, symbolic: bool) -> Result<()> {
+    let existing = match fs::symlink_metadata(link_name) {
+        Ok(metadata) => metadata,
+        Err(_) => return Ok(()),
+    };
+
+    if existing.is_dir() {
+        return Err(anyhow!("cannot overwrite directory"));
+    }
+    if !symbolic {
+        // Removing the destination first would delete the only copy
+        if let Ok(source_metadata) = fs::metadata(source) {
+            if source_metadata.dev() == existing.dev() && source_metadata.ino() == existing.ino() {
+                return Err(anyhow!("'{}' and '{}' are the same file", source.display(), link_name.display()));
+            }
+        }
+    }
+    fs::remove_file(link_name)?;
+    Ok(())
+}
+
+fn handle_rmdir(args: &[String], dry_run: DryRun, verbosity: Verbosity) -> Result<i32> {
+    let mut parents = false;
+    let mut verbose = false;
+    let mut ignore_non_empty = false;
+    let mut dirs = Vec::new();
+    for arg in args {
+        match arg.as_str() {
+            "-p" | "--parents" => parents = true,
+            "-v" | "--verbose" => verbose = true,
+            "--ignore-fail-on-non-empty" => ignore_non_empty = true,
+            _ => dirs.push(arg),
+        }
+    }
+
+    if dirs.is_empty() {
         return Err(anyhow!("rmdir: missing operand"));
     }
 
-    for dir_path in args {
-        if let Err(e) = fs::remove_dir(dir_path) {
-            eprintln!("rmdir: failed to remove directory '{}': {}", dir_path, e);
-            return Ok(-60);
+    let mut encountered_error = false;
+    for dir_path in dirs {
+        // With -p the parents named in the operand are removed too, innermost first
+        let path = Path::new(dir_path.trim_end_matches('/'));
+        let levels: Vec<&Path> = if parents {
+            path.ancestors()
+                .filter(|level| !level.as_os_str().is_empty() && *level != Path::new("/"))
+                .collect()
+        } else {
+            vec![Path::new(dir_path)]
+        };
+
+        for level in levels {
+            if dry_run.0 {
+                println!("would remove directory '{}'", level.display());
+                continue;
+            }
+            if verbose {
+                println!("rmdir: removing directory, '{}'", level.display());
+            }
+            if let Err(e) = fs::remove_dir(level) {
+                let non_empty = e.kind() == io::ErrorKind::DirectoryNotEmpty;
+                if !(non_empty && ignore_non_empty) {
+                    verbosity.error(format_args!(
+                        "rmdir: failed to remove directory '{}': {}",
+                        level.display(),
+                        e
+                    ));
+                    encountered_error = true;
+                }
+                // Stop climbing this operand but carry on with the next one
+                break;
+            }
         }
     }
+
+    if encountered_error {
+        return Ok(-60);
+    }
     Ok(0)
 }
 
-fn handle_rm(args: &[String]) -> Result<i32> {
+fn handle_rm(args: &[String], dry_run: DryRun, verbosity: Verbosity) -> Result<i32> {
     let mut recursive = false;
     let mut dir_only = false;
+    let mut one_file_system = false;
+    let mut stats = RmStats::default();
+    let mut show_stats = false;
     let mut files_to_remove = Vec::new();
 
     let mut i = 0;
@@ -177,6 +952,15 @@ fn handle_rm(args: &[String]) -> Result<i32> {
             "-d" | "--dir" => {
                 dir_only = true;
             }
+            "--one-file-system" => {
+                one_file_system = true;
+            }
+            "--stats" => {
+                show_stats = true;
+            }
+            "-v" | "--verbose" => {
+                stats.progress = true;
+            }
             _ => {
                 files_to_remove.push(&args[i]);
             }
@@ -194,30 +978,77 @@ fn handle_rm(args: &[String]) -> Result<i32> {
 
         if path.is_dir() {
             if recursive {
-                if let Err(e) = fs::remove_dir_all(path) {
-                    eprintln!("rm: cannot remove directory '{}': {}", path.display(), e);
-                    encountered_error = true;
+                // Only descend into entries on the same device as the operand
+                let device = match fs::symlink_metadata(path) {
+                    Ok(metadata) if one_file_system => Some(metadata.dev()),
+                    _ => None,
+                };
+                match remove_tree(path, device, dry_run, verbosity, &mut stats) {
+                    Ok(true) => {}
+                    Ok(false) => encountered_error = true,
+                    Err(e) => {
+                        verbosity.error(format_args!(
+                            "rm: cannot remove directory '{}': {}",
+                            path.display(),
+                            e
+                        ));
+                        encountered_error = true;
+                    }
                 }
+            } else if dir_only && dry_run.0 {
+                println!("would remove '{}'", path.display());
+                stats.record(true);
             } else if dir_only {
-                if let Err(e) = fs::remove_dir(path) {
-                    eprintln!("rm: cannot remove empty directory '{}': {}", path.display(), e);
-                    encountered_error = true;
+                match fs::remove_dir(path) {
+                    Ok(()) => stats.record(true),
+                    Err(e) => {
+                        verbosity.error(format_args!(
+                            "rm: cannot remove empty directory '{}': {}",
+                            path.display(),
+                            e
+                        ));
+                        encountered_error = true;
+                    }
                 }
             } else {
-                eprintln!("rm: cannot remove directory '{}': Is a directory. Use -r or -d to remove directories.", path.display());
+                verbosity.error(format_args!(
+                    "rm: cannot remove directory '{}': Is a directory. Use -r or -d to remove directories.",
+                    path.display()
+                ));
                 encountered_error = true;
             }
+        } else if (path.is_file() || path.is_symlink()) && dry_run.0 {
+            println!("would remove '{}'", path.display());
+            stats.record(false);
         } else if path.is_file() || path.is_symlink() {
-            if let Err(e) = fs::remove_file(path) {
-                eprintln!("rm: cannot remove '{}': {}", path.display(), e);
-                encountered_error = true;
+            match fs::remove_file(path) {
+                Ok(()) => stats.record(false),
+                Err(e) => {
+                    verbosity.error(format_args!("rm: cannot remove '{}': {}", path.display(), e));
+                    encountered_error = true;
+                }
             }
         } else {
-            eprintln!("rm: cannot remove '{}': No such file or directory", path.display());
+            verbosity.error(format_args!(
+                "rm: cannot remove '{}': No such file or directory",
+                path.display()
+            ));
             encountered_error = true;
         }
     }
 
+    if show_stats {
+        let counted = |count: u64, one: &str, many: &str| {
+            format!("{} {}", count, if count == 1 { one } else { many })
+        };
+        println!(
+            "rm: {} {} and {}",
+            if dry_run.0 { "would remove" } else { "removed" },
+            counted(stats.files, "file", "files"),
+            counted(stats.directories, "directory", "directories")
+        );
+    }
+
     if encountered_error {
         Ok(-70)
     } else {
@@ -225,20 +1056,300 @@ fn handle_rm(args: &[String]) -> Result<i32> {
     }
 }
 
-fn handle_ls(args: &[String]) -> Result<i32> {
-    let mut show_all = false;
-    let mut recursive = false;
-    let mut paths_to_list = Vec::new();
+/// How often `rm -v` reports how far a large delete has got.
+const RM_PROGRESS_EVERY: u64 = 10_000;
 
-    let mut i = 0;
-    while i < args.len() {
-        match args[i].as_str() {
-            "-a" | "--all" => {
-                show_all = true;
-            }
-            "-R" | "--recursive" => {
-                recursive = true;
-            }
+/// What `rm` has removed so far, for `--stats` and the `-v` progress count.
+#[derive(Debug, Default)]
+struct RmStats {
+    files: u64,
+    directories: u64,
+    progress: bool,
+}
+
+impl RmStats {
+    fn record(&mut self, is_dir: bool) {
+        if is_dir {
+            self.directories += 1;
+        } else {
+            self.files += 1;
+        }
+        let removed = self.files + self.directories;
+        if self.progress && removed.is_multiple_of(RM_PROGRESS_EVERY) {
+            eprintln!("rm: {} entries removed so far", removed);
+        }
+    }
+}
+
+// Removes a tree children-first (or only reports it under --dry-run). When
+// `device` is set, entries on another filesystem are skipped, along with the
+// directories that still contain them. Returns false if anything was kept.
+fn remove_tree(
+    path: &Path,
+    device: Option<u64>,
+    dry_run: DryRun,
+    verbosity: Verbosity,
+    stats: &mut RmStats,
+) -> Result<bool> {
+    let metadata = fs::symlink_metadata(path)?;
+    if device.is_some_and(|device| metadata.dev() != device) {
+        verbosity.error(format_args!(
+            "rm: skipping '{}', since it's on a different device",
+            path.display()
+        ));
+        return Ok(false);
+    }
+
+    let mut removed_all = true;
+    if metadata.is_dir() {
+        for entry in fs::read_dir(path)? {
+            removed_all &= remove_tree(&entry?.path(), device, dry_run, verbosity, stats)?;
+        }
+    }
+    if !removed_all {
+        return Ok(false);
+    }
+
+    if dry_run.0 {
+        println!("would remove '{}'", path.display());
+    } else if metadata.is_dir() {
+        fs::remove_dir(path)?;
+    } else {
+        fs::remove_file(path)?;
+    }
+    stats.record(metadata.is_dir());
+    Ok(true)
+}
+
+/// How `ls -l` renders modification times (`--time-style`).
+#[derive(Debug, Clone, Copy)]
+enum TimeStyle<'a> {
+    /// Month, day and time for recent files, the year for old ones
+    Default,
+    Iso,
+    LongIso,
+    FullIso,
+    Format(&'a str),
+}
+
+/// Which timestamp `ls` shows and sorts by: `-c` picks the status change
+/// time, `-u` the access time, otherwise the modification time.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum LsTime {
+    Modified,
+    Changed,
+    Accessed,
+}
+
+impl LsTime {
+    fn of(self, metadata: &fs::Metadata) -> chrono::DateTime<chrono::Utc> {
+        let (seconds, nanoseconds) = match self {
+            LsTime::Modified => (metadata.mtime(), metadata.mtime_nsec()),
+            LsTime::Changed => (metadata.ctime(), metadata.ctime_nsec()),
+            LsTime::Accessed => (metadata.atime(), metadata.atime_nsec()),
+        };
+        chrono::DateTime::from_timestamp(seconds, nanoseconds as u32).unwrap_or_default()
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct LsOptions<'a> {
+    show_all: bool,
+    /// List `.` and `..` too (`-a`, but not `-A`)
+    dot_entries: bool,
+    long: bool,
+    human: bool,
+    columns: bool,
+    /// Append a `/`, `*`, `@`, `|` or `=` type indicator to names (`-F`)
+    classify: bool,
+    time: LsTime,
+    /// Newest first instead of by name
+    sort_by_time: bool,
+    time_style: TimeStyle<'a>,
+    /// Unit of the size column; `None` shows plain bytes
+    size_unit: Option<BlockSize>,
+    /// Unit of the `total` line
+    total_unit: BlockSize,
+    verbosity: Verbosity,
+}
+
+/// A `--block-size` unit. When it was given as a bare suffix (`K`, `MB`),
+/// the suffix is printed after each number, as coreutils does.
+#[derive(Debug, Clone, Copy)]
+struct BlockSize {
+    bytes: u64,
+    suffix: &'static str,
+}
+
+impl BlockSize {
+    const KIBIBYTE: BlockSize = BlockSize { bytes: 1024, suffix: "" };
+
+    // Counts are rounded up, so a partly used block counts as a whole one
+    fn format(self, bytes: u64) -> String {
+        format!("{}{}", bytes.div_ceil(self.bytes), self.suffix)
+    }
+}
+
+const BLOCK_SIZE_UNITS: [(&str, u64); 20] = [
+    ("k", 1 << 10),
+    ("K", 1 << 10),
+    ("M", 1 << 20),
+    ("G", 1 << 30),
+    ("T", 1 << 40),
+    ("P", 1 << 50),
+    ("E", 1 << 60),
+    ("KiB", 1 << 10),
+    ("MiB", 1 << 20),
+    ("GiB", 1 << 30),
+    ("TiB", 1 << 40),
+    ("PiB", 1 << 50),
+    ("EiB", 1 << 60),
+    ("kB", 1_000),
+    ("KB", 1_000),
+    ("MB", 1_000_000),
+    ("GB", 1_000_000_000),
+    ("TB", 1_000_000_000_000),
+    ("PB", 1_000_000_000_000_000),
+    ("EB", 1_000_000_000_000_000_000),
+];
+
+fn parse_block_size(spec: &str) -> Result<BlockSize> {
+    let digits = spec.find(|c: char| !c.is_ascii_digit()).unwrap_or(spec.len());
+    let (number, suffix) = spec.split_at(digits);
+    let (shown, multiplier) = if suffix.is_empty() {
+        ("", 1)
+    } else {
+        BLOCK_SIZE_UNITS
+            .iter()
+            .copied()
+            .find(|(unit, _)| *unit == suffix)
+            .ok_or_else(|| anyhow!("ls: invalid suffix in --block-size argument '{}'", spec))?
+    };
+    let number = if number.is_empty() { Some(1) } else { number.parse::<u64>().ok() };
+    let bytes = number
+        .and_then(|number| number.checked_mul(multiplier))
+        .filter(|bytes| *bytes > 0)
+        .ok_or_else(|| anyhow!("ls: invalid --block-size argument '{}'", spec))?;
+    // Only a bare unit is repeated after the numbers; coreutils always
+    // spells the decimal kilo in lower case
+    let suffix = match shown {
+        _ if digits > 0 => "",
+        "KB" => "kB",
+        _ => shown,
+    };
+    Ok(BlockSize { bytes, suffix })
+}
+
+// `terminal` says whether `out` is a terminal, which decides the default layout
+fn handle_ls(
+    args: &[String],
+    verbosity: Verbosity,
+    out: &mut dyn Write,
+    terminal: bool,
+) -> Result<i32> {
+    // Like coreutils, only pack names into columns when writing to a terminal
+    let mut options = LsOptions {
+        show_all: false,
+        dot_entries: false,
+        long: false,
+        human: false,
+        columns: terminal,
+        classify: false,
+        time: LsTime::Modified,
+        sort_by_time: false,
+        time_style: TimeStyle::Default,
+        size_unit: None,
+        total_unit: BlockSize::KIBIBYTE,
+        verbosity,
+    };
+    // Like coreutils, the environment sets the default unit; unparsable
+    // values are ignored
+    let from_env = ["LS_BLOCK_SIZE", "BLOCK_SIZE"]
+        .iter()
+        .find_map(|name| std::env::var(name).ok())
+        .and_then(|spec| parse_block_size(&spec).ok());
+    let mut block_size: Option<BlockSize> = None;
+    let mut kibibytes = false;
+    let mut recursive = false;
+    let mut follow = false;
+    let mut json = false;
+    let mut paths_to_list = Vec::new();
+
+    let mut i = 0;
+    while i < args.len() {
+        match args[i].as_str() {
+            "-a" | "--all" => {
+                options.show_all = true;
+                options.dot_entries = true;
+            }
+            "-A" | "--almost-all" => {
+                options.show_all = true;
+                options.dot_entries = false;
+            }
+            "-R" | "--recursive" => {
+                recursive = true;
+            }
+            // Only matter with -R: whether it descends into symlinked directories
+            "-L" | "--dereference" => {
+                follow = true;
+            }
+            "-P" => {
+                follow = false;
+            }
+            "-l" => {
+                options.long = true;
+            }
+            "-t" => {
+                options.sort_by_time = true;
+            }
+            "-F" | "--classify" => {
+                options.classify = true;
+            }
+            "--json" => {
+                json = true;
+            }
+            "-c" => {
+                options.time = LsTime::Changed;
+            }
+            "-u" => {
+                options.time = LsTime::Accessed;
+            }
+            // -h and --block-size override each other, the last one wins
+            "-h" | "--human-readable" => {
+                options.human = true;
+                block_size = None;
+            }
+            "-k" | "--kibibytes" => {
+                kibibytes = true;
+            }
+            "--block-size" => {
+                i += 1;
+                let spec = args
+                    .get(i)
+                    .ok_or_else(|| anyhow!("ls: option '--block-size' requires an argument"))?;
+                block_size = Some(parse_block_size(spec)?);
+                options.human = false;
+            }
+            arg if arg.starts_with("--block-size=") => {
+                block_size = Some(parse_block_size(&arg["--block-size=".len()..])?);
+                options.human = false;
+            }
+            "-1" => {
+                options.columns = false;
+            }
+            "-C" => {
+                options.columns = true;
+            }
+            "--time-style" => {
+                i += 1;
+                let style = args
+                    .get(i)
+                    .ok_or_else(|| anyhow!("ls: option '--time-style' requires an argument"))?;
+                options.time_style = parse_time_style(style)?;
+            }
+            arg if arg.starts_with("--time-style=") => {
+                options.time_style = parse_time_style(&arg["--time-style=".len()..])?;
+            }
             _ => {
                 paths_to_list.push(PathBuf::from(&args[i]));
             }
@@ -250,22 +1361,95 @@ fn handle_ls(args: &[String]) -> Result<i32> {
         paths_to_list.push(PathBuf::from("."));
     }
 
+    // Like coreutils, -c and -u sort by their time unless the times are
+    // only being shown by -l
+    if !options.long && options.time != LsTime::Modified {
+        options.sort_by_time = true;
+    }
+
+    // -k only changes the unit of totals, and only when --block-size was
+    // not given
+    options.size_unit = block_size.or(from_env);
+    options.total_unit = match (block_size, from_env) {
+        (Some(unit), _) => unit,
+        (None, _) if kibibytes => BlockSize::KIBIBYTE,
+        (None, unit) => unit.unwrap_or(BlockSize::KIBIBYTE),
+    };
+
+    // Like coreutils, missing operands are reported first, file operands
+    // are listed together next, then each directory gets its own section
+    let with_headers = recursive || paths_to_list.len() > 1;
     let mut encountered_error = false;
-    for path_to_list in paths_to_list {
-        if path_to_list.is_file() {
-            println!("{}", path_to_list.display());
-            continue;
+    let (existing, missing): (Vec<PathBuf>, Vec<PathBuf>) =
+        paths_to_list.into_iter().partition(|path| path.symlink_metadata().is_ok());
+    for path in missing {
+        eprintln!("ls: cannot access '{}': No such file or directory", path.display());
+        encountered_error = true;
+    }
+    let (files, directories): (Vec<PathBuf>, Vec<PathBuf>) =
+        existing.into_iter().partition(|path| !path.is_dir());
+
+    if json {
+        let mut entries: Vec<(String, PathBuf)> =
+            files.into_iter().map(|path| (path.display().to_string(), path)).collect();
+        ls_sort(&mut entries, options);
+        let mut records = Vec::new();
+        let mut guard = WalkGuard::new(follow);
+        let errors = &mut encountered_error;
+        ls_json_records(&entries, options, false, &mut guard, &mut records, errors)?;
+        for path in directories {
+            let entries = match ls_read_dir(&path, options) {
+                Ok(entries) => entries,
+                Err(e) => {
+                    options.verbosity.error(format_args!(
+                        "ls: cannot open directory '{}': {}",
+                        path.display(),
+                        e
+                    ));
+                    encountered_error = true;
+                    continue;
+                }
+            };
+            // A fresh guard has nothing to loop back to yet
+            let mut guard = WalkGuard::new(follow);
+            let _ = guard.enter(&path);
+            let errors = &mut encountered_error;
+            ls_json_records(&entries, options, recursive, &mut guard, &mut records, errors)?;
+        }
+        print_json(&records, out)?;
+        return Ok(if encountered_error { -80 } else { 0 });
+    }
+
+    let mut first_section = true;
+    if !files.is_empty() {
+        let mut entries: Vec<(String, PathBuf)> =
+            files.into_iter().map(|path| (path.display().to_string(), path)).collect();
+        ls_sort(&mut entries, options);
+        if let Err(e) = ls_print_entries(&entries, options, out) {
+            eprintln!("ls: {}", e);
+            encountered_error = true;
         }
+        first_section = false;
+    }
 
+    for path_to_list in directories {
         if recursive {
-            if let Err(_) = ls_recursive(&path_to_list, show_all, &path_to_list) {
+            let mut guard = WalkGuard::new(follow);
+            if !ls_recursive(&path_to_list, options, &mut guard, &mut first_section, out) {
                 encountered_error = true;
             }
-        } else {
-            if let Err(e) = ls_single_directory(&path_to_list, show_all) {
-                eprintln!("ls: cannot access '{}': {}", path_to_list.display(), e);
-                encountered_error = true;
+            continue;
+        }
+        if with_headers {
+            if !first_section {
+                writeln!(out)?;
             }
+            writeln!(out, "{}:", path_to_list.display())?;
+        }
+        first_section = false;
+        if let Err(e) = ls_single_directory(&path_to_list, options, out) {
+            eprintln!("ls: cannot access '{}': {}", path_to_list.display(), e);
+            encountered_error = true;
         }
     }
 
@@ -276,269 +1460,5218 @@ fn handle_ls(args: &[String]) -> Result<i32> {
     }
 }
 
-fn ls_single_directory(path: &Path, show_all: bool) -> Result<()> {
-    for entry in fs::read_dir(path)? {
-        let entry = entry?;
-        let file_name = entry.file_name();
-        let file_name_str = file_name.to_string_lossy();
-        if show_all || !file_name_str.starts_with('.') {
-            println!("{}", file_name_str);
-        }
+fn ls_single_directory(path: &Path, options: LsOptions<'_>, out: &mut dyn Write) -> Result<()> {
+    let entries = ls_read_dir(path, options)?;
+    if options.long {
+        ls_print_total(&entries, options, out)?;
     }
-    Ok(())
+    ls_print_entries(&entries, options, out)
 }
 
-fn ls_recursive(path: &Path, show_all: bool, base_path: &Path) -> Result<()> {
-    if path.is_file() {
-        println!("{}", path.strip_prefix(base_path).unwrap_or(path).display());
-        return Ok(());
+// The disk usage of the listed entries, from their allocated blocks
+fn ls_print_total(
+    entries: &[(String, PathBuf)],
+    options: LsOptions<'_>,
+    out: &mut dyn Write,
+) -> Result<()> {
+    let mut bytes = 0;
+    for (_, path) in entries {
+        // st_blocks is always counted in 512-byte units
+        bytes += fs::symlink_metadata(path)?.blocks() * 512;
+    }
+    if options.human {
+        writeln!(out, "total {}", format_size(bytes))?;
+    } else {
+        writeln!(out, "total {}", options.total_unit.format(bytes))?;
     }
+    Ok(())
+}
 
-    println!("{}:", path.display());
+// The visible entries of a directory as (name, path) pairs, sorted by name.
+// read_dir never yields `.` and `..`, so -a adds them itself.
+fn ls_read_dir(path: &Path, options: LsOptions<'_>) -> io::Result<Vec<(String, PathBuf)>> {
+    let mut entries = Vec::new();
+    if options.dot_entries {
+        for name in [".", ".."] {
+            entries.push((name.to_string(), path.join(name)));
+        }
+    }
     for entry in fs::read_dir(path)? {
         let entry = entry?;
         let file_name = entry.file_name();
         let file_name_str = file_name.to_string_lossy();
-        if show_all || !file_name_str.starts_with('.') {
-            let full_path = path.join(&file_name);
-            if full_path.is_dir() {
-                if file_name_str != "." && file_name_str != ".." {
-                    ls_recursive(&full_path, show_all, base_path)?;
-                }
-            } else {
-                println!("{}", full_path.strip_prefix(base_path).unwrap_or(&full_path).display());
-            }
+        if options.show_all || !file_name_str.starts_with('.') {
+            entries.push((file_name_str.into_owned(), entry.path()));
         }
     }
-    Ok(())
+    ls_sort(&mut entries, options);
+    Ok(entries)
 }
 
-fn handle_cp(args: &[String]) -> Result<i32> {
-    let mut recursive = false;
-    let mut operands = Vec::new(); // Will hold source(s) and destination
+// `.` and `..` are listed with -a but never descended into
+fn is_dot_entry(name: &str) -> bool {
+    name == "." || name == ".."
+}
 
-    let mut i = 0;
-    while i < args.len() {
-        match args[i].as_str() {
-            "-R" | "-r" | "--recursive" => {
-                recursive = true;
+// By name, or newest first with ties by name when sorting by time
+fn ls_sort(entries: &mut [(String, PathBuf)], options: LsOptions<'_>) {
+    entries.sort();
+    if options.sort_by_time {
+        entries.sort_by_cached_key(|(_, path)| {
+            let time = path.symlink_metadata().map(|metadata| options.time.of(&metadata));
+            std::cmp::Reverse(time.unwrap_or_default())
+        });
+    }
+}
+
+// Adds a record for each entry, and with -R for everything below the
+// subdirectories among them. A directory that cannot be read, or that -L
+// leads back to, is reported and skipped, like in a text listing.
+fn ls_json_records(
+    entries: &[(String, PathBuf)],
+    options: LsOptions<'_>,
+    recursive: bool,
+    guard: &mut WalkGuard,
+    records: &mut Vec<FileRecord>,
+    encountered_error: &mut bool,
+) -> Result<()> {
+    for (name, path) in entries {
+        let metadata = fs::symlink_metadata(path)?;
+        records.push(FileRecord::new(name, path, &metadata));
+        if recursive && !is_dot_entry(name) && guard.metadata(path)?.is_dir() {
+            if guard.enter(path).is_err() {
+                options.verbosity.error(format_args!(
+                    "ls: {}: not listing already-listed directory",
+                    path.display()
+                ));
+                *encountered_error = true;
+                continue;
             }
-            _ => {
-                operands.push(&args[i]);
+            match ls_read_dir(path, options) {
+                Ok(children) => {
+                    ls_json_records(&children, options, true, guard, records, encountered_error)?
+                }
+                Err(e) => {
+                    options.verbosity.error(format_args!(
+                        "ls: cannot open directory '{}': {}",
+                        path.display(),
+                        e
+                    ));
+                    *encountered_error = true;
+                }
             }
+            guard.leave();
         }
-        i += 1;
     }
+    Ok(())
+}
 
-    if operands.len() < 2 {
-        return Err(anyhow!("cp: missing file operand"));
+// Lists a directory under a `path:` header, then each subdirectory in name
+// order, with a blank line between sections as coreutils prints them.
+// Errors are reported as they happen; returns false if there were any.
+fn ls_recursive(
+    path: &Path,
+    options: LsOptions<'_>,
+    guard: &mut WalkGuard,
+    first_section: &mut bool,
+    out: &mut dyn Write,
+) -> bool {
+    if guard.enter(path).is_err() {
+        eprintln!("ls: {}: not listing already-listed directory", path.display());
+        return false;
     }
+    let succeeded = ls_recursive_section(path, options, guard, first_section, out);
+    guard.leave();
+    succeeded
+}
 
-    let source_path_str = operands[0];
-    let destination_path_str = operands[1];
-
-    let source = PathBuf::from(source_path_str);
-    let mut destination = PathBuf::from(destination_path_str);
+fn ls_recursive_section(
+    path: &Path,
+    options: LsOptions<'_>,
+    guard: &mut WalkGuard,
+    first_section: &mut bool,
+    out: &mut dyn Write,
+) -> bool {
+    let separator = if *first_section { "" } else { "\n" };
+    *first_section = false;
+    if let Err(e) = writeln!(out, "{}{}:", separator, path.display()) {
+        eprintln!("ls: {}", e);
+        return false;
+    }
 
-    // If destination is an existing directory, append source name to it
-    if destination.is_dir() {
-        if let Some(file_name) = source.file_name() {
-            destination.push(file_name);
+    let entries = match ls_read_dir(path, options) {
+        Ok(entries) => entries,
+        Err(e) => {
+            eprintln!("ls: cannot open directory '{}': {}", path.display(), e);
+            return false;
         }
+    };
+    let mut succeeded = true;
+    let listed = if options.long { ls_print_total(&entries, options, out) } else { Ok(()) }
+        .and_then(|_| ls_print_entries(&entries, options, out));
+    if let Err(e) = listed {
+        eprintln!("ls: {}: {}", path.display(), e);
+        succeeded = false;
     }
 
-    if source.is_dir() {
-        if !recursive {
-            eprintln!("cp: -r not specified; omitting directory '{}'", source.display());
-            return Ok(-90);
-        }
-        if let Err(e) = copy_dir_recursive(&source, &destination) {
-            eprintln!("cp: cannot copy directory '{}' to '{}': {}", source.display(), destination.display(), e);
-            return Ok(-90);
+    for (name, entry_path) in &entries {
+        if is_dot_entry(name) {
+            continue;
         }
-    } else if source.is_file() {
-        if let Err(e) = fs::copy(&source, &destination) {
-            eprintln!("cp: cannot copy '{}' to '{}': {}", source.display(), destination.display(), e);
-            return Ok(-90);
+        // Symlinks to directories are listed, and only followed with -L
+        let is_dir = guard.metadata(entry_path).is_ok_and(|metadata| metadata.is_dir());
+        if is_dir && !ls_recursive(entry_path, options, guard, first_section, out) {
+            succeeded = false;
         }
-    } else {
-        eprintln!("cp: cannot stat '{}': No such file or directory", source.display());
-        return Ok(-90);
     }
-
-    Ok(0)
+    succeeded
 }
 
-fn copy_dir_recursive(source: &Path, destination: &Path) -> Result<()> {
-    fs::create_dir_all(destination)?;
-    for entry in fs::read_dir(source)? {
-        let entry = entry?;
-        let path = entry.path();
-        let dest_path = destination.join(entry.file_name());
-
-        if path.is_dir() {
-            copy_dir_recursive(&path, &dest_path)?;
+// Prints (displayed name, path) pairs, either one name per line or as
+// aligned long-listing rows
+fn ls_print_entries(
+    entries: &[(String, PathBuf)],
+    options: LsOptions<'_>,
+    out: &mut dyn Write,
+) -> Result<()> {
+    if !options.long {
+        let classified: Vec<String> = entries
+            .iter()
+            .map(|(name, path)| match fs::symlink_metadata(path) {
+                Ok(metadata) if options.classify => format!("{}{}", name, classify(&metadata)),
+                _ => name.clone(),
+            })
+            .collect();
+        let names: Vec<&str> = classified.iter().map(String::as_str).collect();
+        if options.columns {
+            print_columns(&names, terminal_width(), out)?;
         } else {
-            fs::copy(&path, &dest_path)?;
+            for name in names {
+                writeln!(out, "{}", name)?;
+            }
         }
+        return Ok(());
+    }
+
+    let users = read_id_names("/etc/passwd");
+    let groups = read_id_names("/etc/group");
+    let now = chrono::Utc::now();
+
+    let mut rows = Vec::new();
+    for (name, path) in entries {
+        let metadata = fs::symlink_metadata(path)?;
+        let size = match options.size_unit {
+            _ if options.human => format_size(metadata.size()),
+            Some(unit) => unit.format(metadata.size()),
+            None => metadata.size().to_string(),
+        };
+        let time = options.time.of(&metadata);
+        rows.push([
+            mode_string(&metadata),
+            metadata.nlink().to_string(),
+            users.get(&metadata.uid()).cloned().unwrap_or_else(|| metadata.uid().to_string()),
+            groups.get(&metadata.gid()).cloned().unwrap_or_else(|| metadata.gid().to_string()),
+            size,
+            format_mtime(&time, options.time_style, &now)?,
+            long_name(name, path, &metadata, options.classify),
+        ]);
+    }
+
+    let width = |column: usize| rows.iter().map(|row| row[column].len()).max().unwrap_or(0);
+    let (links_w, user_w, group_w, size_w) = (width(1), width(2), width(3), width(4));
+    for row in &rows {
+        writeln!(
+            out,
+            "{} {:>links_w$} {:<user_w$} {:<group_w$} {:>size_w$} {} {}",
+            row[0], row[1], row[2], row[3], row[4], row[5], row[6]
+        )?;
     }
     Ok(())
 }
 
-fn handle_touch(args: &[String]) -> Result<i32> {
-    let mut access_only = false;
-    let mut no_create = false;
-    let mut modify_only = false;
-    let mut files_to_touch = Vec::new();
-
-    let mut i = 0;
-    while i < args.len() {
-        match args[i].as_str() {
-            "-a" => access_only = true,
-            "-c" | "--no-create" => no_create = true,
-            "-m" => modify_only = true,
-            _ => files_to_touch.push(&args[i]),
+// Symlinks show where they point; the stored target is printed as-is, so
+// dangling links are listed too. With -F a link is classified by what it
+// points to, as coreutils does in long listings.
+fn long_name(name: &str, path: &Path, metadata: &fs::Metadata, classify_name: bool) -> String {
+    if metadata.file_type().is_symlink() {
+        if let Ok(target) = fs::read_link(path) {
+            let suffix = match fs::metadata(path) {
+                Ok(target_metadata) if classify_name => classify(&target_metadata),
+                _ => "",
+            };
+            return format!("{} -> {}{}", name, target.display(), suffix);
         }
-        i += 1;
     }
+    if classify_name {
+        return format!("{}{}", name, classify(metadata));
+    }
+    name.to_string()
+}
 
-    if files_to_touch.is_empty() {
-        return Err(anyhow!("touch: missing file operand"));
+// The `ls -F` indicator for a file type; regular files only get one when
+// someone may execute them
+fn classify(metadata: &fs::Metadata) -> &'static str {
+    let file_type = metadata.file_type();
+    if file_type.is_dir() {
+        "/"
+    } else if file_type.is_symlink() {
+        "@"
+    } else if file_type.is_fifo() {
+        "|"
+    } else if file_type.is_socket() {
+        "="
+    } else if file_type.is_file() && metadata.mode() & 0o111 != 0 {
+        "*"
+    } else {
+        ""
     }
+}
 
-    let now = FileTime::now();
-    let mut encountered_error = false;
+fn parse_time_style(style: &str) -> Result<TimeStyle<'_>> {
+    match style {
+        "locale" => Ok(TimeStyle::Default),
+        "iso" => Ok(TimeStyle::Iso),
+        "long-iso" => Ok(TimeStyle::LongIso),
+        "full-iso" => Ok(TimeStyle::FullIso),
+        _ if style.starts_with('+') => Ok(TimeStyle::Format(&style[1..])),
+        _ => Err(anyhow!("ls: invalid argument '{}' for '--time-style'", style)),
+    }
+}
 
-    for file_path_str in files_to_touch {
-        let path = Path::new(file_path_str);
+// Files modified in the last six months (and not in the future) count as
+// recent and show the time of day instead of the year, like coreutils
+fn format_mtime(
+    mtime: &chrono::DateTime<chrono::Utc>,
+    style: TimeStyle<'_>,
+    now: &chrono::DateTime<chrono::Utc>,
+) -> Result<String> {
+    let six_months = chrono::Duration::seconds(31_556_952 / 2);
+    let recent = *mtime <= *now && *now - *mtime < six_months;
+    let format = match style {
+        TimeStyle::Default if recent => "%b %e %H:%M",
+        TimeStyle::Default => "%b %e  %Y",
+        TimeStyle::Iso if recent => "%m-%d %H:%M",
+        TimeStyle::Iso => "%Y-%m-%d ",
+        TimeStyle::LongIso => "%Y-%m-%d %H:%M",
+        TimeStyle::FullIso => "%Y-%m-%d %H:%M:%S%.9f %z",
+        TimeStyle::Format(format) => format,
+    };
+    format_time(mtime, format, false).map_err(|e| anyhow!("ls: {}", e))
+}
 
-        let metadata_res = fs::metadata(path);
+fn terminal_width() -> usize {
+    if let Some((terminal_size::Width(width), _)) = terminal_size::terminal_size() {
+        return width as usize;
+    }
+    std::env::var("COLUMNS")
+        .ok()
+        .and_then(|columns| columns.parse().ok())
+        .filter(|columns| *columns > 0)
+        .unwrap_or(80)
+}
 
-        if metadata_res.is_err() {
-            // File does not exist
-            if no_create {
-                continue; // Do not create if -c is specified
-            }
-            // Create the file
-            if let Err(e) = fs::File::create(path) {
-                eprintln!("touch: cannot touch '{}': {}", path.display(), e);
-                encountered_error = true;
-                continue;
-            }
-            // If created, times are already current, no need to set explicitly unless specified
-        } else {
-            // File exists
-            let metadata = metadata_res?;
-            let atime = FileTime::from_last_access_time(&metadata);
-            let mtime = FileTime::from_last_modification_time(&metadata);
+// Lays names out top-to-bottom, then left-to-right, using as many columns
+// as fit in `width`. Each column is as wide as its longest name plus two
+// spaces of separation, like coreutils ls.
+fn print_columns(names: &[&str], width: usize, out: &mut dyn Write) -> io::Result<()> {
+    if names.is_empty() {
+        return Ok(());
+    }
 
-            let new_atime = if modify_only { atime } else { now };
-            let new_mtime = if access_only { mtime } else { now };
+    let lengths: Vec<usize> = names.iter().map(|name| name.chars().count()).collect();
+    let mut layout = (names.len(), vec![lengths.iter().copied().max().unwrap_or(0)]);
+    for columns in (2..=names.len()).rev() {
+        let rows = names.len().div_ceil(columns);
+        // Skip column counts that would leave the last column empty
+        if (columns - 1) * rows >= names.len() {
+            continue;
+        }
+        let widths: Vec<usize> = lengths
+            .chunks(rows)
+            .map(|column| column.iter().copied().max().unwrap_or(0))
+            .collect();
+        let total: usize = widths.iter().sum::<usize>() + 2 * (widths.len() - 1);
+        if total <= width {
+            layout = (rows, widths);
+            break;
+        }
+    }
 
-            if let Err(e) = set_file_times(path, new_atime, new_mtime) {
-                eprintln!("touch: cannot touch '{}': {}", path.display(), e);
-                encountered_error = true;
+    let (rows, widths) = layout;
+    for row in 0..rows {
+        let mut line = String::new();
+        for (column, column_width) in widths.iter().enumerate() {
+            let index = column * rows + row;
+            let Some(name) = names.get(index) else { break };
+            line.push_str(name);
+            if names.get(index + rows).is_some() {
+                line.push_str(&" ".repeat(column_width - lengths[index] + 2));
             }
         }
+        writeln!(out, "{}", line)?;
     }
+    Ok(())
+}
 
-    if encountered_error {
-        Ok(-100)
-    } else {
-        Ok(0)
+// Maps numeric ids to names from a passwd/group style file
+fn read_id_names(file: &str) -> HashMap<u32, String> {
+    let mut names = HashMap::new();
+    if let Ok(content) = fs::read_to_string(file) {
+        for line in content.lines() {
+            let fields: Vec<&str> = line.split(':').collect();
+            if let (Some(name), Some(Ok(id))) = (fields.first(), fields.get(2).map(|id| id.parse())) {
+                names.entry(id).or_insert_with(|| name.to_string());
+            }
+        }
     }
+    names
+}
+
+// Renders a mode like `drwxr-xr-x`, including setuid/setgid/sticky markers
+fn mode_string(metadata: &fs::Metadata) -> String {
+    let file_type = metadata.file_type();
+    let kind = if file_type.is_dir() {
+        'd'
+    } else if file_type.is_symlink() {
+        'l'
+    } else if file_type.is_fifo() {
+        'p'
+    } else if file_type.is_socket() {
+        's'
+    } else if file_type.is_char_device() {
+        'c'
+    } else if file_type.is_block_device() {
+        'b'
+    } else {
+        '-'
+    };
+
+    let mode = metadata.mode();
+    let bit = |mask: u32, c: char| if mode & mask != 0 { c } else { '-' };
+    let special = |exec_mask: u32, special_mask: u32, set: char| {
+        match (mode & exec_mask != 0, mode & special_mask != 0) {
+            (true, true) => set,
+            (false, true) => set.to_ascii_uppercase(),
+            (true, false) => 'x',
+            (false, false) => '-',
+        }
+    };
+
+    [
+        kind,
+        bit(0o400, 'r'),
+        bit(0o200, 'w'),
+        special(0o100, 0o4000, 's'),
+        bit(0o040, 'r'),
+        bit(0o020, 'w'),
+        special(0o010, 0o2000, 's'),
+        bit(0o004, 'r'),
+        bit(0o002, 'w'),
+        special(0o001, 0o1000, 't'),
+    ]
+    .iter()
+    .collect()
 }
 
-fn handle_chmod(args: &[String]) -> Result<i32> {
-    if args.len() != 2 {
-        return Err(anyhow!("chmod: missing operand or too many arguments"));
+/// Formats a byte count the way `ls -h` does: plain bytes below 1K, then one
+/// decimal below 10 units (1.2K, 3.4M) and whole units above, rounding up.
+fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 6] = ["K", "M", "G", "T", "P", "E"];
+    if bytes < 1024 {
+        return bytes.to_string();
     }
 
-    let mode_str = &args[0];
-    let path = Path::new(&args[1]);
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    value /= 1024.0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
 
-    let current_permissions = fs::metadata(path)?.permissions();
-    let mut current_mode = current_permissions.mode();
+    if value < 10.0 {
+        let rounded = (value * 10.0).ceil() / 10.0;
+        if rounded < 10.0 {
+            return format!("{:.1}{}", rounded, UNITS[unit]);
+        }
+    }
+    let rounded = value.ceil();
+    if rounded >= 1024.0 && unit < UNITS.len() - 1 {
+        return format!("1.0{}", UNITS[unit + 1]);
+    }
+    format!("{:.0}{}", rounded, UNITS[unit])
+}
 
-    if mode_str.chars().all(char::is_numeric) {
-        // Numeric mode
-        let numeric_mode = u32::from_str_radix(mode_str, 8)
-            .map_err(|_| anyhow!("chmod: invalid mode: '{}'", mode_str))?;
-        current_mode = numeric_mode;
-    } else {
-        // Symbolic mode parsing
-        let mut chars = mode_str.chars().peekable();
-        let mut target_who_mask = 0;
-        let mut op = ' '; // Default operator
-        let mut perm_bits = 0;
-
-        // Parse 'who' part (u, g, o, a)
-        let mut found_who = false;
-        while let Some(&c) = chars.peek() {
-            match c {
-                'u' => { target_who_mask |= 0o700; chars.next(); found_who = true; },
-                'g' => { target_who_mask |= 0o070; chars.next(); found_who = true; },
-                'o' => { target_who_mask |= 0o007; chars.next(); found_who = true; },
-                'a' => { target_who_mask |= 0o777; chars.next(); found_who = true; },
-                _ => break,
-            }
-        }
-        if !found_who { // If no 'who' specified, default to 'a' (all)
-            target_who_mask = 0o777;
-        }
-
-        // Parse operator (+ or -)
-        if let Some(&c) = chars.peek() {
-            if c == '+' || c == '-' {
-                op = c;
-                chars.next();
-            } else {
-                return Err(anyhow!("chmod: invalid symbolic mode operator: '{}'", c));
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum CopyMode {
+    Copy,
+    HardLink,
+    Symlink,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct CopyOptions<'a> {
+    mode: CopyMode,
+    dry_run: DryRun,
+    progress: bool,
+    /// Worker threads used for the files of a recursive copy
+    jobs: usize,
+    /// How existing destinations are kept before being overwritten
+    backup: Option<Backup<'a>>,
+    /// Existing destinations are skipped; the check is atomic with the create
+    no_clobber: bool,
+    sparse: Sparse,
+    preserve: Preserve,
+    /// With --preserve=links, where the first copy of each multiply-linked
+    /// source file went, by the source's (device, inode)
+    linked: &'a Mutex<HashMap<(u64, u64), PathBuf>>,
+    verbosity: Verbosity,
+}
+
+/// Which attributes a copy carries over from its source (`-p`, `--preserve`).
+/// fs::copy already keeps a file's permission bits; `mode` also sets them on
+/// copied directories, and again after any change of owner.
+#[derive(Debug, Default, Clone, Copy)]
+struct Preserve {
+    mode: bool,
+    ownership: bool,
+    timestamps: bool,
+    /// Hard links between copied files become hard links between the copies
+    links: bool,
+}
+
+impl Preserve {
+    // What -p and a bare --preserve add
+    const DEFAULT: &str = "mode,ownership,timestamps";
+
+    // Adds the attributes of a --preserve=LIST to the ones already chosen
+    fn parse(mut self, list: &str) -> Result<Preserve> {
+        for attribute in list.split(',') {
+            match attribute {
+                "mode" => self.mode = true,
+                "ownership" => self.ownership = true,
+                "timestamps" => self.timestamps = true,
+                "links" => self.links = true,
+                "all" => {
+                    self = Preserve { mode: true, ownership: true, timestamps: true, links: true };
+                }
+                _ => return Err(anyhow!("cp: invalid argument '{}' for '--preserve'", attribute)),
             }
-        } else {
-            return Err(anyhow!("chmod: missing symbolic mode operator"));
         }
+        Ok(self)
+    }
 
-        // Parse permissions (r, w, x)
-        let mut found_perms = false;
-        while let Some(&c) = chars.peek() {
-            match c {
-                'r' => { perm_bits |= 0o4; chars.next(); found_perms = true; },
-                'w' => { perm_bits |= 0o2; chars.next(); found_perms = true; },
-                'x' => { perm_bits |= 0o1; chars.next(); found_perms = true; },
-                _ => return Err(anyhow!("chmod: invalid permission: '{}'", c)),
+    // Ownership goes first because changing it can clear the setuid and
+    // setgid bits. Like cp, a user who may not give files away keeps them.
+    fn apply(self, metadata: &fs::Metadata, destination: &Path) -> io::Result<()> {
+        let is_symlink = metadata.file_type().is_symlink();
+        if self.ownership {
+            let owner = (Some(metadata.uid()), Some(metadata.gid()));
+            match std::os::unix::fs::lchown(destination, owner.0, owner.1) {
+                Err(e) if e.kind() == io::ErrorKind::PermissionDenied => {}
+                result => result?,
             }
         }
-        if !found_perms {
-            return Err(anyhow!("chmod: missing symbolic permissions"));
+        if self.mode && !is_symlink {
+            fs::set_permissions(destination, metadata.permissions())?;
+        }
+        if self.timestamps {
+            let atime = FileTime::from_last_access_time(metadata);
+            let mtime = FileTime::from_last_modification_time(metadata);
+            if is_symlink {
+                filetime::set_symlink_file_times(destination, atime, mtime)?;
+            } else {
+                set_file_times(destination, atime, mtime)?;
+            }
         }
+        Ok(())
+    }
+}
 
-        // Apply permissions based on operator
-        let mut effective_perm_change = 0;
+/// When a copy leaves holes instead of writing runs of zeros (`--sparse`).
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+enum Sparse {
+    /// Only when the source has holes itself, as coreutils guesses
+    #[default]
+    Auto,
+    Always,
+    Never,
+}
 
-        // Calculate permission bits for user, group, other based on `perm_bits`
-        let user_perm = (perm_bits & 0o4) << 6 | (perm_bits & 0o2) << 6 | (perm_bits & 0o1) << 6;
-        let group_perm = (perm_bits & 0o4) << 3 | (perm_bits & 0o2) << 3 | (perm_bits & 0o1) << 3;
-        let other_perm = perm_bits & 0o4 | perm_bits & 0o2 | perm_bits & 0o1;
+/// The coreutils version-control schemes for `--backup`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum BackupMode {
+    /// `dest~`
+    Simple,
+    /// `dest.~N~` with the next free N
+    Numbered,
+    /// Numbered if numbered backups already exist, simple otherwise
+    Existing,
+}
 
-        // Combine based on who_mask
-        effective_perm_change |= user_perm & target_who_mask;
-        effective_perm_change |= group_perm & target_who_mask;
-        effective_perm_change |= other_perm & target_who_mask;
-        
-        if op == '+' {
-            current_mode |= effective_perm_change;
-        } else { // op == '-'
-            current_mode &= !effective_perm_change;
-        }
+#[derive(Debug, Clone, Copy)]
+struct Backup<'a> {
+    mode: BackupMode,
+    suffix: &'a str,
+}
+
+// Maps a --backup CONTROL word (or $VERSION_CONTROL) to a scheme; `none` turns backups off
+fn parse_backup_control(control: &str) -> Result<Option<BackupMode>> {
+    match control {
+        "none" | "off" => Ok(None),
+        "simple" | "never" => Ok(Some(BackupMode::Simple)),
+        "numbered" | "t" => Ok(Some(BackupMode::Numbered)),
+        "existing" | "nil" => Ok(Some(BackupMode::Existing)),
+        _ => Err(anyhow!("invalid backup type '{}'", control)),
+    }
+}
+
+// The backup mode when --backup is given without CONTROL
+fn default_backup_mode() -> Result<Option<BackupMode>> {
+    match std::env::var("VERSION_CONTROL") {
+        Ok(control) if !control.is_empty() => parse_backup_control(&control),
+        _ => Ok(Some(BackupMode::Existing)),
     }
+}
+
+fn default_backup_suffix() -> String {
+    std::env::var("SIMPLE_BACKUP_SUFFIX")
+        .ok()
+        .filter(|suffix| !suffix.is_empty() && !suffix.contains('/'))
+        .unwrap_or_else(|| "~".to_string())
+}
+
+// Picks the name an existing destination is moved to before being replaced
+fn backup_path(destination: &Path, backup: Backup<'_>) -> PathBuf {
+    let name = destination.file_name().unwrap_or_default().to_string_lossy().into_owned();
+    let directory = destination.parent().filter(|parent| !parent.as_os_str().is_empty());
 
-    let new_permissions = fs::Permissions::from_mode(current_mode);
+    // Highest N among the existing `name.~N~` files
+    let prefix = format!("{}.~", name);
+    let highest = fs::read_dir(directory.unwrap_or(Path::new(".")))
+        .into_iter()
+        .flatten()
+        .flatten()
+        .filter_map(|entry| {
+            let entry_name = entry.file_name().to_string_lossy().into_owned();
+            entry_name.strip_prefix(&prefix)?.strip_suffix('~')?.parse::<u64>().ok()
+        })
+        .max();
 
-    if let Err(e) = fs::set_permissions(path, new_permissions) {
-        eprintln!("chmod: cannot change permissions of '{}': {}", path.display(), e);
-        return Ok(-25);
+    let numbered = match backup.mode {
+        BackupMode::Simple => false,
+        BackupMode::Numbered => true,
+        BackupMode::Existing => highest.is_some(),
+    };
+    let backup_name = if numbered {
+        format!("{}{}~", prefix, highest.unwrap_or(0) + 1)
+    } else {
+        format!("{}{}", name, backup.suffix)
+    };
+    destination.with_file_name(backup_name)
+}
+
+// Renames an existing destination out of the way; directories are left alone
+fn make_backup(destination: &Path, backup: Backup<'_>, dry_run: DryRun) -> io::Result<()> {
+    match fs::symlink_metadata(destination) {
+        Ok(metadata) if !metadata.is_dir() => {
+            let backup_name = backup_path(destination, backup);
+            if dry_run.0 {
+                let (from, to) = (destination.display(), backup_name.display());
+                println!("would back up '{}' -> '{}'", from, to);
+                return Ok(());
+            }
+            fs::rename(destination, backup_name)
+        }
+        _ => Ok(()),
     }
+}
 
-    Ok(0)
-}
\ No newline at end of file
+fn handle_cp(args: &[String], dry_run: DryRun, verbosity: Verbosity) -> Result<i32> {
+    let mut recursive = false;
+    let mut parents = false;
+    let mut progress = false;
+    let mut jobs = 1;
+    let mut backup_mode = None;
+    let mut suffix = None;
+    let mut no_clobber = false;
+    let mut sparse = Sparse::Auto;
+    let mut preserve = Preserve::default();
+    let mut mode = CopyMode::Copy;
+    let mut operands = Vec::new(); // Will hold source(s) and destination
+
+    let mut i = 0;
+    while i < args.len() {
+        match args[i].as_str() {
+            "-R" | "-r" | "--recursive" => {
+                recursive = true;
+            }
+            "-l" | "--link" => {
+                mode = CopyMode::HardLink;
+            }
+            "-s" | "--symbolic-link" => {
+                mode = CopyMode::Symlink;
+            }
+            "--parents" => {
+                parents = true;
+            }
+            "--progress" => {
+                progress = true;
+            }
+            "-n" | "--no-clobber" => {
+                no_clobber = true;
+            }
+            "-j" | "--jobs" => {
+                i += 1;
+                jobs = args
+                    .get(i)
+                    .and_then(|value| value.parse().ok())
+                    .filter(|jobs| *jobs > 0)
+                    .ok_or_else(|| anyhow!("cp: --jobs requires a positive number"))?;
+            }
+            "-b" | "--backup" => {
+                backup_mode = default_backup_mode().map_err(|e| anyhow!("cp: {}", e))?;
+            }
+            arg if arg.starts_with("--backup=") => {
+                let control = &arg["--backup=".len()..];
+                backup_mode = parse_backup_control(control).map_err(|e| anyhow!("cp: {}", e))?;
+            }
+            "-S" | "--suffix" => {
+                i += 1;
+                let value = args
+                    .get(i)
+                    .ok_or_else(|| anyhow!("cp: option '--suffix' requires an argument"))?;
+                suffix = Some(value.clone());
+            }
+            arg if arg.starts_with("--suffix=") => {
+                suffix = Some(arg["--suffix=".len()..].to_string());
+            }
+            "-p" | "--preserve" => {
+                preserve = preserve.parse(Preserve::DEFAULT)?;
+            }
+            arg if arg.starts_with("--preserve=") => {
+                preserve = preserve.parse(&arg["--preserve=".len()..])?;
+            }
+            arg if arg.starts_with("--sparse=") => {
+                sparse = match &arg["--sparse=".len()..] {
+                    "auto" => Sparse::Auto,
+                    "always" => Sparse::Always,
+                    "never" => Sparse::Never,
+                    when => return Err(anyhow!("cp: invalid argument '{}' for '--sparse'", when)),
+                };
+            }
+            _ => {
+                operands.push(&args[i]);
+            }
+        }
+        i += 1;
+    }
+
+    if operands.len() < 2 {
+        return Err(anyhow!("cp: missing file operand"));
+    }
+
+    let suffix = suffix.unwrap_or_else(default_backup_suffix);
+    let backup = backup_mode.map(|mode| Backup { mode, suffix: &suffix });
+    if no_clobber && backup.is_some() {
+        return Err(anyhow!("cp: options --backup and --no-clobber are mutually exclusive"));
+    }
+    let linked = Mutex::new(HashMap::new());
+    let options = CopyOptions {
+        mode,
+        dry_run,
+        progress,
+        jobs,
+        backup,
+        no_clobber,
+        sparse,
+        preserve,
+        linked: &linked,
+        verbosity,
+    };
+    if !dry_run.0 {
+        install_interrupt_cleanup();
+    }
+
+    let (sources, destination) = operands.split_at(operands.len() - 1);
+    let destination = PathBuf::from(destination[0]);
+
+    if (sources.len() > 1 || parents) && !destination.is_dir() {
+        verbosity.error(format_args!("cp: target '{}' is not a directory", destination.display()));
+        return Ok(-90);
+    }
+
+    let mut encountered_error = false;
+    for source_path_str in sources {
+        let source = PathBuf::from(source_path_str);
+
+        let target = if parents {
+            // Keep the whole source path under the destination directory
+            let relative: PathBuf = source
+                .components()
+                .filter(|component| !matches!(component, std::path::Component::RootDir))
+                .collect();
+            let target = destination.join(relative);
+            if let Some(parent) = target.parent() {
+                if dry_run.0 {
+                    if !parent.is_dir() {
+                        println!("would create directory '{}'", parent.display());
+                    }
+                } else if let Err(e) = fs::create_dir_all(parent) {
+                    verbosity.error(format_args!(
+                        "cp: cannot create directory '{}': {}",
+                        parent.display(),
+                        e
+                    ));
+                    encountered_error = true;
+                    continue;
+                }
+            }
+            target
+        } else if destination.is_dir() {
+            // If destination is an existing directory, append source name to it
+            match source.file_name() {
+                Some(file_name) => destination.join(file_name),
+                None => destination.clone(),
+            }
+        } else {
+            destination.clone()
+        };
+
+        // `dest/` names a directory; only a directory source may create it
+        if names_missing_directory(&target) && !source.is_dir() {
+            verbosity.error(format_args!(
+                "cp: cannot create regular file '{}': Not a directory",
+                target.display()
+            ));
+            encountered_error = true;
+            continue;
+        }
+
+        if !cp_path(&source, &target, recursive, options) {
+            encountered_error = true;
+        }
+    }
+
+    if encountered_error {
+        Ok(-90)
+    } else {
+        Ok(0)
+    }
+}
+
+// Copies one source operand to its final target path, reporting errors
+fn cp_path(source: &Path, destination: &Path, recursive: bool, options: CopyOptions<'_>) -> bool {
+    if source.is_dir() {
+        if !recursive {
+            options.verbosity.error(format_args!(
+                "cp: -r not specified; omitting directory '{}'",
+                source.display()
+            ));
+            return false;
+        }
+        // The copy would keep finding its own output and never finish
+        if is_same_or_descendant(source, destination) {
+            options.verbosity.error(format_args!(
+                "cp: cannot copy a directory, '{}', into itself, '{}'",
+                source.display(),
+                destination.display()
+            ));
+            return false;
+        }
+        if let Err(e) = copy_dir_recursive(source, destination, options) {
+            options.verbosity.error(format_args!(
+                "cp: cannot copy directory '{}' to '{}': {}",
+                source.display(),
+                destination.display(),
+                e
+            ));
+            return false;
+        }
+    } else if source.is_file() {
+        if let Err(e) = copy_file(source, destination, options) {
+            options.verbosity.error(format_args!(
+                "cp: cannot copy '{}' to '{}': {}",
+                source.display(),
+                destination.display(),
+                e
+            ));
+            return false;
+        }
+    } else {
+        options.verbosity.error(format_args!(
+            "cp: cannot stat '{}': No such file or directory",
+            source.display()
+        ));
+        return false;
+    }
+    true
+}
+
+// Copies, hard-links or symlinks a single non-directory entry
+fn copy_file(source: &Path, destination: &Path, options: CopyOptions<'_>) -> Result<()> {
+    if let Some(backup) = options.backup {
+        make_backup(destination, backup, options.dry_run)?;
+    }
+    if options.dry_run.0 {
+        let action = match options.mode {
+            CopyMode::Copy => "copy",
+            CopyMode::HardLink => "hard link",
+            CopyMode::Symlink => "symlink",
+        };
+        if !(options.no_clobber && destination.symlink_metadata().is_ok()) {
+            println!("would {} '{}' -> '{}'", action, source.display(), destination.display());
+        }
+        return Ok(());
+    }
+
+    if options.preserve.links && options.mode == CopyMode::Copy {
+        let metadata = fs::metadata(source)?;
+        if metadata.nlink() > 1 {
+            // Held for the whole copy, so that another name for the same
+            // file never links to a copy that does not exist yet
+            let mut linked = options.linked.lock().unwrap_or_else(|e| e.into_inner());
+            let key = (metadata.dev(), metadata.ino());
+            if let Some(first) = linked.get(&key) {
+                fs::hard_link(first, destination)?;
+                return Ok(());
+            }
+            copy_contents(source, destination, options)?;
+            linked.insert(key, destination.to_path_buf());
+            return Ok(());
+        }
+    }
+    copy_contents(source, destination, options)
+}
+
+// Copies, hard-links or symlinks `source` itself, then copies over the
+// attributes --preserve asked for
+fn copy_contents(source: &Path, destination: &Path, options: CopyOptions<'_>) -> Result<()> {
+    // The bar only makes sense on a terminal
+    let progress = options.progress && io::stderr().is_terminal();
+    let holes = match options.sparse {
+        Sparse::Always => true,
+        Sparse::Never => false,
+        // st_blocks is always counted in 512-byte units
+        Sparse::Auto => fs::metadata(source)
+            .is_ok_and(|metadata| metadata.is_file() && metadata.blocks() * 512 < metadata.len()),
+    };
+    let result = match options.mode {
+        CopyMode::Copy if progress || options.no_clobber || holes => {
+            copy_streamed(source, destination, options.no_clobber, progress, holes)
+        }
+        CopyMode::Copy => {
+            let _partial = PartialFile::new(destination);
+            fs::copy(source, destination).map(|_| ())
+        }
+        CopyMode::HardLink => fs::hard_link(source, destination),
+        CopyMode::Symlink => {
+            // A relative target would resolve against the link's directory,
+            // so always point the link at the absolute source path
+            let target = if source.is_absolute() {
+                Ok(source.to_path_buf())
+            } else {
+                fs::canonicalize(source)
+            };
+            target.and_then(|target| symlink(target, destination))
+        }
+    };
+    match result {
+        // Links are created atomically too, so losing the race is a skip
+        Err(e) if options.no_clobber && e.kind() == io::ErrorKind::AlreadyExists => Ok(()),
+        Ok(()) if options.mode == CopyMode::Copy => {
+            Ok(options.preserve.apply(&fs::metadata(source)?, destination)?)
+        }
+        result => Ok(result?),
+    }
+}
+
+// Destinations that are being written right now; several can be open at
+// once when cp copies in parallel
+static PARTIAL_FILES: Mutex<Vec<PathBuf>> = Mutex::new(Vec::new());
+
+// Registers a destination for removal on Ctrl-C until it is dropped
+struct PartialFile(PathBuf);
+
+impl PartialFile {
+    fn new(path: &Path) -> PartialFile {
+        let mut files = PARTIAL_FILES.lock().unwrap_or_else(|e| e.into_inner());
+        files.push(path.to_path_buf());
+        PartialFile(path.to_path_buf())
+    }
+}
+
+impl Drop for PartialFile {
+    fn drop(&mut self) {
+        let mut files = PARTIAL_FILES.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(index) = files.iter().position(|path| *path == self.0) {
+            files.swap_remove(index);
+        }
+    }
+}
+
+// Installs (once) a SIGINT handler that deletes half-written destinations
+// before exiting, so an interrupted copy never leaves a truncated file behind
+fn install_interrupt_cleanup() {
+    static INSTALL: Once = Once::new();
+    INSTALL.call_once(|| {
+        let installed = ctrlc::set_handler(|| {
+            // Holding the lock until exit keeps other threads from
+            // unregistering (or finishing) files while we clean up
+            let files = PARTIAL_FILES.lock().unwrap_or_else(|e| e.into_inner());
+            for path in files.iter() {
+                let _ = fs::remove_file(path);
+            }
+            std::process::exit(130);
+        });
+        if let Err(e) = installed {
+            eprintln!("cp: cannot install interrupt handler: {}", e);
+        }
+    });
+}
+
+// Streams the file in chunks, optionally redrawing a progress line on stderr
+// a few times a second with the percentage done and the average throughput.
+// With `exclusive` the destination is opened with O_EXCL, so the existence
+// check and the create are one step and two racing copies can't both win.
+fn copy_streamed(
+    source: &Path,
+    destination: &Path,
+    exclusive: bool,
+    progress: bool,
+    holes: bool,
+) -> io::Result<()> {
+    const CHUNK_SIZE: usize = 1024 * 1024;
+    // Holes are made a whole block at a time, so shorter runs of zeros are written
+    const HOLE_BLOCK: usize = 4096;
+    const REDRAW_INTERVAL: std::time::Duration = std::time::Duration::from_millis(250);
+    const BAR_WIDTH: usize = 30;
+
+    let mut input = fs::File::open(source)?;
+    let metadata = input.metadata()?;
+    let total = metadata.len();
+    let mut output = if exclusive {
+        fs::OpenOptions::new().write(true).create_new(true).open(destination)?
+    } else {
+        fs::File::create(destination)?
+    };
+    // Only a file we created (or truncated) is ours to remove on Ctrl-C
+    let _partial = PartialFile::new(destination);
+
+    let name = source.file_name().unwrap_or(source.as_os_str()).to_string_lossy();
+    let started = std::time::Instant::now();
+    let mut last_redraw: Option<std::time::Instant> = None;
+    let mut copied: u64 = 0;
+    let mut chunk = vec![0u8; CHUNK_SIZE];
+
+    let draw = |copied: u64| {
+        // A file that grows while being copied must not push the bar past 100%
+        let percent = (copied * 100).checked_div(total).unwrap_or(100).min(100);
+        let filled = percent as usize * BAR_WIDTH / 100;
+        let seconds = started.elapsed().as_secs_f64().max(0.001);
+        let rate = (copied as f64 / seconds) as u64;
+        eprint!(
+            "\r{}: {:>3}% [{}{}] {} {}/s ",
+            name,
+            percent,
+            "#".repeat(filled),
+            " ".repeat(BAR_WIDTH - filled),
+            format_size(copied),
+            format_size(rate)
+        );
+    };
+
+    loop {
+        let read = match input.read(&mut chunk) {
+            Ok(0) => break,
+            Ok(read) => read,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        };
+        if holes {
+            for block in chunk[..read].chunks(HOLE_BLOCK) {
+                if block.iter().all(|&byte| byte == 0) {
+                    output.seek(SeekFrom::Current(block.len() as i64))?;
+                } else {
+                    output.write_all(block)?;
+                }
+            }
+        } else {
+            output.write_all(&chunk[..read])?;
+        }
+        copied += read as u64;
+        if progress && last_redraw.is_none_or(|at| at.elapsed() >= REDRAW_INTERVAL) {
+            draw(copied);
+            last_redraw = Some(std::time::Instant::now());
+        }
+    }
+    if progress {
+        draw(copied);
+        eprintln!();
+    }
+    // A trailing hole was only seeked over, so the length still has to be set
+    if holes {
+        output.set_len(copied)?;
+    }
+
+    // Keep the permission bits, as fs::copy does
+    fs::set_permissions(destination, metadata.permissions())?;
+    Ok(())
+}
+
+fn copy_dir_recursive(source: &Path, destination: &Path, options: CopyOptions<'_>) -> Result<()> {
+    if options.jobs > 1 && !options.dry_run.0 {
+        return copy_dir_parallel(source, destination, options);
+    }
+    if options.dry_run.0 {
+        println!("would create directory '{}'", destination.display());
+    } else {
+        fs::create_dir_all(destination)?;
+    }
+    for entry in fs::read_dir(source)? {
+        let entry = entry?;
+        let path = entry.path();
+        let dest_path = destination.join(entry.file_name());
+        let file_type = entry.file_type()?;
+
+        if file_type.is_symlink() {
+            // Recreate the link itself instead of copying what it points to
+            let target = fs::read_link(&path)?;
+            if options.dry_run.0 {
+                println!("would symlink '{}' -> '{}'", dest_path.display(), target.display());
+            } else {
+                symlink(&target, &dest_path)?;
+                options.preserve.apply(&fs::symlink_metadata(&path)?, &dest_path)?;
+            }
+        } else if file_type.is_dir() {
+            copy_dir_recursive(&path, &dest_path, options)?;
+        } else {
+            copy_file(&path, &dest_path, options)?;
+        }
+    }
+    // Only now, since filling the directory changed its times and a
+    // read-only mode would have kept it from being filled
+    if !options.dry_run.0 {
+        options.preserve.apply(&fs::metadata(source)?, destination)?;
+    }
+    Ok(())
+}
+
+// Recreates the directory skeleton (and symlinks) first, then hands the
+// regular files to a pool of workers, so no file is copied before its
+// directory exists
+fn copy_dir_parallel(source: &Path, destination: &Path, options: CopyOptions<'_>) -> Result<()> {
+    let mut files = Vec::new();
+    let mut directories = Vec::new();
+    let mut pending = vec![(source.to_path_buf(), destination.to_path_buf())];
+    while let Some((from, to)) = pending.pop() {
+        fs::create_dir_all(&to)?;
+        for entry in fs::read_dir(&from)? {
+            let entry = entry?;
+            let path = entry.path();
+            let dest_path = to.join(entry.file_name());
+            let file_type = entry.file_type()?;
+
+            if file_type.is_symlink() {
+                symlink(fs::read_link(&path)?, &dest_path)?;
+                options.preserve.apply(&fs::symlink_metadata(&path)?, &dest_path)?;
+            } else if file_type.is_dir() {
+                pending.push((path, dest_path));
+            } else {
+                files.push((path, dest_path));
+            }
+        }
+        directories.push((from, to));
+    }
+
+    // Progress bars from several workers would only garble each other
+    let options = CopyOptions { progress: false, ..options };
+    let next = AtomicUsize::new(0);
+    let failure = Mutex::new(None);
+    std::thread::scope(|scope| {
+        for _ in 0..options.jobs.min(files.len()) {
+            scope.spawn(|| loop {
+                let index = next.fetch_add(1, Ordering::Relaxed);
+                let Some((from, to)) = files.get(index) else {
+                    break;
+                };
+                if let Err(e) = copy_file(from, to, options) {
+                    let mut failure = failure.lock().unwrap_or_else(|poison| poison.into_inner());
+                    failure.get_or_insert(anyhow!("'{}': {}", from.display(), e));
+                    break;
+                }
+            });
+        }
+    });
+
+    if let Some(e) = failure.into_inner().unwrap_or_else(|poisoned| poisoned.into_inner()) {
+        return Err(e);
+    }
+    // The workers have filled every directory, so their attributes can be set
+    for (from, to) in directories {
+        options.preserve.apply(&fs::metadata(&from)?, &to)?;
+    }
+    Ok(())
+}
+
+fn handle_touch(args: &[String]) -> Result<i32> {
+    let mut access = false;
+    let mut no_create = false;
+    let mut modify = false;
+    let mut time: Option<FileTime> = None;
+    let mut files_to_touch = Vec::new();
+
+    let mut i = 0;
+    while i < args.len() {
+        let arg = &args[i];
+        i += 1;
+        match arg.as_str() {
+            "-c" | "--no-create" => no_create = true,
+            "-t" | "-d" | "--date" => {
+                let value = args
+                    .get(i)
+                    .ok_or_else(|| anyhow!("touch: option '{}' requires an argument", arg))?;
+                i += 1;
+                let parsed = if arg == "-t" {
+                    timestamp::parse_stamp(value)
+                } else {
+                    timestamp::parse_date(value)
+                };
+                let parsed =
+                    parsed.ok_or_else(|| anyhow!("touch: invalid date format '{}'", value))?;
+                time = Some(FileTime::from_unix_time(
+                    parsed.timestamp(),
+                    parsed.timestamp_subsec_nanos(),
+                ));
+            }
+            // Short flags may be grouped, e.g. `-am` or `-cm`
+            flags if flags.len() > 1
+                && flags.starts_with('-')
+                && flags[1..].chars().all(|c| matches!(c, 'a' | 'c' | 'm')) =>
+            {
+                for flag in flags[1..].chars() {
+                    match flag {
+                        'a' => access = true,
+                        'c' => no_create = true,
+                        _ => modify = true,
+                    }
+                }
+            }
+            _ => files_to_touch.push(arg),
+        }
+    }
+
+    if files_to_touch.is_empty() {
+        return Err(anyhow!("touch: missing file operand"));
+    }
+
+    // Neither -a nor -m means both times are updated
+    if !access && !modify {
+        access = true;
+        modify = true;
+    }
+
+    let now = FileTime::now();
+    // -t/-d replace the current time for whichever times are being set
+    let new_time = time.unwrap_or(now);
+    let mut encountered_error = false;
+
+    for file_path_str in files_to_touch {
+        let path = Path::new(file_path_str);
+
+        match fs::metadata(path) {
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                if no_create {
+                    continue; // Do not create if -c is specified
+                }
+                // A new file already carries the current times
+                let created = fs::File::create(path).and_then(|_| match time {
+                    Some(_) => set_file_times(
+                        path,
+                        if access { new_time } else { now },
+                        if modify { new_time } else { now },
+                    ),
+                    None => Ok(()),
+                });
+                if let Err(e) = created {
+                    eprintln!("touch: cannot touch '{}': {}", path.display(), e);
+                    encountered_error = true;
+                }
+            }
+            Err(e) => {
+                eprintln!("touch: cannot touch '{}': {}", path.display(), e);
+                encountered_error = true;
+            }
+            // Existing files and directories only get their times updated
+            Ok(metadata) => {
+                let atime = FileTime::from_last_access_time(&metadata);
+                let mtime = FileTime::from_last_modification_time(&metadata);
+
+                let new_atime = if access { new_time } else { atime };
+                let new_mtime = if modify { new_time } else { mtime };
+
+                if let Err(e) = set_file_times(path, new_atime, new_mtime) {
+                    eprintln!("touch: cannot touch '{}': {}", path.display(), e);
+                    encountered_error = true;
+                }
+            }
+        }
+    }
+
+    if encountered_error {
+        Ok(-100)
+    } else {
+        Ok(0)
+    }
+}
+
+fn handle_chmod(args: &[String], dry_run: DryRun, verbosity: Verbosity) -> Result<i32> {
+    let mut reference: Option<&str> = None;
+    let mut recursive = false;
+    let mut operands = Vec::new();
+
+    let mut i = 0;
+    while i < args.len() {
+        match args[i].as_str() {
+            "-R" | "--recursive" => {
+                recursive = true;
+            }
+            "--reference" => {
+                i += 1;
+                let file = args
+                    .get(i)
+                    .ok_or_else(|| anyhow!("chmod: option '--reference' requires an argument"))?;
+                reference = Some(file);
+            }
+            arg if arg.starts_with("--reference=") => {
+                reference = Some(&arg["--reference=".len()..]);
+            }
+            _ => operands.push(args[i].as_str()),
+        }
+        i += 1;
+    }
+
+    // With --reference there is no MODE operand, only targets
+    let reference_mode = match reference {
+        Some(file) => match fs::metadata(file) {
+            Ok(metadata) => Some(metadata.permissions().mode() & 0o7777),
+            Err(e) => {
+                verbosity.error(format_args!(
+                    "chmod: failed to get attributes of '{}': {}",
+                    file,
+                    e
+                ));
+                return Ok(-25);
+            }
+        },
+        None => None,
+    };
+    let (mode, paths) = match (reference_mode, operands.as_slice()) {
+        (Some(_), paths) if !paths.is_empty() => ("", paths),
+        (None, [mode, paths @ ..]) if !paths.is_empty() => (*mode, paths),
+        _ => return Err(anyhow!("chmod: missing operand")),
+    };
+    // Reject a bad mode before touching anything
+    if reference_mode.is_none() {
+        chmod_apply(mode, 0, false)?;
+    }
+
+    let options = ChmodOptions { mode, reference_mode, recursive, dry_run, verbosity };
+    let mut encountered_error = false;
+    for path in paths {
+        let path = Path::new(path);
+        let metadata = match fs::metadata(path) {
+            Ok(metadata) => metadata,
+            Err(e) => {
+                verbosity.error(format_args!("chmod: cannot access '{}': {}", path.display(), e));
+                encountered_error = true;
+                continue;
+            }
+        };
+        if !chmod_path(path, &metadata, options) {
+            encountered_error = true;
+        }
+    }
+
+    if encountered_error {
+        Ok(-25)
+    } else {
+        Ok(0)
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct ChmodOptions<'a> {
+    mode: &'a str,
+    reference_mode: Option<u32>,
+    recursive: bool,
+    dry_run: DryRun,
+    verbosity: Verbosity,
+}
+
+// Changes one file, then, with -R, everything below it. The mode is worked
+// out per file, since `X` depends on what each file already is. Returns
+// false if anything failed.
+fn chmod_path(path: &Path, metadata: &fs::Metadata, options: ChmodOptions<'_>) -> bool {
+    let current_mode = metadata.permissions().mode();
+    let new_mode = match options.reference_mode {
+        Some(mode) => Ok((current_mode & !0o7777) | mode),
+        None => chmod_apply(options.mode, current_mode, metadata.is_dir()),
+    };
+    let mut succeeded = true;
+    match new_mode {
+        Ok(mode) if options.dry_run.0 => {
+            println!("would change mode of '{}' to {:04o}", path.display(), mode & 0o7777);
+        }
+        Ok(mode) => {
+            if let Err(e) = fs::set_permissions(path, fs::Permissions::from_mode(mode)) {
+                options.verbosity.error(format_args!(
+                    "chmod: cannot change permissions of '{}': {}",
+                    path.display(),
+                    e
+                ));
+                succeeded = false;
+            }
+        }
+        Err(e) => {
+            options.verbosity.error(format_args!("{}", e));
+            return false;
+        }
+    }
+
+    if options.recursive && metadata.is_dir() {
+        // Like coreutils, symlinks met during the walk are left alone
+        let walked = walk_sorted(path, |entry| {
+            match entry.symlink_metadata() {
+                Ok(entry_metadata) if entry_metadata.file_type().is_symlink() => {}
+                Ok(entry_metadata) => {
+                    if !chmod_path(entry, &entry_metadata, options) {
+                        succeeded = false;
+                    }
+                }
+                Err(e) => {
+                    options.verbosity.error(format_args!(
+                        "chmod: cannot access '{}': {}",
+                        entry.display(),
+                        e
+                    ));
+                    succeeded = false;
+                }
+            }
+            Ok(())
+        });
+        if let Err(e) = walked {
+            options.verbosity.error(format_args!(
+                "chmod: cannot read directory '{}': {}",
+                path.display(),
+                e
+            ));
+            succeeded = false;
+        }
+    }
+    succeeded
+}
+
+// Applies a numeric or symbolic MODE to `mode`
+fn chmod_apply(mode_str: &str, mut mode: u32, is_dir: bool) -> Result<u32> {
+    if mode_str.chars().all(char::is_numeric) {
+        // Up to four octal digits; the leading one holds setuid/setgid/sticky
+        let numeric_mode = u32::from_str_radix(mode_str, 8)
+            .ok()
+            .filter(|mode| *mode <= 0o7777)
+            .ok_or_else(|| anyhow!("chmod: invalid mode: '{}'", mode_str))?;
+        return Ok((mode & !0o7777) | numeric_mode);
+    }
+
+    // Symbolic mode parsing
+    let mut chars = mode_str.chars().peekable();
+    let mut target_who_mask = 0;
+    let mut perm_bits = 0;
+
+    // Parse 'who' part (u, g, o, a)
+    let mut found_who = false;
+    while let Some(&c) = chars.peek() {
+        match c {
+            'u' => { target_who_mask |= 0o4700; chars.next(); found_who = true; },
+            'g' => { target_who_mask |= 0o2070; chars.next(); found_who = true; },
+            'o' => { target_who_mask |= 0o1007; chars.next(); found_who = true; },
+            'a' => { target_who_mask |= 0o7777; chars.next(); found_who = true; },
+            _ => break,
+        }
+    }
+    if !found_who { // If no 'who' specified, default to 'a' (all)
+        target_who_mask = 0o7777;
+    }
+
+    // Parse operator (+ or -)
+    let op;
+    if let Some(&c) = chars.peek() {
+        if c == '+' || c == '-' {
+            op = c;
+            chars.next();
+        } else {
+            return Err(anyhow!("chmod: invalid symbolic mode operator: '{}'", c));
+        }
+    } else {
+        return Err(anyhow!("chmod: missing symbolic mode operator"));
+    }
+
+    // Parse permissions (r, w, x, X, s, t)
+    let mut found_perms = false;
+    while let Some(&c) = chars.peek() {
+        match c {
+            'r' => { perm_bits |= 0o4; chars.next(); found_perms = true; },
+            'w' => { perm_bits |= 0o2; chars.next(); found_perms = true; },
+            'x' => { perm_bits |= 0o1; chars.next(); found_perms = true; },
+            // Execute only for directories and files something can already execute
+            'X' => {
+                if is_dir || mode & 0o111 != 0 {
+                    perm_bits |= 0o1;
+                }
+                chars.next();
+                found_perms = true;
+            },
+            's' => { perm_bits |= 0o10; chars.next(); found_perms = true; },
+            't' => { perm_bits |= 0o20; chars.next(); found_perms = true; },
+            _ => return Err(anyhow!("chmod: invalid permission: '{}'", c)),
+        }
+    }
+    if !found_perms {
+        return Err(anyhow!("chmod: missing symbolic permissions"));
+    }
+
+    // Apply permissions based on operator
+    let mut effective_perm_change = 0;
+
+    // Calculate permission bits for user, group, other based on `perm_bits`
+    // `s` maps to setuid for u and setgid for g, `t` to the sticky bit
+    let rwx = perm_bits & 0o7;
+    let set_id = perm_bits & 0o10 != 0;
+    let sticky = perm_bits & 0o20 != 0;
+    let user_perm = rwx << 6 | if set_id { 0o4000 } else { 0 };
+    let group_perm = rwx << 3 | if set_id { 0o2000 } else { 0 };
+    let other_perm = rwx | if sticky { 0o1000 } else { 0 };
+
+    // Combine based on who_mask
+    effective_perm_change |= user_perm & target_who_mask;
+    effective_perm_change |= group_perm & target_who_mask;
+    effective_perm_change |= other_perm & target_who_mask;
+    
+    if op == '+' {
+        mode |= effective_perm_change;
+    } else { // op == '-'
+        mode &= !effective_perm_change;
+    }
+    Ok(mode)
+}
+
+fn handle_mktemp(args: &[String]) -> Result<i32> {
+    let mut directory = false;
+    let mut parent: Option<PathBuf> = None;
+    let mut template: Option<&String> = None;
+
+    let mut i = 0;
+    while i < args.len() {
+        match args[i].as_str() {
+            "-d" | "--directory" => {
+                directory = true;
+            }
+            "-p" | "--tmpdir" => {
+                i += 1;
+                let dir = args.get(i).ok_or_else(|| anyhow!("mktemp: option requires an argument -- 'p'"))?;
+                parent = Some(PathBuf::from(dir));
+            }
+            _ => {
+                if template.is_some() {
+                    return Err(anyhow!("mktemp: too many templates"));
+                }
+                template = Some(&args[i]);
+            }
+        }
+        i += 1;
+    }
+
+    let template = PathBuf::from(template.map(String::as_str).unwrap_or("tmp.XXXXXXXXXX"));
+    // A bare template (no directory part) goes into $TMPDIR or /tmp
+    let template = match parent {
+        Some(dir) => dir.join(template),
+        None if template.components().count() == 1 => temp_dir().join(template),
+        None => template,
+    };
+
+    let created = if directory {
+        create_temp_dir(&template)
+    } else {
+        create_temp_file(&template).map(|(path, _)| path)
+    };
+    match created {
+        Ok(path) => {
+            println!("{}", path.display());
+            Ok(0)
+        }
+        Err(e) => {
+            eprintln!("mktemp: failed to create {} via template '{}': {}",
+                if directory { "directory" } else { "file" }, template.display(), e);
+            Ok(-110)
+        }
+    }
+}
+
+const TEMP_ATTEMPTS: usize = 100;
+
+fn temp_dir() -> PathBuf {
+    std::env::var_os("TMPDIR")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .unwrap_or_else(|| PathBuf::from("/tmp"))
+}
+
+// Creates a new 0600 file from a template ending in a run of X's
+fn create_temp_file(template: &Path) -> Result<(PathBuf, fs::File)> {
+    create_unique(template, |path| {
+        fs::OpenOptions::new().write(true).create_new(true).mode(0o600).open(path)
+    })
+}
+
+// Creates a new 0700 directory from a template ending in a run of X's
+fn create_temp_dir(template: &Path) -> Result<PathBuf> {
+    create_unique(template, |path| fs::DirBuilder::new().mode(0o700).create(path)).map(|(path, _)| path)
+}
+
+// Replaces the trailing X's of the template with random characters until
+// `create` succeeds. `create` must fail with AlreadyExists rather than reuse
+// an existing path, which is what makes the creation race-free.
+fn create_unique<T>(template: &Path, create: impl Fn(&Path) -> std::io::Result<T>) -> Result<(PathBuf, T)> {
+    let name = template
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .ok_or_else(|| anyhow!("invalid template"))?;
+    let prefix = name.trim_end_matches('X');
+    let random_len = name.len() - prefix.len();
+    if random_len < 3 {
+        return Err(anyhow!("too few X's in template '{}'", name));
+    }
+
+    for _ in 0..TEMP_ATTEMPTS {
+        let candidate = template.with_file_name(format!(
+            "{}{}",
+            prefix,
+            Alphanumeric.sample_string(&mut rand::rng(), random_len)
+        ));
+        match create(&candidate) {
+            Ok(created) => return Ok((candidate, created)),
+            Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => continue,
+            Err(e) => return Err(e.into()),
+        }
+    }
+    Err(anyhow!("no unused name found after {} attempts", TEMP_ATTEMPTS))
+}
+
+fn handle_shuf(args: &[String]) -> Result<i32> {
+    let mut count: Option<usize> = None;
+    let mut output: Option<&String> = None;
+    let mut seed: Option<u64> = None;
+    let mut input: Option<&String> = None;
+
+    let mut i = 0;
+    while i < args.len() {
+        let arg = args[i].as_str();
+        match arg {
+            "-n" | "--head-count" => {
+                i += 1;
+                let value = args.get(i).ok_or_else(|| anyhow!("shuf: option requires an argument -- 'n'"))?;
+                count = Some(value.parse().map_err(|_| anyhow!("shuf: invalid line count: '{}'", value))?);
+            }
+            "-o" | "--output" => {
+                i += 1;
+                output = Some(args.get(i).ok_or_else(|| anyhow!("shuf: option requires an argument -- 'o'"))?);
+            }
+            "--seed" => {
+                i += 1;
+                let value = args.get(i).ok_or_else(|| anyhow!("shuf: option '--seed' requires an argument"))?;
+                seed = Some(parse_seed(value)?);
+            }
+            _ if arg.starts_with("--seed=") => {
+                seed = Some(parse_seed(&arg["--seed=".len()..])?);
+            }
+            _ if arg.starts_with("--random-source=") => {
+                seed = Some(seed_from_file(Path::new(&arg["--random-source=".len()..]))?);
+            }
+            _ => {
+                if input.is_some() {
+                    return Err(anyhow!("shuf: extra operand '{}'", arg));
+                }
+                input = Some(&args[i]);
+            }
+        }
+        i += 1;
+    }
+
+    let read_result = match input.map(String::as_str) {
+        None | Some("-") => io::stdin().lock().lines().collect::<io::Result<Vec<String>>>(),
+        Some(path) => fs::File::open(path)
+            .and_then(|file| io::BufReader::new(file).lines().collect::<io::Result<Vec<String>>>()),
+    };
+    let mut lines = match read_result {
+        Ok(lines) => lines,
+        Err(e) => {
+            eprintln!("shuf: {}: {}", input.map(String::as_str).unwrap_or("-"), e);
+            return Ok(-120);
+        }
+    };
+
+    let mut rng = match seed {
+        Some(seed) => StdRng::seed_from_u64(seed),
+        None => StdRng::from_os_rng(),
+    };
+    shuffle_lines(&mut lines, &mut rng);
+    lines.truncate(count.unwrap_or(lines.len()));
+
+    let mut out: Box<dyn Write> = match output {
+        Some(path) => match fs::File::create(path) {
+            Ok(file) => Box::new(io::BufWriter::new(file)),
+            Err(e) => {
+                eprintln!("shuf: {}: {}", path, e);
+                return Ok(-120);
+            }
+        },
+        None => Box::new(io::stdout().lock()),
+    };
+    for line in &lines {
+        writeln!(out, "{}", line)?;
+    }
+    out.flush()?;
+    Ok(0)
+}
+
+fn parse_seed(value: &str) -> Result<u64> {
+    value.parse().map_err(|_| anyhow!("shuf: invalid seed: '{}'", value))
+}
+
+// Uses the first 8 bytes of the file as the seed, like a fixed random source
+fn seed_from_file(path: &Path) -> Result<u64> {
+    let mut bytes = [0u8; 8];
+    fs::File::open(path)
+        .and_then(|mut file| file.read_exact(&mut bytes))
+        .map_err(|e| anyhow!("shuf: {}: {}", path.display(), e))?;
+    Ok(u64::from_le_bytes(bytes))
+}
+
+// Fisher-Yates: walk from the end, swapping each slot with a random earlier one
+fn shuffle_lines(lines: &mut [String], rng: &mut impl Rng) {
+    for i in (1..lines.len()).rev() {
+        let j = rng.random_range(0..=i);
+        lines.swap(i, j);
+    }
+}
+
+fn handle_date(args: &[String]) -> Result<i32> {
+    let mut utc = false;
+    let mut format = "%a %b %e %H:%M:%S %Z %Y";
+    let mut date: Option<&str> = None;
+
+    let mut i = 0;
+    while i < args.len() {
+        let arg = &args[i];
+        i += 1;
+        match arg.as_str() {
+            "-u" | "--utc" | "--universal" => utc = true,
+            "-d" | "--date" => {
+                date = Some(
+                    args.get(i)
+                        .ok_or_else(|| anyhow!("date: option '{}' requires an argument", arg))?,
+                );
+                i += 1;
+            }
+            _ if arg.starts_with("--date=") => date = Some(&arg["--date=".len()..]),
+            _ if arg.starts_with('+') => format = &arg[1..],
+            _ => return Err(anyhow!("date: invalid date '{}'", arg)),
+        }
+    }
+
+    let time = match date {
+        Some(text) => match timestamp::parse_date(text) {
+            Some(time) => time,
+            None => {
+                eprintln!("date: invalid date '{}'", text);
+                return Ok(-130);
+            }
+        },
+        None => chrono::Utc::now(),
+    };
+    match format_time(&time, format, utc) {
+        Ok(text) => {
+            println!("{}", text);
+            Ok(0)
+        }
+        Err(e) => {
+            eprintln!("date: {}", e);
+            Ok(-130)
+        }
+    }
+}
+
+// strftime-style formatting in UTC or local time. chrono panics when
+// displaying an invalid format, so the format is written fallibly instead.
+fn format_time(time: &chrono::DateTime<chrono::Utc>, format: &str, utc: bool) -> Result<String> {
+    use std::fmt::Write as _;
+
+    let mut text = String::new();
+    let written = if utc {
+        write!(text, "{}", time.format(format))
+    } else {
+        write!(text, "{}", time.with_timezone(&chrono::Local).format(format))
+    };
+    written.map_err(|_| anyhow!("invalid format '{}'", format))?;
+    Ok(text)
+}
+
+fn handle_stat(args: &[String]) -> Result<i32> {
+    let mut format: Option<&String> = None;
+    let mut file_system = false;
+    let mut json = false;
+    let mut files = Vec::new();
+
+    let mut i = 0;
+    while i < args.len() {
+        match args[i].as_str() {
+            "-c" | "--format" => {
+                i += 1;
+                format = Some(args.get(i).ok_or_else(|| anyhow!("stat: option requires an argument -- 'c'"))?);
+            }
+            "-f" | "--file-system" => file_system = true,
+            "--json" => json = true,
+            _ => files.push(&args[i]),
+        }
+        i += 1;
+    }
+
+    if files.is_empty() {
+        return Err(anyhow!("stat: missing operand"));
+    }
+    if json && (file_system || format.is_some()) {
+        return Err(anyhow!("stat: --json cannot be combined with --format or --file-system"));
+    }
+
+    let mut encountered_error = false;
+    let mut records = Vec::new();
+    for file in files {
+        if file_system {
+            match fs_stat(file) {
+                Ok(stats) => match format {
+                    Some(format) => println!("{}", fs_stat_format(format, file, &stats)),
+                    None => print_fs_stat(file, &stats),
+                },
+                Err(e) => {
+                    eprintln!("stat: cannot read file system information for '{}': {}", file, e);
+                    encountered_error = true;
+                }
+            }
+            continue;
+        }
+        let metadata = match fs::symlink_metadata(file) {
+            Ok(metadata) => metadata,
+            Err(e) => {
+                eprintln!("stat: cannot stat '{}': {}", file, e);
+                encountered_error = true;
+                continue;
+            }
+        };
+        match format {
+            _ if json => records.push(FileRecord::new(file, Path::new(file), &metadata)),
+            Some(format) => println!("{}", stat_format(format, file, &metadata)),
+            None => print_stat(file, &metadata),
+        }
+    }
+    if json {
+        print_json(&records, &mut io::stdout().lock())?;
+    }
+
+    if encountered_error {
+        Ok(-140)
+    } else {
+        Ok(0)
+    }
+}
+
+/// One file in the `--json` output of `ls` and `stat`. The field names and
+/// their meaning are the stable output format: new fields may be added, but
+/// existing ones must not change.
+#[derive(Debug, serde::Serialize)]
+struct FileRecord {
+    /// The name as it would be displayed: the operand, or the entry name
+    /// inside a listed directory
+    name: String,
+    path: String,
+    /// One of `file`, `directory`, `symlink`, `fifo`, `socket`,
+    /// `char_device`, `block_device`
+    #[serde(rename = "type")]
+    kind: &'static str,
+    size: u64,
+    /// Permission bits, including setuid/setgid/sticky, in octal (`"0644"`)
+    mode: String,
+    /// Modification time in seconds since the Unix epoch
+    mtime: i64,
+    /// Where a symlink points; absent for other types
+    #[serde(skip_serializing_if = "Option::is_none")]
+    target: Option<String>,
+}
+
+impl FileRecord {
+    // Describes the file itself; symlinks are not followed
+    fn new(name: &str, path: &Path, metadata: &fs::Metadata) -> FileRecord {
+        let file_type = metadata.file_type();
+        let kind = if file_type.is_dir() {
+            "directory"
+        } else if file_type.is_symlink() {
+            "symlink"
+        } else if file_type.is_fifo() {
+            "fifo"
+        } else if file_type.is_socket() {
+            "socket"
+        } else if file_type.is_char_device() {
+            "char_device"
+        } else if file_type.is_block_device() {
+            "block_device"
+        } else {
+            "file"
+        };
+        let target = if file_type.is_symlink() {
+            fs::read_link(path).ok().map(|target| target.display().to_string())
+        } else {
+            None
+        };
+        FileRecord {
+            name: name.to_string(),
+            path: path.display().to_string(),
+            kind,
+            size: metadata.size(),
+            mode: format!("{:04o}", metadata.mode() & 0o7777),
+            mtime: metadata.mtime(),
+            target,
+        }
+    }
+}
+
+// Prints the records as one JSON array on a single line
+fn print_json(records: &[FileRecord], out: &mut dyn Write) -> Result<()> {
+    serde_json::to_writer(&mut *out, records)?;
+    writeln!(out)?;
+    Ok(())
+}
+
+fn print_stat(name: &str, metadata: &fs::Metadata) {
+    let time = |secs: i64, nsecs: i64| {
+        chrono::DateTime::from_timestamp(secs, nsecs as u32)
+            .unwrap_or_default()
+            .with_timezone(&chrono::Local)
+            .format("%Y-%m-%d %H:%M:%S.%f %z")
+            .to_string()
+    };
+
+    println!("  File: {}", name);
+    println!("  Size: {:<15} Blocks: {:<10} IO Block: {:<6} {}",
+        metadata.size(), metadata.blocks(), metadata.blksize(), file_type_name(metadata));
+    println!("Device: {:<15} Inode: {:<11} Links: {}", metadata.dev(), metadata.ino(), metadata.nlink());
+    println!("Access: ({:04o}/{})  Uid: {:>5}   Gid: {:>5}",
+        metadata.mode() & 0o7777, mode_string(metadata), metadata.uid(), metadata.gid());
+    println!("Access: {}", time(metadata.atime(), metadata.atime_nsec()));
+    println!("Modify: {}", time(metadata.mtime(), metadata.mtime_nsec()));
+    println!("Change: {}", time(metadata.ctime(), metadata.ctime_nsec()));
+}
+
+fn file_type_name(metadata: &fs::Metadata) -> &'static str {
+    let file_type = metadata.file_type();
+    if file_type.is_dir() {
+        "directory"
+    } else if file_type.is_symlink() {
+        "symbolic link"
+    } else if file_type.is_fifo() {
+        "fifo"
+    } else if file_type.is_socket() {
+        "socket"
+    } else if file_type.is_char_device() {
+        "character special file"
+    } else if file_type.is_block_device() {
+        "block special file"
+    } else if metadata.size() == 0 {
+        "regular empty file"
+    } else {
+        "regular file"
+    }
+}
+
+// Expands `stat -c` directives; unknown ones are kept literally
+fn stat_format(format: &str, name: &str, metadata: &fs::Metadata) -> String {
+    let mut out = String::new();
+    let mut chars = format.chars();
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => out.push_str(name),
+            Some('s') => out.push_str(&metadata.size().to_string()),
+            Some('a') => out.push_str(&format!("{:o}", metadata.mode() & 0o7777)),
+            Some('A') => out.push_str(&mode_string(metadata)),
+            Some('u') => out.push_str(&metadata.uid().to_string()),
+            Some('g') => out.push_str(&metadata.gid().to_string()),
+            Some('Y') => out.push_str(&metadata.mtime().to_string()),
+            Some('i') => out.push_str(&metadata.ino().to_string()),
+            Some('%') => out.push('%'),
+            Some(other) => {
+                out.push('%');
+                out.push(other);
+            }
+            None => out.push('%'),
+        }
+    }
+    out
+}
+
+/// Filesystem-level numbers for `stat -f`; block counts are in units of
+/// the fundamental block size.
+#[derive(Debug, Clone, Copy)]
+struct FsStat {
+    block_size: u64,
+    fundamental_block_size: u64,
+    blocks: u64,
+    blocks_free: u64,
+    blocks_available: u64,
+    inodes: u64,
+    inodes_free: u64,
+    id: u64,
+    name_max: u64,
+    fs_type: u64,
+}
+
+fn fs_stat(path: &str) -> io::Result<FsStat> {
+    let c_path = std::ffi::CString::new(path)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+    // SAFETY: the path is NUL-terminated and both structs are plain data
+    // that the calls fill in
+    let mut vfs: libc::statvfs = unsafe { std::mem::zeroed() };
+    if unsafe { libc::statvfs(c_path.as_ptr(), &mut vfs) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    // statvfs has no filesystem type, so that one comes from statfs
+    let mut fs: libc::statfs = unsafe { std::mem::zeroed() };
+    if unsafe { libc::statfs(c_path.as_ptr(), &mut fs) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    Ok(FsStat {
+        block_size: vfs.f_bsize as u64,
+        fundamental_block_size: vfs.f_frsize as u64,
+        blocks: vfs.f_blocks as u64,
+        blocks_free: vfs.f_bfree as u64,
+        blocks_available: vfs.f_bavail as u64,
+        inodes: vfs.f_files as u64,
+        inodes_free: vfs.f_ffree as u64,
+        // glibc packs the two fsid words low-first; coreutils prints them
+        // high-first, so swap the halves to match its ID
+        id: (vfs.f_fsid as u64).rotate_left(32),
+        name_max: vfs.f_namemax as u64,
+        fs_type: fs.f_type as u64,
+    })
+}
+
+// The names coreutils uses for the usual magic numbers
+fn fs_type_name(magic: u64) -> String {
+    let name = match magic {
+        0xEF53 => "ext2/ext3",
+        0x0102_1994 => "tmpfs",
+        0x9123_683E => "btrfs",
+        0x5846_5342 => "xfs",
+        0x2FC1_2FC1 => "zfs",
+        0xF2F5_2010 => "f2fs",
+        0x794C_7630 => "overlayfs",
+        0x6573_5546 => "fuseblk",
+        0x6969 => "nfs",
+        0x4D44 => "msdos",
+        0x5346_544E => "ntfs",
+        0x9660 => "isofs",
+        0x7371_7368 => "squashfs",
+        0x8584_58F6 => "ramfs",
+        0x9FA0 => "proc",
+        0x6265_6572 => "sysfs",
+        0x1CD1 => "devpts",
+        0x6367_7270 => "cgroup2fs",
+        0x0102_1997 => "v9fs",
+        _ => return format!("UNKNOWN (0x{:x})", magic),
+    };
+    name.to_string()
+}
+
+fn print_fs_stat(name: &str, stats: &FsStat) {
+    println!("  File: \"{}\"", name);
+    println!("    ID: {:<8x} Namelen: {:<7} Type: {}",
+        stats.id, stats.name_max, fs_type_name(stats.fs_type));
+    println!("Block size: {:<10} Fundamental block size: {}",
+        stats.block_size, stats.fundamental_block_size);
+    println!("Blocks: Total: {:<10} Free: {:<10} Available: {}",
+        stats.blocks, stats.blocks_free, stats.blocks_available);
+    println!("Inodes: Total: {:<10} Free: {}", stats.inodes, stats.inodes_free);
+}
+
+// Expands the `stat -f -c` directives; unknown ones are kept literally
+fn fs_stat_format(format: &str, name: &str, stats: &FsStat) -> String {
+    let mut out = String::new();
+    let mut chars = format.chars();
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => out.push_str(name),
+            Some('b') => out.push_str(&stats.blocks.to_string()),
+            Some('f') => out.push_str(&stats.blocks_free.to_string()),
+            Some('a') => out.push_str(&stats.blocks_available.to_string()),
+            Some('c') => out.push_str(&stats.inodes.to_string()),
+            Some('d') => out.push_str(&stats.inodes_free.to_string()),
+            Some('i') => out.push_str(&format!("{:x}", stats.id)),
+            Some('l') => out.push_str(&stats.name_max.to_string()),
+            Some('s') => out.push_str(&stats.block_size.to_string()),
+            Some('S') => out.push_str(&stats.fundamental_block_size.to_string()),
+            Some('t') => out.push_str(&format!("{:x}", stats.fs_type)),
+            Some('T') => out.push_str(&fs_type_name(stats.fs_type)),
+            Some('%') => out.push('%'),
+            Some(other) => {
+                out.push('%');
+                out.push(other);
+            }
+            None => out.push('%'),
+        }
+    }
+    out
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+struct WcCounts {
+    lines: usize,
+    words: usize,
+    chars: usize,
+    bytes: usize,
+    max_line: usize,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct WcOptions {
+    lines: bool,
+    words: bool,
+    chars: bool,
+    bytes: bool,
+    max_line: bool,
+    /// Count a "\r\n" ending as one character (`--crlf`)
+    crlf: bool,
+}
+
+fn handle_wc(args: &[String], input: &mut dyn BufRead, out: &mut dyn Write) -> Result<i32> {
+    let mut options = WcOptions {
+        lines: false,
+        words: false,
+        chars: false,
+        bytes: false,
+        max_line: false,
+        crlf: false,
+    };
+    let mut files = Vec::new();
+    let mut files0_from: Option<&str> = None;
+    let mut jobs = 1;
+
+    let mut i = 0;
+    while i < args.len() {
+        let arg = &args[i];
+        match arg.as_str() {
+            "-l" | "--lines" => options.lines = true,
+            "-w" | "--words" => options.words = true,
+            "-m" | "--chars" => options.chars = true,
+            "-c" | "--bytes" => options.bytes = true,
+            "-L" | "--max-line-length" => options.max_line = true,
+            "--crlf" => options.crlf = true,
+            "-j" | "--jobs" => {
+                i += 1;
+                jobs = args
+                    .get(i)
+                    .and_then(|value| value.parse().ok())
+                    .filter(|jobs| *jobs > 0)
+                    .ok_or_else(|| anyhow!("wc: --jobs requires a positive number"))?;
+            }
+            _ if arg.starts_with("--files0-from=") => {
+                files0_from = Some(&arg["--files0-from=".len()..]);
+            }
+            _ => files.push(arg.clone()),
+        }
+        i += 1;
+    }
+
+    // The names come from a NUL-separated list instead of the command line
+    if let Some(list) = files0_from {
+        if !files.is_empty() {
+            return Err(anyhow!(
+                "wc: extra operand '{}'\nfile operands cannot be combined with --files0-from",
+                files[0]
+            ));
+        }
+        let mut names = Vec::new();
+        let read = if list == "-" {
+            input.read_to_end(&mut names)
+        } else {
+            fs::File::open(list).and_then(|mut file| file.read_to_end(&mut names))
+        };
+        if let Err(e) = read {
+            eprintln!("wc: cannot open '{}' for reading: {}", list, e);
+            return Ok(-150);
+        }
+        files = names
+            .split(|byte| *byte == 0)
+            .filter(|name| !name.is_empty())
+            .map(|name| String::from_utf8_lossy(name).into_owned())
+            .collect();
+        if files.is_empty() {
+            return Ok(0);
+        }
+    }
+    if !(options.lines || options.words || options.chars || options.bytes || options.max_line) {
+        options.lines = true;
+        options.words = true;
+        options.bytes = true;
+    }
+
+    let mut encountered_error = false;
+    let mut rows: Vec<(WcCounts, Option<&str>)> = Vec::new();
+    if files.is_empty() {
+        rows.push((wc_count(&mut *input, options.crlf)?, None));
+    }
+    // The input can only be read from this thread
+    let counted = if jobs > 1 && !files.iter().any(|file| file == "-") {
+        parallel_map(&files, jobs, |file| wc_count_file(file, options.crlf))
+    } else {
+        files
+            .iter()
+            .map(|file| match file.as_str() {
+                "-" => wc_count(&mut *input, options.crlf),
+                _ => wc_count_file(file, options.crlf),
+            })
+            .collect()
+    };
+    for (file, counted) in files.iter().zip(counted) {
+        let file = file.as_str();
+        match counted {
+            Ok(counts) => rows.push((counts, Some(file))),
+            Err(e) => {
+                eprintln!("wc: {}: {}", file, e);
+                encountered_error = true;
+            }
+        }
+    }
+
+    if files.len() > 1 {
+        // Every column is summed except -L, which reports the longest line overall
+        let total = rows.iter().fold(WcCounts::default(), |acc, (counts, _)| WcCounts {
+            lines: acc.lines + counts.lines,
+            words: acc.words + counts.words,
+            chars: acc.chars + counts.chars,
+            bytes: acc.bytes + counts.bytes,
+            max_line: acc.max_line.max(counts.max_line),
+        });
+        rows.push((total, Some("total")));
+    }
+
+    let columns = |counts: &WcCounts| {
+        let mut values = Vec::new();
+        if options.lines { values.push(counts.lines); }
+        if options.words { values.push(counts.words); }
+        if options.chars { values.push(counts.chars); }
+        if options.bytes { values.push(counts.bytes); }
+        if options.max_line { values.push(counts.max_line); }
+        values
+    };
+    let width = rows
+        .iter()
+        .flat_map(|(counts, _)| columns(counts))
+        .map(|value| value.to_string().len())
+        .max()
+        .unwrap_or(1);
+    for (counts, name) in &rows {
+        let numbers: Vec<String> = columns(counts).iter().map(|value| format!("{:>width$}", value)).collect();
+        match name {
+            Some(name) => writeln!(out, "{} {}", numbers.join(" "), name)?,
+            None => writeln!(out, "{}", numbers.join(" "))?,
+        }
+    }
+
+    if encountered_error {
+        Ok(-150)
+    } else {
+        Ok(0)
+    }
+}
+
+fn wc_count_file(file: &str, crlf: bool) -> io::Result<WcCounts> {
+    fs::File::open(file).and_then(|f| wc_count(io::BufReader::new(f), crlf))
+}
+
+// Calls `f` on every item on a pool of up to `jobs` threads. Results come
+// back in whatever order the workers finish, so they are sorted by index to
+// keep the order of `items`.
+fn parallel_map<T: Sync, R: Send>(items: &[T], jobs: usize, f: impl Fn(&T) -> R + Sync) -> Vec<R> {
+    let next = AtomicUsize::new(0);
+    let results = Mutex::new(Vec::with_capacity(items.len()));
+    std::thread::scope(|scope| {
+        for _ in 0..jobs.min(items.len()) {
+            scope.spawn(|| loop {
+                let index = next.fetch_add(1, Ordering::Relaxed);
+                let Some(item) = items.get(index) else {
+                    break;
+                };
+                let result = f(item);
+                let mut results = results.lock().unwrap_or_else(|poison| poison.into_inner());
+                results.push((index, result));
+            });
+        }
+    });
+
+    let mut results = results.into_inner().unwrap_or_else(|poison| poison.into_inner());
+    results.sort_by_key(|(index, _)| *index);
+    results.into_iter().map(|(_, result)| result).collect()
+}
+
+// Lines are counted by their '\n', so a last line without one is not
+// counted; with `crlf` a "\r\n" ending also counts as a single character
+fn wc_count(mut reader: impl BufRead, crlf: bool) -> io::Result<WcCounts> {
+    let mut counts = WcCounts::default();
+    let mut line = Vec::new();
+    loop {
+        line.clear();
+        if reader.read_until(b'\n', &mut line)? == 0 {
+            break;
+        }
+        counts.bytes += line.len();
+        if line.ends_with(b"\n") {
+            counts.lines += 1;
+        }
+        counts.words += line
+            .split(|b| b.is_ascii_whitespace())
+            .filter(|word| !word.is_empty())
+            .count();
+
+        if crlf {
+            strip_crlf(&mut line);
+        }
+        let text = String::from_utf8_lossy(&line);
+        counts.chars += text.chars().count();
+        counts.max_line = counts.max_line.max(display_width(text.trim_end_matches('\n')));
+    }
+    Ok(counts)
+}
+
+// Width of a line on screen, with tabs advancing to the next multiple of 8
+fn display_width(line: &str) -> usize {
+    line.chars().fold(0, |width, c| match c {
+        '\t' => (width / 8 + 1) * 8,
+        c if c.is_control() => width,
+        _ => width + 1,
+    })
+}
+
+fn handle_basename(args: &[String]) -> Result<i32> {
+    let mut multiple = false;
+    let mut suffix: Option<&str> = None;
+    let mut terminator = '\n';
+    let mut names = Vec::new();
+
+    let mut i = 0;
+    while i < args.len() {
+        match args[i].as_str() {
+            "-a" | "--multiple" => multiple = true,
+            "-z" | "--zero" => terminator = '\0',
+            "-s" | "--suffix" => {
+                i += 1;
+                suffix = Some(
+                    args.get(i)
+                        .ok_or_else(|| anyhow!("basename: option requires an argument -- 's'"))?,
+                );
+                multiple = true;
+            }
+            _ => names.push(args[i].as_str()),
+        }
+        i += 1;
+    }
+
+    if names.is_empty() {
+        return Err(anyhow!("basename: missing operand"));
+    }
+    // Without -a/-s the optional second operand is the suffix
+    if !multiple {
+        match names.len() {
+            1 => {}
+            2 => suffix = Some(names.pop().unwrap_or_default()),
+            _ => return Err(anyhow!("basename: extra operand '{}'", names[2])),
+        }
+    }
+
+    let mut out = io::stdout().lock();
+    for name in names {
+        write!(out, "{}{}", base_name(name, suffix), terminator)?;
+    }
+    Ok(0)
+}
+
+// Last path component, ignoring trailing slashes, with an optional suffix
+// removed unless it makes up the whole name
+fn base_name(path: &str, suffix: Option<&str>) -> String {
+    let trimmed = path.trim_end_matches('/');
+    if trimmed.is_empty() {
+        return if path.is_empty() { String::new() } else { "/".to_string() };
+    }
+
+    let name = trimmed.rsplit('/').next().unwrap_or(trimmed);
+    match suffix {
+        Some(suffix) if name != suffix => name.strip_suffix(suffix).unwrap_or(name).to_string(),
+        _ => name.to_string(),
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+struct DuOptions {
+    human: bool,
+    max_depth: Option<usize>,
+    sort_by_size: bool,
+    /// Threads walking each operand; a directory at any depth hands its
+    /// entries to whichever of them are idle
+    jobs: usize,
+}
+
+/// State shared by every thread walking one `du` operand.
+#[derive(Debug, Default)]
+struct DuShared {
+    /// Threads that are not walking anything and can be handed entries
+    idle: AtomicUsize,
+    failed: AtomicBool,
+}
+
+impl DuShared {
+    // Takes up to `wanted` idle threads, returning how many it got
+    fn claim(&self, wanted: usize) -> usize {
+        let idle = self.idle.fetch_update(Ordering::Relaxed, Ordering::Relaxed, |idle| {
+            Some(idle - idle.min(wanted))
+        });
+        idle.unwrap_or_else(|idle| idle).min(wanted)
+    }
+
+    fn release(&self, threads: usize) {
+        self.idle.fetch_add(threads, Ordering::Relaxed);
+    }
+}
+
+/// What one thread's walk of part of a `du` operand has found.
+#[derive(Debug, Default)]
+struct DuTally {
+    /// Reported paths with their totals, each directory after its contents
+    rows: Vec<(u64, PathBuf)>,
+    /// Hard-linked files counted so far by (device, inode), with their size
+    /// and the path they were counted under
+    linked: HashMap<(u64, u64), (u64, PathBuf)>,
+}
+
+impl DuTally {
+    // Appends what a walk of the entry `size` was returned for found, after
+    // everything already in this tally. A hard-linked file both of them
+    // counted stays with this tally and is taken back out of the entry's
+    // size and of the rows of the entry's directories above it, so the
+    // result is the same as if one thread had walked both in order.
+    fn merge(&mut self, entry: DuTally, mut size: u64) -> u64 {
+        let DuTally { mut rows, linked } = entry;
+        for (key, (bytes, file)) in linked {
+            match self.linked.entry(key) {
+                std::collections::hash_map::Entry::Vacant(slot) => {
+                    slot.insert((bytes, file));
+                }
+                std::collections::hash_map::Entry::Occupied(_) => {
+                    size -= bytes;
+                    for (total, dir) in &mut rows {
+                        if file.starts_with(dir) {
+                            *total -= bytes;
+                        }
+                    }
+                }
+            }
+        }
+        self.rows.extend(rows);
+        size
+    }
+}
+
+fn handle_du(args: &[String]) -> Result<i32> {
+    let mut options = DuOptions { jobs: 1, ..DuOptions::default() };
+    let mut follow = false;
+    let mut paths = Vec::new();
+
+    let mut i = 0;
+    while i < args.len() {
+        match args[i].as_str() {
+            "-h" | "--human-readable" => options.human = true,
+            "-s" | "--summarize" => options.max_depth = Some(0),
+            "-S" | "--sort" => options.sort_by_size = true,
+            "-L" | "--dereference" => follow = true,
+            "-P" | "--no-dereference" => follow = false,
+            "-d" | "--max-depth" => {
+                i += 1;
+                let depth = args
+                    .get(i)
+                    .ok_or_else(|| anyhow!("du: option '--max-depth' requires an argument"))?;
+                options.max_depth = Some(parse_max_depth(depth)?);
+            }
+            arg if arg.starts_with("--max-depth=") => {
+                options.max_depth = Some(parse_max_depth(&arg["--max-depth=".len()..])?);
+            }
+            "-j" | "--jobs" => {
+                i += 1;
+                options.jobs = args
+                    .get(i)
+                    .and_then(|value| value.parse().ok())
+                    .filter(|jobs| *jobs > 0)
+                    .ok_or_else(|| anyhow!("du: --jobs requires a positive number"))?;
+            }
+            _ => paths.push(PathBuf::from(&args[i])),
+        }
+        i += 1;
+    }
+
+    if paths.is_empty() {
+        paths.push(PathBuf::from("."));
+    }
+
+    let mut encountered_error = false;
+    for path in paths {
+        let mut tally = DuTally::default();
+        let shared = DuShared { idle: AtomicUsize::new(options.jobs - 1), ..DuShared::default() };
+        let mut guard = WalkGuard::new(follow);
+        let walked = du_walk(&path, 0, options, &mut tally, &shared, &mut guard);
+        encountered_error |= shared.failed.into_inner();
+        if let Err(e) = walked {
+            eprintln!("du: cannot access '{}': {}", path.display(), e);
+            encountered_error = true;
+            continue;
+        }
+        let mut rows = tally.rows;
+        if options.sort_by_size {
+            rows.sort_by_key(|(bytes, _)| std::cmp::Reverse(*bytes));
+        }
+        for (bytes, shown) in rows {
+            let size = if options.human {
+                format_size(bytes)
+            } else {
+                bytes.div_ceil(1024).to_string()
+            };
+            println!("{}\t{}", size, shown.display());
+        }
+    }
+
+    if encountered_error {
+        return Ok(-160);
+    }
+    Ok(0)
+}
+
+fn parse_max_depth(value: &str) -> Result<usize> {
+    value
+        .parse()
+        .map_err(|_| anyhow!("du: invalid maximum depth '{}'", value))
+}
+
+// Returns the disk usage of `path` and everything below it. Directories are
+// reported after their contents, but only down to --max-depth; deeper levels
+// still count towards their ancestors. Hard-linked files are counted once,
+// under the first path a serial walk would meet, and with -L a link back to
+// a directory being walked is skipped, like du.
+fn du_walk(
+    path: &Path,
+    depth: usize,
+    options: DuOptions,
+    tally: &mut DuTally,
+    shared: &DuShared,
+    guard: &mut WalkGuard,
+) -> io::Result<u64> {
+    let metadata = guard.metadata(path)?;
+    let mut total = metadata.blocks() * 512;
+    if metadata.nlink() > 1 && !metadata.is_dir() {
+        let key = (metadata.dev(), metadata.ino());
+        if tally.linked.contains_key(&key) {
+            return Ok(0);
+        }
+        tally.linked.insert(key, (total, path.to_path_buf()));
+    }
+
+    if metadata.is_dir() {
+        if guard.enter(path).is_err() {
+            return Ok(0);
+        }
+        let children: io::Result<Vec<PathBuf>> = fs::read_dir(path)
+            .and_then(|entries| entries.map(|entry| entry.map(|entry| entry.path())).collect());
+        match children {
+            Ok(mut children) => {
+                // Sorted, so that which directory a hard link is charged to
+                // does not depend on the order the file system lists them in
+                children.sort();
+                // One of the workers takes over this thread's share while it
+                // waits for them, so a single entry never needs a helper
+                let helpers = shared.claim(children.len().saturating_sub(1));
+                if helpers > 0 {
+                    let threads = helpers + 1;
+                    total += du_children_parallel(
+                        &children, depth + 1, threads, options, tally, shared, guard,
+                    );
+                    shared.release(helpers);
+                } else {
+                    for child in children {
+                        total += du_child(&child, depth + 1, options, tally, shared, guard);
+                    }
+                }
+            }
+            Err(e) => {
+                eprintln!("du: cannot read directory '{}': {}", path.display(), e);
+                shared.failed.store(true, Ordering::Relaxed);
+            }
+        }
+        guard.leave();
+    }
+
+    // Plain files are only listed when they are the operand itself
+    let reported = metadata.is_dir() || depth == 0;
+    if reported && options.max_depth.is_none_or(|max| depth <= max) {
+        tally.rows.push((total, path.to_path_buf()));
+    }
+    Ok(total)
+}
+
+// Walks one entry below an operand, reporting it and counting nothing on failure
+fn du_child(
+    child: &Path,
+    depth: usize,
+    options: DuOptions,
+    tally: &mut DuTally,
+    shared: &DuShared,
+    guard: &mut WalkGuard,
+) -> u64 {
+    du_walk(child, depth, options, tally, shared, guard).unwrap_or_else(|e| {
+        eprintln!("du: cannot access '{}': {}", child.display(), e);
+        shared.failed.store(true, Ordering::Relaxed);
+        0
+    })
+}
+
+// Sizes the entries of a directory on `threads` threads. Each entry gets a
+// tally of its own, starting from a copy of the directory's walk guard, and
+// the tallies are merged in directory order afterwards, so the output is the
+// same as a serial walk's however the threads were scheduled.
+fn du_children_parallel(
+    children: &[PathBuf],
+    depth: usize,
+    threads: usize,
+    options: DuOptions,
+    tally: &mut DuTally,
+    shared: &DuShared,
+    guard: &WalkGuard,
+) -> u64 {
+    let results = parallel_map(children, threads, |child| {
+        let mut child_tally = DuTally::default();
+        let size = du_child(child, depth, options, &mut child_tally, shared, &mut guard.clone());
+        (size, child_tally)
+    });
+    let mut total = 0;
+    for (size, child_tally) in results {
+        total += tally.merge(child_tally, size);
+    }
+    total
+}
+
+// Calls `callback` with every entry of `dir`, sorted by name, so recursive
+// commands visit trees in the same order on every run
+fn walk_sorted(
+    dir: &Path,
+    mut callback: impl FnMut(&Path) -> io::Result<()>,
+) -> io::Result<()> {
+    let mut entries = fs::read_dir(dir)?.collect::<io::Result<Vec<_>>>()?;
+    entries.sort_by_key(|entry| entry.file_name());
+    for entry in entries {
+        callback(&entry.path())?;
+    }
+    Ok(())
+}
+
+/// How a recursive walk treats symlinks it meets below its starting points:
+/// -P lists them as links, -L looks through them and descends into linked
+/// directories. With -L, the directories being walked are tracked by
+/// canonical path so that a link back to one of them is reported instead of
+/// looping forever.
+#[derive(Debug, Default, Clone)]
+struct WalkGuard {
+    follow: bool,
+    /// (canonical, as reached) for each directory from the start down to the current one
+    ancestors: Vec<(PathBuf, PathBuf)>,
+}
+
+impl WalkGuard {
+    fn new(follow: bool) -> Self {
+        WalkGuard { follow, ancestors: Vec::new() }
+    }
+
+    // A dangling link is still seen as a link under -L
+    fn metadata(&self, path: &Path) -> io::Result<fs::Metadata> {
+        if self.follow {
+            fs::metadata(path).or_else(|_| fs::symlink_metadata(path))
+        } else {
+            fs::symlink_metadata(path)
+        }
+    }
+
+    // Called before descending into `dir`, and paired with `leave` once done.
+    // If `dir` is one of the directories already being walked, returns the
+    // path that one was reached by and the walk must not descend.
+    fn enter(&mut self, dir: &Path) -> Result<(), PathBuf> {
+        if !self.follow {
+            return Ok(());
+        }
+        let canonical = fs::canonicalize(dir).unwrap_or_else(|_| dir.to_path_buf());
+        if let Some((_, reached)) = self.ancestors.iter().find(|(seen, _)| *seen == canonical) {
+            return Err(reached.clone());
+        }
+        self.ancestors.push((canonical, dir.to_path_buf()));
+        Ok(())
+    }
+
+    fn leave(&mut self) {
+        if self.follow {
+            self.ancestors.pop();
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+struct GrepOptions {
+    ignore_case: bool,
+    invert: bool,
+    line_numbers: bool,
+    count: bool,
+    files_with_matches: bool,
+    only_matching: bool,
+    before_context: usize,
+    after_context: usize,
+}
+
+fn handle_grep(args: &[String], input: &mut dyn BufRead, out: &mut dyn Write) -> Result<i32> {
+    let mut options = GrepOptions::default();
+    let mut recursive = false;
+    let mut follow = false;
+    let mut operands = Vec::new();
+    let mut i = 0;
+    while i < args.len() {
+        let arg = &args[i];
+        match arg.as_str() {
+            // -A/-B/-C take the count either attached (`-C2`) or as the next argument
+            _ if arg.len() >= 2 && ["-A", "-B", "-C"].contains(&&arg[..2]) => {
+                let flag = &arg[..2];
+                let value = if arg.len() > 2 {
+                    Some(&arg[2..])
+                } else {
+                    i += 1;
+                    args.get(i).map(String::as_str)
+                };
+                let lines = parse_context(value, flag)?;
+                if flag != "-B" {
+                    options.after_context = lines;
+                }
+                if flag != "-A" {
+                    options.before_context = lines;
+                }
+            }
+            _ if arg.starts_with("--after-context=") => {
+                options.after_context = parse_context(arg.split_once('=').map(|(_, n)| n), arg)?;
+            }
+            _ if arg.starts_with("--before-context=") => {
+                options.before_context = parse_context(arg.split_once('=').map(|(_, n)| n), arg)?;
+            }
+            _ if arg.starts_with("--context=") => {
+                let lines = parse_context(arg.split_once('=').map(|(_, n)| n), arg)?;
+                options.after_context = lines;
+                options.before_context = lines;
+            }
+            "-i" | "--ignore-case" => options.ignore_case = true,
+            "-v" | "--invert-match" => options.invert = true,
+            "-n" | "--line-number" => options.line_numbers = true,
+            "-c" | "--count" => options.count = true,
+            "-l" | "--files-with-matches" => options.files_with_matches = true,
+            "-o" | "--only-matching" => options.only_matching = true,
+            "-r" | "--recursive" => recursive = true,
+            // -L and -P already mean other things to grep, so -R follows links as in GNU grep
+            "-R" | "--dereference-recursive" => {
+                recursive = true;
+                follow = true;
+            }
+            _ => operands.push(arg.as_str()),
+        }
+        i += 1;
+    }
+
+    let Some((pattern, files)) = operands.split_first() else {
+        return Err(anyhow!("grep: missing pattern"));
+    };
+    let regex = regex::RegexBuilder::new(pattern)
+        .case_insensitive(options.ignore_case)
+        .build()
+        .map_err(|e| anyhow!("grep: invalid pattern '{}': {}", pattern, e))?;
+
+    let mut matched = false;
+    let mut encountered_error = false;
+
+    if files.is_empty() && !recursive {
+        matched |= grep_reader(&mut *input, "(standard input)", None, &regex, options, out)?;
+    }
+
+    // -r replaces each directory with the files below it; with no operands
+    // it searches the current directory, naming files without a `./`
+    let mut inputs = Vec::new();
+    let mut guard = WalkGuard::new(follow);
+    if recursive && files.is_empty() {
+        grep_collect(Path::new("."), true, &mut guard, &mut inputs, &mut encountered_error);
+    }
+    for file in files {
+        if recursive && Path::new(file).is_dir() {
+            let dir = Path::new(file);
+            grep_collect(dir, false, &mut guard, &mut inputs, &mut encountered_error);
+        } else {
+            inputs.push(file.to_string());
+        }
+    }
+
+    // The file name prefixes each line once there is more than one input
+    let show_names = inputs.len() > 1 || recursive;
+    for file in &inputs {
+        let file = file.as_str();
+        let prefix = if show_names { Some(file) } else { None };
+        let result = if file == "-" {
+            grep_reader(&mut *input, "(standard input)", prefix, &regex, options, &mut *out)
+        } else {
+            fs::File::open(file).and_then(|handle| {
+                let reader = io::BufReader::new(handle);
+                grep_reader(reader, file, prefix, &regex, options, &mut *out)
+            })
+        };
+        match result {
+            Ok(found) => matched |= found,
+            Err(e) => {
+                eprintln!("grep: {}: {}", file, e);
+                encountered_error = true;
+            }
+        }
+    }
+
+    if encountered_error {
+        return Ok(-170);
+    }
+    // Like grep, 1 means nothing was selected rather than a failure
+    Ok(if matched { 0 } else { 1 })
+}
+
+// Gathers the regular files under `dir` in sorted order. Like grep -r,
+// symlinks met on the way are skipped rather than followed, unless -R asked
+// to follow them; a link back to a directory being searched only warns.
+fn grep_collect(
+    dir: &Path,
+    implied: bool,
+    guard: &mut WalkGuard,
+    inputs: &mut Vec<String>,
+    encountered_error: &mut bool,
+) {
+    if guard.enter(dir).is_err() {
+        eprintln!("grep: {}: warning: recursive directory loop", dir.display());
+        return;
+    }
+    let walked = walk_sorted(dir, |path| {
+        let metadata = guard.metadata(path)?;
+        if metadata.is_dir() {
+            grep_collect(path, implied, guard, inputs, encountered_error);
+        } else if metadata.is_file() {
+            let shown = if implied { path.strip_prefix(".").unwrap_or(path) } else { path };
+            inputs.push(shown.display().to_string());
+        }
+        Ok(())
+    });
+    if let Err(e) = walked {
+        eprintln!("grep: {}: {}", dir.display(), e);
+        *encountered_error = true;
+    }
+    guard.leave();
+}
+
+fn parse_context(value: Option<&str>, flag: &str) -> Result<usize> {
+    let value = value.ok_or_else(|| anyhow!("grep: option '{}' requires an argument", flag))?;
+    value
+        .parse()
+        .map_err(|_| anyhow!("grep: {}: invalid context length argument", value))
+}
+
+// Prints the selected lines of one input in the requested output mode and
+// reports whether any line was selected. Context lines are kept in a small
+// window before each match and counted down after it; groups that do not
+// touch are separated by `--`.
+fn grep_reader(
+    mut reader: impl BufRead,
+    name: &str,
+    prefix: Option<&str>,
+    regex: &regex::Regex,
+    options: GrepOptions,
+    out: &mut dyn Write,
+) -> io::Result<bool> {
+    let show_context = !options.count && !options.files_with_matches && !options.only_matching;
+    let mut before = std::collections::VecDeque::new();
+    let mut after_remaining = 0;
+    let mut last_printed: Option<usize> = None;
+
+    let mut selected = 0;
+    let mut buffer = Vec::new();
+    let mut line_number = 0;
+
+    while reader.read_until(b'\n', &mut buffer)? > 0 {
+        line_number += 1;
+        let line = String::from_utf8_lossy(&buffer);
+        let line = line.strip_suffix('\n').unwrap_or(&line).to_string();
+        buffer.clear();
+
+        if regex.is_match(&line) == options.invert {
+            if show_context {
+                if after_remaining > 0 {
+                    after_remaining -= 1;
+                    grep_print(out, prefix, options, line_number, '-', &line)?;
+                    last_printed = Some(line_number);
+                } else if options.before_context > 0 {
+                    if before.len() == options.before_context {
+                        before.pop_front();
+                    }
+                    before.push_back((line_number, line));
+                }
+            }
+            continue;
+        }
+
+        selected += 1;
+        if options.files_with_matches {
+            writeln!(out, "{}", name)?;
+            return Ok(true);
+        }
+        if options.count {
+            continue;
+        }
+
+        if options.only_matching {
+            // Inverted matches have no matched text to show
+            if !options.invert {
+                for found in regex.find_iter(&line).filter(|found| !found.is_empty()) {
+                    grep_print(out, prefix, options, line_number, ':', found.as_str())?;
+                }
+            }
+            continue;
+        }
+
+        let first_shown = before.front().map_or(line_number, |(number, _)| *number);
+        if options.before_context + options.after_context > 0 {
+            if let Some(last) = last_printed {
+                if first_shown > last + 1 {
+                    writeln!(out, "--")?;
+                }
+            }
+        }
+        for (number, context) in before.drain(..) {
+            grep_print(out, prefix, options, number, '-', &context)?;
+        }
+        grep_print(out, prefix, options, line_number, ':', &line)?;
+        last_printed = Some(line_number);
+        after_remaining = options.after_context;
+    }
+
+    if options.count {
+        match prefix {
+            Some(prefix) => writeln!(out, "{}:{}", prefix, selected)?,
+            None => writeln!(out, "{}", selected)?,
+        }
+    }
+    Ok(selected > 0)
+}
+
+// Matching lines use `:` after the file name and line number, context lines `-`
+fn grep_print(
+    out: &mut dyn Write,
+    prefix: Option<&str>,
+    options: GrepOptions,
+    line_number: usize,
+    separator: char,
+    text: &str,
+) -> io::Result<()> {
+    if let Some(prefix) = prefix {
+        write!(out, "{}{}", prefix, separator)?;
+    }
+    if options.line_numbers {
+        write!(out, "{}{}", line_number, separator)?;
+    }
+    writeln!(out, "{}", text)
+}
+
+/// A `find` predicate; all of them must hold for a path to be reported.
+#[derive(Debug)]
+enum FindTest {
+    Name(String),
+    Type(char),
+    /// Age in whole days compared with N (`+N` older, `-N` newer)
+    MTime(std::cmp::Ordering, i64),
+    /// Size rounded up to the unit, compared with N
+    Size(std::cmp::Ordering, u64, u64),
+    /// Modified after the reference file, as (seconds, nanoseconds)
+    Newer(i64, i64),
+}
+
+/// What `find` does with a path once every test holds, in command-line order.
+#[derive(Debug)]
+enum FindAction {
+    Print,
+    Print0,
+    Delete,
+    /// Command and arguments, with `{}` standing for the path
+    Exec(Vec<String>),
+}
+
+#[derive(Debug, Default)]
+struct FindOptions {
+    tests: Vec<FindTest>,
+    actions: Vec<FindAction>,
+    max_depth: Option<usize>,
+    min_depth: usize,
+    dry_run: bool,
+    verbosity: Verbosity,
+    now: i64,
+}
+
+impl FindOptions {
+    // -delete has to see a directory's contents before the directory itself
+    fn depth_first(&self) -> bool {
+        self.actions.iter().any(|action| matches!(action, FindAction::Delete))
+    }
+}
+
+fn handle_find(args: &[String], dry_run: DryRun, verbosity: Verbosity) -> Result<i32> {
+    // -L and -P come before the paths, the last one wins
+    let mut follow = false;
+    let mut args = args;
+    while let Some((first, rest)) = args.split_first() {
+        match first.as_str() {
+            "-L" => follow = true,
+            "-P" => follow = false,
+            _ => break,
+        }
+        args = rest;
+    }
+
+    // Paths come first, the expression starts at the first dash argument
+    let split = args.iter().position(|arg| arg.starts_with('-')).unwrap_or(args.len());
+    let (paths, expression) = args.split_at(split);
+
+    let mut options = FindOptions {
+        dry_run: dry_run.0,
+        verbosity,
+        now: chrono::Utc::now().timestamp(),
+        ..FindOptions::default()
+    };
+    let mut i = 0;
+    while i < expression.len() {
+        let primary = expression[i].as_str();
+        let mut value = || {
+            i += 1;
+            expression
+                .get(i)
+                .map(String::as_str)
+                .ok_or_else(|| anyhow!("find: missing argument to '{}'", primary))
+        };
+        match primary {
+            "-name" => options.tests.push(FindTest::Name(value()?.to_string())),
+            "-type" => match value()? {
+                kind @ ("f" | "d" | "l") => {
+                    options.tests.push(FindTest::Type(kind.chars().next().unwrap_or('f')));
+                }
+                kind => return Err(anyhow!("find: unknown argument to -type: {}", kind)),
+            },
+            "-mtime" => {
+                let (ordering, days) = parse_find_number(value()?, "-mtime")?;
+                options.tests.push(FindTest::MTime(ordering, days as i64));
+            }
+            "-size" => {
+                let value = value()?;
+                // Units as in find: c bytes, k/M/G binary multiples, 512-byte blocks by default
+                let (number, unit) = match value.chars().last() {
+                    Some('c') => (&value[..value.len() - 1], 1),
+                    Some('k') => (&value[..value.len() - 1], 1024),
+                    Some('M') => (&value[..value.len() - 1], 1024 * 1024),
+                    Some('G') => (&value[..value.len() - 1], 1024 * 1024 * 1024),
+                    _ => (value, 512),
+                };
+                let (ordering, size) = parse_find_number(number, "-size")?;
+                options.tests.push(FindTest::Size(ordering, size, unit));
+            }
+            "-newer" => {
+                let reference = value()?;
+                let metadata = fs::metadata(reference)
+                    .map_err(|e| anyhow!("find: '{}': {}", reference, e))?;
+                options.tests.push(FindTest::Newer(metadata.mtime(), metadata.mtime_nsec()));
+            }
+            "-newermt" => {
+                let text = value()?;
+                let time = timestamp::parse_date(text).ok_or_else(|| {
+                    anyhow!(
+                        "find: I cannot figure out how to interpret '{}' as a date or time",
+                        text
+                    )
+                })?;
+                let nanos = i64::from(time.timestamp_subsec_nanos());
+                options.tests.push(FindTest::Newer(time.timestamp(), nanos));
+            }
+            "-maxdepth" => options.max_depth = Some(parse_depth(value()?)?),
+            "-mindepth" => options.min_depth = parse_depth(value()?)?,
+            "-print" => options.actions.push(FindAction::Print),
+            "-print0" | "--null" => options.actions.push(FindAction::Print0),
+            "-delete" => options.actions.push(FindAction::Delete),
+            "-exec" => {
+                let rest = &expression[i + 1..];
+                let end = rest
+                    .iter()
+                    .position(|arg| arg == ";")
+                    .ok_or_else(|| anyhow!("find: missing argument to '-exec'"))?;
+                if end == 0 {
+                    return Err(anyhow!("find: missing argument to '-exec'"));
+                }
+                options.actions.push(FindAction::Exec(rest[..end].to_vec()));
+                i += end + 1;
+            }
+            _ => return Err(anyhow!("find: unknown predicate '{}'", primary)),
+        }
+        i += 1;
+    }
+
+    // Without an explicit action every match is printed
+    if options.actions.is_empty() {
+        options.actions.push(FindAction::Print);
+    }
+
+    let default_path = [String::from(".")];
+    let paths = if paths.is_empty() { &default_path[..] } else { paths };
+
+    let mut out = io::stdout().lock();
+    let mut encountered_error = false;
+    for path in paths {
+        let mut guard = WalkGuard::new(follow);
+        find_walk(Path::new(path), 0, &options, &mut guard, &mut out, &mut encountered_error)?;
+    }
+    out.flush()?;
+
+    if encountered_error {
+        return Ok(-180);
+    }
+    Ok(0)
+}
+
+// `+N` means more than N, `-N` less than N and a bare `N` exactly N
+fn parse_find_number(value: &str, primary: &str) -> Result<(std::cmp::Ordering, u64)> {
+    let (ordering, digits) = match value.as_bytes().first() {
+        Some(b'+') => (std::cmp::Ordering::Greater, &value[1..]),
+        Some(b'-') => (std::cmp::Ordering::Less, &value[1..]),
+        _ => (std::cmp::Ordering::Equal, value),
+    };
+    let number = digits
+        .parse()
+        .map_err(|_| anyhow!("find: invalid argument '{}' to '{}'", value, primary))?;
+    Ok((ordering, number))
+}
+
+fn parse_depth(value: &str) -> Result<usize> {
+    value
+        .parse()
+        .map_err(|_| anyhow!("find: invalid depth '{}'", value))
+}
+
+// Visits `path` before its children, or after them with -delete; unreadable
+// entries are reported and skipped. With -L, links are followed everywhere,
+// starting points included, as find does.
+fn find_walk(
+    path: &Path,
+    depth: usize,
+    options: &FindOptions,
+    guard: &mut WalkGuard,
+    out: &mut impl Write,
+    encountered_error: &mut bool,
+) -> io::Result<()> {
+    let metadata = match guard.metadata(path) {
+        Ok(metadata) => metadata,
+        Err(e) => {
+            options.verbosity.error(format_args!("find: '{}': {}", path.display(), e));
+            *encountered_error = true;
+            return Ok(());
+        }
+    };
+    if metadata.is_dir() {
+        if let Err(ancestor) = guard.enter(path) {
+            options.verbosity.error(format_args!(
+                "find: File system loop detected; '{}' is part of the same file system loop \
+                 as '{}'.",
+                path.display(),
+                ancestor.display()
+            ));
+            *encountered_error = true;
+            return Ok(());
+        }
+    }
+
+    let visit = depth >= options.min_depth && find_matches(path, &metadata, options);
+    if visit && !options.depth_first() {
+        find_act(path, &metadata, options, out, encountered_error)?;
+    }
+
+    if metadata.is_dir() && options.max_depth.is_none_or(|max| depth < max) {
+        // Unreadable directories are reported and skipped, but a failed
+        // write to the output stops the whole walk
+        let mut output_error = None;
+        let walked = walk_sorted(path, |child| {
+            if output_error.is_none() {
+                output_error =
+                    find_walk(child, depth + 1, options, guard, out, encountered_error).err();
+            }
+            Ok(())
+        });
+        if let Err(e) = walked {
+            options.verbosity.error(format_args!("find: '{}': {}", path.display(), e));
+            *encountered_error = true;
+        }
+        if let Some(e) = output_error {
+            return Err(e);
+        }
+    }
+
+    if visit && options.depth_first() {
+        find_act(path, &metadata, options, out, encountered_error)?;
+    }
+    if metadata.is_dir() {
+        guard.leave();
+    }
+    Ok(())
+}
+
+fn find_matches(path: &Path, metadata: &fs::Metadata, options: &FindOptions) -> bool {
+    options.tests.iter().all(|test| match test {
+        FindTest::Name(pattern) => {
+            let name = path.file_name().unwrap_or(path.as_os_str()).to_string_lossy();
+            glob::matches(pattern, &name)
+        }
+        FindTest::Type(kind) => match kind {
+            'd' => metadata.is_dir(),
+            'l' => metadata.file_type().is_symlink(),
+            _ => metadata.is_file(),
+        },
+        FindTest::MTime(ordering, days) => {
+            let age = (options.now - metadata.mtime()).div_euclid(86_400);
+            age.cmp(days) == *ordering
+        }
+        FindTest::Size(ordering, size, unit) => {
+            metadata.len().div_ceil(*unit).cmp(size) == *ordering
+        }
+        FindTest::Newer(seconds, nanos) => {
+            (metadata.mtime(), metadata.mtime_nsec()) > (*seconds, *nanos)
+        }
+    })
+}
+
+// Runs the actions in order; like find, a failing -exec or -delete stops the
+// ones after it for this path
+fn find_act(
+    path: &Path,
+    metadata: &fs::Metadata,
+    options: &FindOptions,
+    out: &mut impl Write,
+    encountered_error: &mut bool,
+) -> io::Result<()> {
+    for action in &options.actions {
+        match action {
+            FindAction::Print | FindAction::Print0 => {
+                // Paths are written as raw bytes so -print0 keeps any file name intact
+                let terminator = if matches!(action, FindAction::Print0) { b"\0" } else { b"\n" };
+                out.write_all(path.as_os_str().as_encoded_bytes())?;
+                out.write_all(terminator)?;
+            }
+            FindAction::Delete => {
+                // The starting point `.` is never removed
+                if path == Path::new(".") {
+                    continue;
+                }
+                if options.dry_run {
+                    writeln!(out, "would remove '{}'", path.display())?;
+                    continue;
+                }
+                // remove_dir only succeeds once the traversal has emptied the directory
+                let removed = if metadata.is_dir() {
+                    fs::remove_dir(path)
+                } else {
+                    fs::remove_file(path)
+                };
+                if let Err(e) = removed {
+                    options.verbosity.error(format_args!(
+                        "find: cannot delete '{}': {}",
+                        path.display(),
+                        e
+                    ));
+                    *encountered_error = true;
+                    return Ok(());
+                }
+            }
+            FindAction::Exec(command) => {
+                let shown = path.to_string_lossy();
+                let words: Vec<String> =
+                    command.iter().map(|word| word.replace("{}", &shown)).collect();
+                out.flush()?;
+                // A dry run (or --quiet) carries over to the command being run
+                let dry_run = DryRun(options.dry_run);
+                let succeeded = match dispatch_nested(&words, dry_run, options.verbosity) {
+                    Ok(code) => code == 0,
+                    Err(e) => {
+                        options.verbosity.error(format_args!("find: {}", e));
+                        false
+                    }
+                };
+                if !succeeded {
+                    return Ok(());
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// A `-k` sort key: fields `start` through `end` (1-based, inclusive),
+/// or through the end of the line when `end` is not given.
+#[derive(Debug, Clone, Copy)]
+struct SortKey {
+    start: usize,
+    end: Option<usize>,
+}
+
+#[derive(Debug, Default)]
+struct SortOptions {
+    reverse: bool,
+    numeric: bool,
+    fold_case: bool,
+    unique: bool,
+    ignore_blanks: bool,
+    separator: Option<char>,
+    keys: Vec<SortKey>,
+}
+
+impl SortOptions {
+    // Keys are compared in order; lines that tie on every key fall back to a
+    // plain byte comparison, like coreutils without -s
+    fn compare(&self, a: &str, b: &str) -> std::cmp::Ordering {
+        let ordering = self.compare_keys(a, b).then_with(|| a.cmp(b));
+        if self.reverse { ordering.reverse() } else { ordering }
+    }
+
+    fn compare_keys(&self, a: &str, b: &str) -> std::cmp::Ordering {
+        if self.keys.is_empty() {
+            return self.compare_text(a, b);
+        }
+        self.keys
+            .iter()
+            .map(|key| self.compare_text(self.key_text(a, key), self.key_text(b, key)))
+            .find(|ordering| ordering.is_ne())
+            .unwrap_or(std::cmp::Ordering::Equal)
+    }
+
+    fn compare_text(&self, a: &str, b: &str) -> std::cmp::Ordering {
+        let (a, b) = if self.ignore_blanks { (a.trim_start(), b.trim_start()) } else { (a, b) };
+        if self.numeric {
+            numeric_prefix(a).total_cmp(&numeric_prefix(b))
+        } else if self.fold_case {
+            a.chars()
+                .map(|c| c.to_ascii_uppercase())
+                .cmp(b.chars().map(|c| c.to_ascii_uppercase()))
+        } else {
+            a.cmp(b)
+        }
+    }
+
+    // Without -t a field is a run of blanks followed by non-blanks, so the
+    // leading blanks belong to the field (which is what -b strips). The key
+    // ends before the separator or blanks that start the field after it.
+    fn key_text<'a>(&self, line: &'a str, key: &SortKey) -> &'a str {
+        let mut starts = Vec::new();
+        match self.separator {
+            Some(separator) => {
+                starts.push(0);
+                let width = separator.len_utf8();
+                starts.extend(line.match_indices(separator).map(|(index, _)| index + width));
+            }
+            None => {
+                // A new field starts at each blank that follows a non-blank
+                starts.push(0);
+                let mut previous_blank = true;
+                for (index, c) in line.char_indices() {
+                    if c.is_whitespace() && !previous_blank {
+                        starts.push(index);
+                    }
+                    previous_blank = c.is_whitespace();
+                }
+            }
+        }
+
+        let Some(&begin) = starts.get(key.start.saturating_sub(1)) else {
+            return "";
+        };
+        let end = match key.end {
+            // The key stops before the separator (or blanks) ending its last field
+            Some(end) => match (starts.get(end), self.separator) {
+                (Some(&next), Some(separator)) => next - separator.len_utf8(),
+                (Some(&next), None) => next,
+                (None, _) => line.len(),
+            },
+            None => line.len(),
+        };
+        &line[begin..end.max(begin)]
+    }
+}
+
+// The leading number of a line for -n; lines without one sort as zero
+fn numeric_prefix(text: &str) -> f64 {
+    let text = text.trim_start();
+    let mut end = 0;
+    for (index, c) in text.char_indices() {
+        let sign = index == 0 && (c == '-' || c == '+');
+        if c.is_ascii_digit() || sign || (c == '.' && !text[..index].contains('.')) {
+            end = index + c.len_utf8();
+        } else {
+            break;
+        }
+    }
+    text[..end].parse().unwrap_or(0.0)
+}
+
+fn parse_sort_key(spec: &str) -> Result<SortKey> {
+    let field = |value: &str| -> Result<usize> {
+        match value.parse::<usize>() {
+            Ok(field) if field > 0 => Ok(field),
+            _ => Err(anyhow!("sort: invalid field specification '{}'", spec)),
+        }
+    };
+    match spec.split_once(',') {
+        Some((start, end)) => Ok(SortKey { start: field(start)?, end: Some(field(end)?) }),
+        None => Ok(SortKey { start: field(spec)?, end: None }),
+    }
+}
+
+fn handle_sort(args: &[String]) -> Result<i32> {
+    let mut options = SortOptions::default();
+    let mut merge = false;
+    let mut buffer_size = SORT_BUFFER_SIZE;
+    let mut output: Option<&str> = None;
+    let mut files = Vec::new();
+
+    let mut i = 0;
+    while i < args.len() {
+        let arg = args[i].as_str();
+        let mut value = || {
+            i += 1;
+            args.get(i)
+                .map(String::as_str)
+                .ok_or_else(|| anyhow!("sort: option '{}' requires an argument", arg))
+        };
+        match arg {
+            "-r" | "--reverse" => options.reverse = true,
+            "-n" | "--numeric-sort" => options.numeric = true,
+            "-f" | "--ignore-case" => options.fold_case = true,
+            "-u" | "--unique" => options.unique = true,
+            "-m" | "--merge" => merge = true,
+            "-b" | "--ignore-leading-blanks" => options.ignore_blanks = true,
+            "-k" | "--key" => options.keys.push(parse_sort_key(value()?)?),
+            "-t" | "--field-separator" => {
+                let separator = value()?;
+                let mut chars = separator.chars();
+                match (chars.next(), chars.next()) {
+                    (Some(c), None) => options.separator = Some(c),
+                    _ => return Err(anyhow!("sort: the separator must be a single character")),
+                }
+            }
+            "-o" | "--output" => output = Some(value()?),
+            "-S" | "--buffer-size" => buffer_size = parse_buffer_size(value()?)?,
+            _ => files.push(arg),
+        }
+        i += 1;
+    }
+
+    if files.is_empty() {
+        files.push("-");
+    }
+
+    if merge {
+        if output.is_some_and(|output| files.contains(&output)) {
+            return Err(anyhow!("sort: -o cannot name one of the inputs when merging"));
+        }
+        let mut readers: Vec<Box<dyn BufRead>> = Vec::new();
+        for file in &files {
+            if *file == "-" {
+                readers.push(Box::new(io::stdin().lock()));
+                continue;
+            }
+            match fs::File::open(file) {
+                Ok(handle) => readers.push(Box::new(io::BufReader::new(handle))),
+                Err(e) => {
+                    eprintln!("sort: cannot read: {}: {}", file, e);
+                    return Ok(-190);
+                }
+            }
+        }
+        let result = match output {
+            Some(path) => fs::File::create(path)
+                .and_then(|file| merge_sorted(readers, &options, io::BufWriter::new(file))),
+            None => merge_sorted(readers, &options, io::stdout().lock()),
+        };
+        if let Err(e) = result {
+            eprintln!("sort: {}", e);
+            return Ok(-190);
+        }
+        return Ok(0);
+    }
+
+    // Lines are sorted in memory until they outgrow the buffer, after which
+    // sorted runs are spilled to temporary files and merged at the end
+    let mut runs = SortRuns::new(buffer_size);
+    let mut buffer = Vec::new();
+    for file in files {
+        let mut reader: Box<dyn BufRead> = if file == "-" {
+            Box::new(io::stdin().lock())
+        } else {
+            match fs::File::open(file) {
+                Ok(handle) => Box::new(io::BufReader::new(handle)),
+                Err(e) => {
+                    eprintln!("sort: cannot read: {}: {}", file, e);
+                    return Ok(-190);
+                }
+            }
+        };
+        loop {
+            match next_line(&mut reader, &mut buffer) {
+                Ok(Some(line)) => runs.push(line, &options)?,
+                Ok(None) => break,
+                Err(e) => {
+                    eprintln!("sort: cannot read: {}: {}", file, e);
+                    return Ok(-190);
+                }
+            }
+        }
+    }
+
+    if !runs.paths.is_empty() {
+        runs.spill(&options)?;
+    }
+
+    // The output is only opened once everything is read, so `-o` may name an input
+    let result = if runs.paths.is_empty() {
+        let mut lines = std::mem::take(&mut runs.lines);
+        lines.sort_by(|a, b| options.compare(a, b));
+        if options.unique {
+            lines.dedup_by(|b, a| options.compare_keys(a, b).is_eq());
+        }
+        match output {
+            Some(path) => fs::File::create(path)
+                .and_then(|file| write_lines(io::BufWriter::new(file), &lines)),
+            None => write_lines(io::stdout().lock(), &lines),
+        }
+    } else {
+        runs.paths
+            .iter()
+            .map(|path| fs::File::open(path).map(io::BufReader::new))
+            .collect::<io::Result<Vec<_>>>()
+            .and_then(|readers| match output {
+                Some(path) => fs::File::create(path)
+                    .and_then(|file| merge_sorted(readers, &options, io::BufWriter::new(file))),
+                None => merge_sorted(readers, &options, io::stdout().lock()),
+            })
+    };
+    if let Err(e) = result {
+        eprintln!("sort: write failed: {}", e);
+        return Ok(-190);
+    }
+    Ok(0)
+}
+
+/// Default amount of line data `sort` keeps in memory before spilling runs.
+const SORT_BUFFER_SIZE: usize = 64 * 1024 * 1024;
+
+// Sizes for -S: a number of KiB, or bytes/KiB/MiB/GiB with a b/K/M/G suffix
+fn parse_buffer_size(value: &str) -> Result<usize> {
+    let (number, unit) = match value.chars().last() {
+        Some('b') => (&value[..value.len() - 1], 1),
+        Some('K' | 'k') => (&value[..value.len() - 1], 1024),
+        Some('M' | 'm') => (&value[..value.len() - 1], 1024 * 1024),
+        Some('G' | 'g') => (&value[..value.len() - 1], 1024 * 1024 * 1024),
+        _ => (value, 1024),
+    };
+    number
+        .parse::<usize>()
+        .ok()
+        .and_then(|number| number.checked_mul(unit))
+        .filter(|size| *size > 0)
+        .ok_or_else(|| anyhow!("sort: invalid buffer size '{}'", value))
+}
+
+/// Lines waiting to be sorted, plus the sorted runs already written out.
+/// The run files are removed when this is dropped.
+struct SortRuns {
+    buffer_size: usize,
+    lines: Vec<String>,
+    bytes: usize,
+    paths: Vec<PathBuf>,
+}
+
+impl SortRuns {
+    fn new(buffer_size: usize) -> Self {
+        SortRuns { buffer_size, lines: Vec::new(), bytes: 0, paths: Vec::new() }
+    }
+
+    fn push(&mut self, line: String, options: &SortOptions) -> Result<()> {
+        self.bytes += line.len() + 1;
+        self.lines.push(line);
+        if self.bytes >= self.buffer_size {
+            self.spill(options)?;
+        }
+        Ok(())
+    }
+
+    // Sorts the buffered lines into a new run file
+    fn spill(&mut self, options: &SortOptions) -> Result<()> {
+        self.lines.sort_by(|a, b| options.compare(a, b));
+        let (path, file) = create_temp_file(&temp_dir().join("rustybox-sort.XXXXXXXXXX"))?;
+        self.paths.push(path.clone());
+        write_lines(io::BufWriter::new(file), &self.lines)
+            .map_err(|e| anyhow!("sort: cannot write '{}': {}", path.display(), e))?;
+        self.lines.clear();
+        self.bytes = 0;
+        Ok(())
+    }
+}
+
+impl Drop for SortRuns {
+    fn drop(&mut self) {
+        for path in &self.paths {
+            let _ = fs::remove_file(path);
+        }
+    }
+}
+
+// One line without its newline, or None at the end of the input
+fn next_line(reader: &mut impl BufRead, buffer: &mut Vec<u8>) -> io::Result<Option<String>> {
+    buffer.clear();
+    if reader.read_until(b'\n', buffer)? == 0 {
+        return Ok(None);
+    }
+    if buffer.last() == Some(&b'\n') {
+        buffer.pop();
+    }
+    Ok(Some(String::from_utf8_lossy(buffer).into_owned()))
+}
+
+/// The next unmerged line of one input, ordered so that the smallest line
+/// (by the sort options) sits on top of the max-heap.
+struct MergeHead<'a> {
+    line: String,
+    source: usize,
+    options: &'a SortOptions,
+}
+
+impl Ord for MergeHead<'_> {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        // Equal lines leave in input order so the merge stays stable
+        self.options
+            .compare(&self.line, &other.line)
+            .then(self.source.cmp(&other.source))
+            .reverse()
+    }
+}
+
+impl PartialOrd for MergeHead<'_> {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for MergeHead<'_> {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other).is_eq()
+    }
+}
+
+impl Eq for MergeHead<'_> {}
+
+// k-way merge of already sorted inputs: only the current line of each input
+// is held in memory and output is written as soon as it is known
+fn merge_sorted<R: BufRead>(
+    mut readers: Vec<R>,
+    options: &SortOptions,
+    mut out: impl Write,
+) -> io::Result<()> {
+    let mut buffer = Vec::new();
+    let mut heap = std::collections::BinaryHeap::new();
+    for (source, reader) in readers.iter_mut().enumerate() {
+        if let Some(line) = next_line(reader, &mut buffer)? {
+            heap.push(MergeHead { line, source, options });
+        }
+    }
+
+    let mut last: Option<String> = None;
+    while let Some(MergeHead { line, source, .. }) = heap.pop() {
+        if let Some(next) = next_line(&mut readers[source], &mut buffer)? {
+            heap.push(MergeHead { line: next, source, options });
+        }
+        let duplicate = last.as_ref().is_some_and(|last| options.compare_keys(last, &line).is_eq());
+        if options.unique && duplicate {
+            continue;
+        }
+        writeln!(out, "{}", line)?;
+        last = Some(line);
+    }
+    out.flush()
+}
+
+fn write_lines(mut out: impl Write, lines: &[String]) -> io::Result<()> {
+    for line in lines {
+        writeln!(out, "{}", line)?;
+    }
+    out.flush()
+}
+
+// tr works on bytes rather than chars: sets, ranges and -c complements are
+// all taken over 0..=255, so multi-byte UTF-8 characters are not single units
+fn handle_tr(args: &[String]) -> Result<i32> {
+    let mut complement = false;
+    let mut delete = false;
+    let mut squeeze = false;
+    let mut sets = Vec::new();
+    for arg in args {
+        match arg.as_str() {
+            "--complement" => complement = true,
+            "--delete" => delete = true,
+            "--squeeze-repeats" => squeeze = true,
+            // Short flags are usually grouped, as in `tr -cd`
+            flags if flags.len() > 1
+                && flags.starts_with('-')
+                && flags[1..].chars().all(|c| matches!(c, 'c' | 'C' | 'd' | 's')) =>
+            {
+                for flag in flags[1..].chars() {
+                    match flag {
+                        'd' => delete = true,
+                        's' => squeeze = true,
+                        _ => complement = true,
+                    }
+                }
+            }
+            _ => sets.push(arg.as_str()),
+        }
+    }
+
+    let (set1, set2) = match (sets.as_slice(), delete, squeeze) {
+        ([set1], true, _) | ([set1], false, true) => (*set1, None),
+        ([set1, set2], false, _) | ([set1, set2], true, true) => (*set1, Some(*set2)),
+        ([], _, _) => return Err(anyhow!("tr: missing operand")),
+        _ => return Err(anyhow!("tr: extra operand or missing operand")),
+    };
+
+    let mut set1 = tr_parse_set(set1)?;
+    if complement {
+        set1 = (0..=255u8).filter(|byte| !set1.contains(byte)).collect();
+    }
+    let set2 = set2.map(tr_parse_set).transpose()?;
+
+    let mut in_set1 = [false; 256];
+    for &byte in &set1 {
+        in_set1[byte as usize] = true;
+    }
+
+    // Translation pads SET2 with its last byte, like coreutils
+    let mut map: [u8; 256] = std::array::from_fn(|byte| byte as u8);
+    if let (false, Some(set2)) = (delete, &set2) {
+        let Some(&last) = set2.last() else {
+            return Err(anyhow!("tr: when not truncating set1, string2 must be non-empty"));
+        };
+        for (index, &byte) in set1.iter().enumerate() {
+            map[byte as usize] = set2.get(index).copied().unwrap_or(last);
+        }
+    }
+
+    // -s squeezes the last set given: SET2 when there is one, SET1 otherwise
+    let mut squeeze_set = [false; 256];
+    if squeeze {
+        for &byte in set2.as_ref().unwrap_or(&set1) {
+            squeeze_set[byte as usize] = true;
+        }
+    }
+
+    let mut input = io::stdin().lock();
+    let mut out = io::BufWriter::new(io::stdout().lock());
+    let mut chunk = [0u8; 64 * 1024];
+    let mut previous: Option<u8> = None;
+    loop {
+        let read = match input.read(&mut chunk) {
+            Ok(0) => break,
+            Ok(read) => read,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => {
+                eprintln!("tr: read error: {}", e);
+                return Ok(-200);
+            }
+        };
+        let mut translated = Vec::with_capacity(read);
+        for &byte in &chunk[..read] {
+            if delete && in_set1[byte as usize] {
+                continue;
+            }
+            let byte = map[byte as usize];
+            if squeeze_set[byte as usize] && previous == Some(byte) {
+                continue;
+            }
+            translated.push(byte);
+            previous = Some(byte);
+        }
+        out.write_all(&translated)?;
+    }
+    out.flush()?;
+    Ok(0)
+}
+
+fn tr_class(name: &str) -> Option<fn(&u8) -> bool> {
+    let test: fn(&u8) -> bool = match name {
+        "alnum" => u8::is_ascii_alphanumeric,
+        "alpha" => u8::is_ascii_alphabetic,
+        "blank" => |byte| *byte == b' ' || *byte == b'\t',
+        "cntrl" => u8::is_ascii_control,
+        "digit" => u8::is_ascii_digit,
+        "graph" => u8::is_ascii_graphic,
+        "lower" => u8::is_ascii_lowercase,
+        "print" => |byte| byte.is_ascii_graphic() || *byte == b' ',
+        "punct" => u8::is_ascii_punctuation,
+        "space" => |byte| byte.is_ascii_whitespace() || *byte == 0x0b,
+        "upper" => u8::is_ascii_uppercase,
+        "xdigit" => u8::is_ascii_hexdigit,
+        _ => return None,
+    };
+    Some(test)
+}
+
+// Expands a tr set: backslash escapes, `a-z` ranges and `[:class:]` names
+fn tr_parse_set(spec: &str) -> Result<Vec<u8>> {
+    let bytes = spec.as_bytes();
+    let mut set = Vec::new();
+    let mut i = 0;
+    // Reads one possibly escaped byte starting at `i`
+    let literal = |i: &mut usize| -> u8 {
+        let byte = bytes[*i];
+        *i += 1;
+        if byte != b'\\' || *i >= bytes.len() {
+            return byte;
+        }
+        let escaped = bytes[*i];
+        *i += 1;
+        match escaped {
+            b'n' => b'\n',
+            b't' => b'\t',
+            b'r' => b'\r',
+            b'a' => 0x07,
+            b'b' => 0x08,
+            b'f' => 0x0c,
+            b'v' => 0x0b,
+            b'0'..=b'7' => {
+                let mut value = u32::from(escaped - b'0');
+                for _ in 0..2 {
+                    match bytes.get(*i) {
+                        Some(digit @ b'0'..=b'7') if value < 0o40 => {
+                            value = value * 8 + u32::from(digit - b'0');
+                            *i += 1;
+                        }
+                        _ => break,
+                    }
+                }
+                value as u8
+            }
+            other => other,
+        }
+    };
+
+    while i < bytes.len() {
+        if bytes[i..].starts_with(b"[:") {
+            if let Some(end) = spec[i + 2..].find(":]") {
+                let name = &spec[i + 2..i + 2 + end];
+                let test = tr_class(name)
+                    .ok_or_else(|| anyhow!("tr: invalid character class '{}'", name))?;
+                set.extend((0..=255u8).filter(test));
+                i += end + 4;
+                continue;
+            }
+        }
+
+        let start = literal(&mut i);
+        if bytes.get(i) == Some(&b'-') && i + 1 < bytes.len() {
+            i += 1;
+            let end = literal(&mut i);
+            if end < start {
+                return Err(anyhow!(
+                    "tr: range-endpoints of '{}-{}' are in reverse collating sequence order",
+                    start as char,
+                    end as char
+                ));
+            }
+            set.extend(start..=end);
+        } else {
+            set.push(start);
+        }
+    }
+    Ok(set)
+}
+
+fn handle_printf(args: &[String]) -> Result<i32> {
+    let Some((format, mut arguments)) = args.split_first() else {
+        return Err(anyhow!("printf: missing operand"));
+    };
+
+    // The format is reused until every argument has been consumed
+    let mut output = Vec::new();
+    let mut invalid = false;
+    loop {
+        let consumed = printf_format(format.as_bytes(), arguments, &mut output, &mut invalid);
+        arguments = &arguments[consumed..];
+        if consumed == 0 || arguments.is_empty() {
+            break;
+        }
+    }
+
+    let mut out = io::stdout().lock();
+    out.write_all(&output)?;
+    out.flush()?;
+    if invalid {
+        return Ok(-210);
+    }
+    Ok(0)
+}
+
+// Expands one pass of the format into `output` and returns how many
+// arguments it used; missing arguments read as empty strings or zero
+fn printf_format(
+    format: &[u8],
+    arguments: &[String],
+    output: &mut Vec<u8>,
+    invalid: &mut bool,
+) -> usize {
+    let mut used = 0;
+    let mut i = 0;
+    while i < format.len() {
+        match format[i] {
+            b'\\' => {
+                i += 1;
+                i += printf_escape(&format[i..], output);
+            }
+            b'%' if format.get(i + 1) == Some(&b'%') => {
+                output.push(b'%');
+                i += 2;
+            }
+            b'%' => {
+                // %[flags][width][.precision]conversion
+                let start = i;
+                i += 1;
+                let flags_start = i;
+                while i < format.len() && b"-0+ #".contains(&format[i]) {
+                    i += 1;
+                }
+                let flags = &format[flags_start..i];
+                let width = printf_digits(format, &mut i);
+                let mut precision = None;
+                if format.get(i) == Some(&b'.') {
+                    i += 1;
+                    precision = Some(printf_digits(format, &mut i));
+                }
+                let Some(&conversion) = format.get(i) else {
+                    output.extend_from_slice(&format[start..]);
+                    break;
+                };
+                i += 1;
+
+                let argument = arguments.get(used).map(String::as_str);
+                let text = match conversion {
+                    b's' => {
+                        let text = argument.unwrap_or("");
+                        match precision {
+                            Some(precision) => text.chars().take(precision).collect(),
+                            None => text.to_string(),
+                        }
+                    }
+                    b'c' => argument
+                        .and_then(|text| text.chars().next())
+                        .map(String::from)
+                        .unwrap_or_default(),
+                    b'd' | b'i' | b'x' | b'X' | b'o' => {
+                        let value = printf_number(argument.unwrap_or("0"), invalid);
+                        let digits = match conversion {
+                            b'x' => format!("{:x}", value),
+                            b'X' => format!("{:X}", value),
+                            b'o' => format!("{:o}", value),
+                            _ => value.unsigned_abs().to_string(),
+                        };
+                        let sign = if value < 0 && matches!(conversion, b'd' | b'i') {
+                            "-"
+                        } else if flags.contains(&b'+') && matches!(conversion, b'd' | b'i') {
+                            "+"
+                        } else {
+                            ""
+                        };
+                        let digits = match precision {
+                            Some(precision) if digits.len() < precision => {
+                                format!("{}{}", "0".repeat(precision - digits.len()), digits)
+                            }
+                            _ => digits,
+                        };
+                        // Zero padding goes between the sign and the digits
+                        if flags.contains(&b'0') && !flags.contains(&b'-') && precision.is_none() {
+                            let padding = width.saturating_sub(sign.len() + digits.len());
+                            format!("{}{}{}", sign, "0".repeat(padding), digits)
+                        } else {
+                            format!("{}{}", sign, digits)
+                        }
+                    }
+                    _ => {
+                        let spec = String::from_utf8_lossy(&format[start..i]);
+                        eprintln!("printf: {}: invalid conversion specification", spec);
+                        *invalid = true;
+                        output.extend_from_slice(&format[start..i]);
+                        continue;
+                    }
+                };
+                used += 1;
+
+                let padding = width.saturating_sub(text.chars().count());
+                if flags.contains(&b'-') {
+                    output.extend_from_slice(text.as_bytes());
+                    output.extend(std::iter::repeat_n(b' ', padding));
+                } else {
+                    output.extend(std::iter::repeat_n(b' ', padding));
+                    output.extend_from_slice(text.as_bytes());
+                }
+            }
+            byte => {
+                output.push(byte);
+                i += 1;
+            }
+        }
+    }
+    used.min(arguments.len())
+}
+
+// Reads a run of decimal digits (a width or precision), zero when there is none
+fn printf_digits(format: &[u8], i: &mut usize) -> usize {
+    let start = *i;
+    while *i < format.len() && format[*i].is_ascii_digit() {
+        *i += 1;
+    }
+    std::str::from_utf8(&format[start..*i])
+        .ok()
+        .and_then(|digits| digits.parse().ok())
+        .unwrap_or(0)
+}
+
+// Writes the byte for the escape sequence after a backslash and returns how
+// many bytes of `rest` it took
+fn printf_escape(rest: &[u8], output: &mut Vec<u8>) -> usize {
+    let Some(&escaped) = rest.first() else {
+        output.push(b'\\');
+        return 0;
+    };
+    let simple = match escaped {
+        b'n' => Some(b'\n'),
+        b't' => Some(b'\t'),
+        b'r' => Some(b'\r'),
+        b'a' => Some(0x07),
+        b'b' => Some(0x08),
+        b'f' => Some(0x0c),
+        b'v' => Some(0x0b),
+        b'\\' => Some(b'\\'),
+        b'"' => Some(b'"'),
+        _ => None,
+    };
+    if let Some(byte) = simple {
+        output.push(byte);
+        return 1;
+    }
+
+    // \NNN takes up to three octal digits, \xHH up to two hex digits
+    let (radix, digits_start, max_digits) = match escaped {
+        b'0'..=b'7' => (8, 0, 3),
+        b'x' => (16, 1, 2),
+        _ => {
+            output.extend_from_slice(&[b'\\', escaped]);
+            return 1;
+        }
+    };
+    let digits: Vec<u8> = rest[digits_start..]
+        .iter()
+        .take(max_digits)
+        .take_while(|byte| (**byte as char).is_digit(radix))
+        .copied()
+        .collect();
+    if digits.is_empty() {
+        output.extend_from_slice(&[b'\\', escaped]);
+        return 1;
+    }
+    let value = digits
+        .iter()
+        .fold(0u32, |value, digit| value * radix + (*digit as char).to_digit(radix).unwrap_or(0));
+    output.push(value as u8);
+    digits_start + digits.len()
+}
+
+// Integer arguments may be decimal, 0x hex, 0 octal, or 'c for a character code
+fn printf_number(text: &str, invalid: &mut bool) -> i64 {
+    let trimmed = text.trim();
+    if let Some(quoted) = trimmed.strip_prefix('\'').or_else(|| trimmed.strip_prefix('"')) {
+        return quoted.chars().next().map_or(0, |c| c as i64);
+    }
+    let (negative, digits) = match trimmed.strip_prefix('-') {
+        Some(digits) => (true, digits),
+        None => (false, trimmed.strip_prefix('+').unwrap_or(trimmed)),
+    };
+    let hex = digits.strip_prefix("0x").or_else(|| digits.strip_prefix("0X"));
+    let parsed = if let Some(hex) = hex {
+        i64::from_str_radix(hex, 16)
+    } else if digits.len() > 1 && digits.starts_with('0') {
+        i64::from_str_radix(&digits[1..], 8)
+    } else {
+        digits.parse()
+    };
+    match parsed {
+        Ok(value) if negative => -value,
+        Ok(value) => value,
+        Err(_) => {
+            eprintln!("printf: '{}': expected a numeric value", text);
+            *invalid = true;
+            0
+        }
+    }
+}
+
+fn handle_xargs(args: &[String], dry_run: DryRun, verbosity: Verbosity) -> Result<i32> {
+    let mut null = false;
+    let mut max_args: Option<usize> = None;
+    let mut replace: Option<&str> = None;
+
+    let mut i = 0;
+    while i < args.len() {
+        let arg = args[i].as_str();
+        match arg {
+            "-0" | "--null" => null = true,
+            "-n" | "--max-args" => {
+                i += 1;
+                max_args = Some(
+                    args.get(i)
+                        .and_then(|value| value.parse().ok())
+                        .filter(|count| *count > 0)
+                        .ok_or_else(|| anyhow!("xargs: -n requires a positive number"))?,
+                );
+            }
+            "-I" => {
+                i += 1;
+                let placeholder = args
+                    .get(i)
+                    .ok_or_else(|| anyhow!("xargs: option requires an argument -- 'I'"))?;
+                replace = Some(placeholder);
+            }
+            // The command and its own arguments start at the first operand
+            _ => break,
+        }
+        i += 1;
+    }
+
+    let echo = [String::from("echo")];
+    let command = if i < args.len() { &args[i..] } else { &echo[..] };
+
+    let mut input = Vec::new();
+    io::stdin().lock().read_to_end(&mut input)?;
+    let input = String::from_utf8_lossy(&input);
+    // -I takes whole lines, -0 NUL-terminated items, otherwise blank-separated words
+    let items: Vec<&str> = if null {
+        input.split('\0').filter(|item| !item.is_empty()).collect()
+    } else if replace.is_some() {
+        input.lines().map(str::trim_start).filter(|line| !line.is_empty()).collect()
+    } else {
+        input.split_whitespace().collect()
+    };
+
+    let invocations: Vec<Vec<String>> = match replace {
+        Some(placeholder) => items
+            .iter()
+            .map(|item| command.iter().map(|word| word.replace(placeholder, item)).collect())
+            .collect(),
+        None => {
+            let batch = max_args.unwrap_or(items.len().max(1));
+            let mut batches: Vec<Vec<String>> = items
+                .chunks(batch)
+                .map(|chunk| {
+                    let items = chunk.iter().map(|item| item.to_string());
+                    command.iter().cloned().chain(items).collect()
+                })
+                .collect();
+            // Like xargs, the command still runs once when there is no input
+            if batches.is_empty() {
+                batches.push(command.to_vec());
+            }
+            batches
+        }
+    };
+
+    let mut failed = false;
+    for words in invocations {
+        // A dry run (or --quiet) carries over to the command being run
+        match dispatch_nested(&words, dry_run, verbosity) {
+            Ok(0) => {}
+            Ok(_) => failed = true,
+            Err(e) => {
+                eprintln!("xargs: {}", e);
+                failed = true;
+            }
+        }
+    }
+
+    if failed {
+        return Ok(-220);
+    }
+    Ok(0)
+}
+
+/// How much of each input `head` or `tail` prints, in lines or bytes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Span {
+    /// The first N units (`head -n N`)
+    First(u64),
+    /// Everything except the last N units (`head -n -N`)
+    AllButLast(u64),
+    /// The last N units (`tail -n N`)
+    Last(u64),
+    /// Everything from unit N on, counting from 1 (`tail -n +N`)
+    From(u64),
+}
+
+fn handle_head(args: &[String]) -> Result<i32> {
+    head_tail("head", args, -230)
+}
+
+fn handle_tail(args: &[String]) -> Result<i32> {
+    head_tail("tail", args, -240)
+}
+
+// Shared argument handling: `-n`/`-c` take N, `-N` (head) or `+N` (tail) for
+// the other direction, and `-5` is short for `-n 5`
+fn head_tail(name: &str, args: &[String], error_code: i32) -> Result<i32> {
+    let parse = |value: &str| -> Result<Span> {
+        let invalid = || anyhow!("{}: invalid number: '{}'", name, value);
+        let (sign, digits) = match value.as_bytes().first() {
+            Some(b'+' | b'-') => (Some(&value[..1]), &value[1..]),
+            _ => (None, value),
+        };
+        let count: u64 = digits.parse().map_err(|_| invalid())?;
+        Ok(match (name, sign) {
+            ("head", Some("-")) => Span::AllButLast(count),
+            ("head", _) => Span::First(count),
+            (_, Some("+")) => Span::From(count),
+            _ => Span::Last(count),
+        })
+    };
+
+    let mut span = parse("10")?;
+    let mut bytes = false;
+    let mut crlf = false;
+    let mut files = Vec::new();
+    let mut i = 0;
+    while i < args.len() {
+        let arg = args[i].as_str();
+        match arg {
+            "-n" | "--lines" | "-c" | "--bytes" => {
+                i += 1;
+                let value = args
+                    .get(i)
+                    .ok_or_else(|| anyhow!("{}: option '{}' requires an argument", name, arg))?;
+                span = parse(value)?;
+                bytes = arg == "-c" || arg == "--bytes";
+            }
+            "--crlf" => crlf = true,
+            _ if arg.len() > 1
+                && arg.starts_with('-')
+                && arg[1..].bytes().all(|b| b.is_ascii_digit()) =>
+            {
+                span = parse(&arg[1..])?;
+                bytes = false;
+            }
+            _ => files.push(arg),
+        }
+        i += 1;
+    }
+    if files.is_empty() {
+        files.push("-");
+    }
+
+    let mut out = io::stdout().lock();
+    let mut encountered_error = false;
+    let show_headers = files.len() > 1;
+    for (index, file) in files.iter().enumerate() {
+        if show_headers {
+            let shown = if *file == "-" { "standard input" } else { file };
+            let separator = if index == 0 { "" } else { "\n" };
+            writeln!(out, "{}==> {} <==", separator, shown)?;
+        }
+        let result = if *file == "-" {
+            write_span(io::stdin().lock(), span, bytes, crlf, &mut out)
+        } else {
+            fs::File::open(file).and_then(|handle| {
+                write_span(io::BufReader::new(handle), span, bytes, crlf, &mut out)
+            })
+        };
+        if let Err(e) = result {
+            eprintln!("{}: cannot read '{}': {}", name, file, e);
+            encountered_error = true;
+        }
+    }
+    out.flush()?;
+
+    if encountered_error {
+        return Ok(error_code);
+    }
+    Ok(0)
+}
+
+// Copies the requested span of lines (including their newlines) or bytes.
+// Only the trailing spans need to hold back up to N units while streaming.
+// `crlf` only affects lines, which are then written with plain '\n' endings.
+fn write_span(
+    mut reader: impl BufRead,
+    span: Span,
+    bytes: bool,
+    crlf: bool,
+    out: &mut impl Write,
+) -> io::Result<()> {
+    if bytes {
+        return match span {
+            Span::First(count) => io::copy(&mut reader.take(count), out).map(|_| ()),
+            Span::From(start) => {
+                io::copy(&mut reader.by_ref().take(start.saturating_sub(1)), &mut io::sink())?;
+                io::copy(&mut reader, out).map(|_| ())
+            }
+            Span::AllButLast(count) | Span::Last(count) => {
+                let mut content = Vec::new();
+                reader.read_to_end(&mut content)?;
+                let split = content.len().saturating_sub(count as usize);
+                match span {
+                    Span::Last(_) => out.write_all(&content[split..]),
+                    _ => out.write_all(&content[..split]),
+                }
+            }
+        };
+    }
+
+    let mut line = Vec::new();
+    let mut held = std::collections::VecDeque::new();
+    let mut number: u64 = 0;
+    while reader.read_until(b'\n', &mut line)? > 0 {
+        number += 1;
+        if crlf {
+            strip_crlf(&mut line);
+        }
+        match span {
+            Span::First(count) if number > count => break,
+            Span::First(_) => out.write_all(&line)?,
+            Span::From(start) if number >= start => out.write_all(&line)?,
+            Span::From(_) => {}
+            Span::AllButLast(count) => {
+                held.push_back(std::mem::take(&mut line));
+                if held.len() as u64 > count {
+                    if let Some(ready) = held.pop_front() {
+                        out.write_all(&ready)?;
+                    }
+                }
+            }
+            Span::Last(count) => {
+                held.push_back(std::mem::take(&mut line));
+                if held.len() as u64 > count {
+                    held.pop_front();
+                }
+            }
+        }
+        line.clear();
+    }
+    if let Span::Last(_) = span {
+        for line in held {
+            out.write_all(&line)?;
+        }
+    }
+    Ok(())
+}
+
+fn handle_watch(args: &[String], dry_run: DryRun, verbosity: Verbosity) -> Result<i32> {
+    let mut interval = 2.0;
+    let mut i = 0;
+    while i < args.len() {
+        match args[i].as_str() {
+            "-n" | "--interval" => {
+                i += 1;
+                interval = args
+                    .get(i)
+                    .and_then(|value| value.parse::<f64>().ok())
+                    .filter(|seconds| seconds.is_finite() && *seconds > 0.0)
+                    .ok_or_else(|| anyhow!("watch: -n requires a positive number of seconds"))?;
+            }
+            _ => break,
+        }
+        i += 1;
+    }
+
+    let command = &args[i..];
+    if command.is_empty() {
+        return Err(anyhow!("watch: missing command"));
+    }
+    // Like watch, intervals below a tenth of a second are rounded up
+    let interval = std::time::Duration::from_secs_f64(interval.max(0.1));
+    let shown = command.join(" ");
+
+    // Runs until the process is interrupted
+    loop {
+        {
+            let mut out = io::stdout().lock();
+            // Clear the screen and move the cursor home
+            write!(out, "\x1b[2J\x1b[H")?;
+            let now = chrono::Local::now().format("%a %b %e %H:%M:%S %Y");
+            writeln!(out, "Every {:.1}s: {}    {}\n", interval.as_secs_f64(), shown, now)?;
+            out.flush()?;
+        }
+
+        if let Err(e) = dispatch_nested(command, dry_run, verbosity) {
+            eprintln!("watch: {}", e);
+        }
+        io::stdout().flush()?;
+        std::thread::sleep(interval);
+    }
+}
+
+/// Record counts reported by `dd` once the copy stops.
+#[derive(Debug, Default, Clone, Copy)]
+struct DdStats {
+    full_in: u64,
+    partial_in: u64,
+    full_out: u64,
+    partial_out: u64,
+    bytes: u64,
+}
+
+fn handle_dd(args: &[String], dry_run: DryRun) -> Result<i32> {
+    let mut input: Option<&str> = None;
+    let mut output: Option<&str> = None;
+    let mut block_size = 512;
+    let mut count: Option<u64> = None;
+    let mut skip = 0;
+    let mut seek = 0;
+    let mut notrunc = false;
+
+    // Operands are key=value pairs rather than flags
+    for arg in args {
+        let Some((key, value)) = arg.split_once('=') else {
+            return Err(anyhow!("dd: unrecognized operand '{}'", arg));
+        };
+        match key {
+            "if" => input = Some(value),
+            "of" => output = Some(value),
+            "bs" => {
+                block_size = dd_number(value)?;
+                if block_size == 0 {
+                    return Err(anyhow!("dd: invalid number: '{}'", value));
+                }
+            }
+            "count" => count = Some(dd_number(value)?),
+            "skip" => skip = dd_number(value)?,
+            "seek" => seek = dd_number(value)?,
+            "conv" => {
+                for conversion in value.split(',') {
+                    match conversion {
+                        "notrunc" => notrunc = true,
+                        _ => return Err(anyhow!("dd: invalid conversion: '{}'", conversion)),
+                    }
+                }
+            }
+            _ => return Err(anyhow!("dd: unrecognized operand '{}'", arg)),
+        }
+    }
+    let skip_bytes = skip
+        .checked_mul(block_size)
+        .ok_or_else(|| anyhow!("dd: skip offset is too large"))?;
+    let seek_bytes = seek
+        .checked_mul(block_size)
+        .ok_or_else(|| anyhow!("dd: seek offset is too large"))?;
+    if seek_bytes > 0 && output.is_none() {
+        return Err(anyhow!("dd: cannot seek on standard output"));
+    }
+
+    if dry_run.0 {
+        println!(
+            "would copy '{}' -> '{}' in {}-byte blocks",
+            input.unwrap_or("-"),
+            output.unwrap_or("-"),
+            block_size
+        );
+        return Ok(0);
+    }
+
+    let reader: Box<dyn Read> = match input {
+        Some(path) => {
+            let opened = fs::File::open(path)
+                .and_then(|mut file| file.seek(SeekFrom::Start(skip_bytes)).map(|_| file));
+            match opened {
+                Ok(file) => Box::new(file),
+                Err(e) => {
+                    eprintln!("dd: failed to open '{}': {}", path, e);
+                    return Ok(-250);
+                }
+            }
+        }
+        None => {
+            // stdin may be a pipe, so skipped blocks are read and dropped
+            let mut stdin = io::stdin().lock();
+            if let Err(e) = io::copy(&mut (&mut stdin).take(skip_bytes), &mut io::sink()) {
+                eprintln!("dd: standard input: cannot skip: {}", e);
+                return Ok(-250);
+            }
+            Box::new(stdin)
+        }
+    };
+    let writer: Box<dyn Write> = match output {
+        Some(path) => {
+            // Without conv=notrunc the file is cut off where the copy starts
+            let opened = fs::OpenOptions::new()
+                .write(true)
+                .create(true)
+                .truncate(false)
+                .open(path)
+                .and_then(|mut file| {
+                    if !notrunc {
+                        file.set_len(seek_bytes)?;
+                    }
+                    file.seek(SeekFrom::Start(seek_bytes))?;
+                    Ok(file)
+                });
+            match opened {
+                Ok(file) => Box::new(file),
+                Err(e) => {
+                    eprintln!("dd: failed to open '{}': {}", path, e);
+                    return Ok(-250);
+                }
+            }
+        }
+        None => Box::new(io::stdout().lock()),
+    };
+
+    let started = std::time::Instant::now();
+    let mut stats = DdStats::default();
+    let copied = dd_copy(reader, writer, block_size as usize, count, &mut stats);
+
+    // The summary is printed even when the copy failed part way
+    if let Err(e) = &copied {
+        eprintln!("dd: {}", e);
+    }
+    let seconds = started.elapsed().as_secs_f64().max(0.000001);
+    eprintln!("{}+{} records in", stats.full_in, stats.partial_in);
+    eprintln!("{}+{} records out", stats.full_out, stats.partial_out);
+    eprintln!(
+        "{} bytes copied, {:.6} s, {}/s",
+        stats.bytes,
+        seconds,
+        format_size((stats.bytes as f64 / seconds) as u64)
+    );
+
+    if copied.is_err() {
+        Ok(-250)
+    } else {
+        Ok(0)
+    }
+}
+
+// Copies one read per record, so a short read (end of file, or a pipe that
+// had less ready) becomes a partial record, as in dd
+fn dd_copy(
+    mut reader: impl Read,
+    mut writer: impl Write,
+    block_size: usize,
+    count: Option<u64>,
+    stats: &mut DdStats,
+) -> io::Result<()> {
+    let mut block = vec![0u8; block_size];
+    while count.is_none_or(|count| stats.full_in + stats.partial_in < count) {
+        let read = match reader.read(&mut block) {
+            Ok(0) => break,
+            Ok(read) => read,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        };
+        let full = read == block_size;
+        if full {
+            stats.full_in += 1;
+        } else {
+            stats.partial_in += 1;
+        }
+        writer.write_all(&block[..read])?;
+        if full {
+            stats.full_out += 1;
+        } else {
+            stats.partial_out += 1;
+        }
+        stats.bytes += read as u64;
+    }
+    writer.flush()
+}
+
+// Sizes take the dd suffixes: c (1), w (2), b (512), kB/MB/GB (powers of
+// 1000) and K/M/G (powers of 1024)
+fn dd_number(value: &str) -> Result<u64> {
+    let units: [(&str, u64); 10] = [
+        ("kB", 1000),
+        ("MB", 1000 * 1000),
+        ("GB", 1000 * 1000 * 1000),
+        ("c", 1),
+        ("w", 2),
+        ("b", 512),
+        ("K", 1024),
+        ("k", 1024),
+        ("M", 1024 * 1024),
+        ("G", 1024 * 1024 * 1024),
+    ];
+    let (number, unit) = units
+        .iter()
+        .find_map(|(suffix, unit)| value.strip_suffix(suffix).map(|number| (number, *unit)))
+        .unwrap_or((value, 1));
+    number
+        .parse::<u64>()
+        .ok()
+        .and_then(|number| number.checked_mul(unit))
+        .ok_or_else(|| anyhow!("dd: invalid number: '{}'", value))
+}
+
+fn handle_time(args: &[String], dry_run: DryRun, verbosity: Verbosity) -> Result<i32> {
+    if args.is_empty() {
+        return Err(anyhow!("time: missing command"));
+    }
+
+    let started = std::time::Instant::now();
+    let (user_before, system_before) = cpu_times();
+    let result = dispatch_nested(args, dry_run, verbosity);
+    let real = started.elapsed();
+    let (user_after, system_after) = cpu_times();
+
+    // Flush first so the report comes after the command's own output
+    io::stdout().flush()?;
+    let show = |duration: std::time::Duration| {
+        let seconds = duration.as_secs_f64();
+        format!("{}m{:.3}s", (seconds / 60.0).floor(), seconds % 60.0)
+    };
+    eprintln!();
+    eprintln!("real\t{}", show(real));
+    eprintln!("user\t{}", show(user_after.saturating_sub(user_before)));
+    eprintln!("sys\t{}", show(system_after.saturating_sub(system_before)));
+
+    // The inner command's exit code (or error) passes through untouched
+    result
+}
+
+// CPU time used so far as (user, system), counting this process's threads
+// and any children it has waited for
+fn cpu_times() -> (std::time::Duration, std::time::Duration) {
+    let to_duration = |time: libc::timeval| {
+        std::time::Duration::from_secs(time.tv_sec as u64)
+            + std::time::Duration::from_micros(time.tv_usec as u64)
+    };
+    let mut user = std::time::Duration::ZERO;
+    let mut system = std::time::Duration::ZERO;
+    for who in [libc::RUSAGE_SELF, libc::RUSAGE_CHILDREN] {
+        // SAFETY: rusage is plain data that getrusage fills in
+        let mut usage: libc::rusage = unsafe { std::mem::zeroed() };
+        if unsafe { libc::getrusage(who, &mut usage) } == 0 {
+            user += to_duration(usage.ru_utime);
+            system += to_duration(usage.ru_stime);
+        }
+    }
+    (user, system)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(list: &[&str]) -> Vec<String> {
+        list.iter().map(|s| s.to_string()).collect()
+    }
+
+    fn path_arg(path: &Path) -> String {
+        path.to_string_lossy().into_owned()
+    }
+
+    #[test]
+    fn cp_recursive_keeps_symlinks() {
+        let tmp = tempfile::tempdir().unwrap();
+        let src = tmp.path().join("src");
+        fs::create_dir(&src).unwrap();
+        fs::write(src.join("data.txt"), "data").unwrap();
+        symlink("data.txt", src.join("link")).unwrap();
+
+        let dst = tmp.path().join("dst");
+        let cp_args = args(&["-r", &path_arg(&src), &path_arg(&dst)]);
+        let code = handle_cp(&cp_args, DryRun(false), Verbosity::Normal).unwrap();
+
+        assert_eq!(code, 0);
+        let copied = dst.join("link");
+        assert!(fs::symlink_metadata(&copied).unwrap().file_type().is_symlink());
+        assert_eq!(fs::read_link(&copied).unwrap(), PathBuf::from("data.txt"));
+        assert_eq!(fs::read_to_string(&copied).unwrap(), "data");
+    }
+
+    #[test]
+    fn ln_links_several_targets_into_a_directory() {
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = tmp.path().join("dir");
+        fs::create_dir(&dir).unwrap();
+        let names = ["one", "two", "three"];
+        let mut ln_args = Vec::new();
+        for name in names {
+            fs::write(tmp.path().join(name), name).unwrap();
+            ln_args.push(path_arg(&tmp.path().join(name)));
+        }
+        ln_args.push(path_arg(&dir));
+
+        assert_eq!(handle_ln(&ln_args).unwrap(), 0);
+        for name in names {
+            let original = fs::metadata(tmp.path().join(name)).unwrap();
+            let link = fs::metadata(dir.join(name)).unwrap();
+            assert_eq!(original.ino(), link.ino());
+        }
+
+        // Several targets need a directory to put the links in
+        let file_args = args(&[&ln_args[0], &ln_args[1], &ln_args[2]]);
+        assert_eq!(handle_ln(&file_args).unwrap(), -50);
+    }
+
+    #[test]
+    fn cp_recursive_refuses_to_copy_a_directory_into_itself() {
+        let tmp = tempfile::tempdir().unwrap();
+        let src = tmp.path().join("src");
+        fs::create_dir_all(src.join("sub")).unwrap();
+        fs::write(src.join("data.txt"), "data").unwrap();
+
+        for dst in [src.join("sub"), src.clone(), src.join("sub/../sub/copy")] {
+            let cp_args = args(&["-r", &path_arg(&src), &path_arg(&dst)]);
+            let code = handle_cp(&cp_args, DryRun(false), Verbosity::Quiet).unwrap();
+            assert_eq!(code, -90);
+        }
+        let entries = |dir: &Path| fs::read_dir(dir).unwrap().count();
+        assert_eq!(entries(&src), 2);
+        assert_eq!(entries(&src.join("sub")), 0);
+    }
+
+    #[test]
+    fn find_follows_symlinks_but_stops_at_a_loop() {
+        let tmp = tempfile::tempdir().unwrap();
+        let top = tmp.path().join("top");
+        fs::create_dir_all(top.join("sub")).unwrap();
+        fs::write(top.join("sub/data.txt"), "data").unwrap();
+        symlink("..", top.join("sub/up")).unwrap();
+        symlink("sub", top.join("alias")).unwrap();
+
+        let options = FindOptions { actions: vec![FindAction::Print], ..FindOptions::default() };
+        let walk_from = |start: &Path, follow: bool| {
+            let mut out = Vec::new();
+            let mut encountered_error = false;
+            let mut guard = WalkGuard::new(follow);
+            find_walk(start, 0, &options, &mut guard, &mut out, &mut encountered_error).unwrap();
+            let prefix = format!("{}/", top.display());
+            let found: Vec<String> = String::from_utf8(out)
+                .unwrap()
+                .lines()
+                .filter_map(|line| line.strip_prefix(&prefix).map(str::to_string))
+                .collect();
+            (found, encountered_error)
+        };
+        let walk = |follow: bool| walk_from(&top, follow);
+
+        let (found, encountered_error) = walk(false);
+        assert_eq!(found, ["alias", "sub", "sub/data.txt", "sub/up"]);
+        assert!(!encountered_error);
+
+        // Both ways to reach up lead back to the starting point, so they are
+        // reported rather than walked
+        let (found, encountered_error) = walk(true);
+        assert_eq!(found, ["alias", "alias/data.txt", "sub", "sub/data.txt"]);
+        assert!(encountered_error);
+
+        // A link given as the starting point is followed too, but only with -L
+        let (found, _) = walk_from(&top.join("alias"), false);
+        assert_eq!(found, ["alias"]);
+        let (found, encountered_error) = walk_from(&top.join("alias"), true);
+        assert_eq!(found, ["alias", "alias/data.txt", "alias/up"]);
+        assert!(encountered_error);
+    }
+
+    #[test]
+    fn cp_sparse_leaves_holes_for_runs_of_zeros() {
+        let tmp = tempfile::tempdir().unwrap();
+        let source = tmp.path().join("image");
+        let mut data = vec![0u8; 4 * 1024 * 1024];
+        data[1024 * 1024..1024 * 1024 + 5].copy_from_slice(b"hello");
+        fs::write(&source, &data).unwrap();
+
+        let copy = |sparse: &str, name: &str| {
+            let target = tmp.path().join(name);
+            let cp_args = args(&[sparse, &path_arg(&source), &path_arg(&target)]);
+            assert_eq!(handle_cp(&cp_args, DryRun(false), Verbosity::Normal).unwrap(), 0);
+            assert_eq!(fs::read(&target).unwrap(), data);
+            fs::metadata(&target).unwrap().blocks() * 512
+        };
+        // The zeros, including the trailing run, become holes
+        assert!(copy("--sparse=always", "always") < 1024 * 1024);
+        assert!(copy("--sparse=never", "never") >= data.len() as u64);
+    }
+
+    #[test]
+    fn cp_preserve_all_keeps_hard_links_and_times() {
+        let tmp = tempfile::tempdir().unwrap();
+        let source = tmp.path().join("src");
+        fs::create_dir_all(source.join("sub")).unwrap();
+        fs::write(source.join("a"), "shared").unwrap();
+        fs::hard_link(source.join("a"), source.join("sub/b")).unwrap();
+        fs::write(source.join("c"), "alone").unwrap();
+        let old = FileTime::from_unix_time(1_000_000_000, 0);
+        for file in ["a", "c", "sub"] {
+            set_file_times(source.join(file), old, old).unwrap();
+        }
+        fs::set_permissions(source.join("sub"), fs::Permissions::from_mode(0o750)).unwrap();
+
+        for (name, jobs) in [("serial", "1"), ("parallel", "4")] {
+            let target = tmp.path().join(name);
+            let cp_args =
+                args(&["-r", "-j", jobs, "--preserve=all", &path_arg(&source), &path_arg(&target)]);
+            assert_eq!(handle_cp(&cp_args, DryRun(false), Verbosity::Normal).unwrap(), 0);
+
+            let a = fs::metadata(target.join("a")).unwrap();
+            let b = fs::metadata(target.join("sub/b")).unwrap();
+            let c = fs::metadata(target.join("c")).unwrap();
+            assert_eq!(a.ino(), b.ino(), "{}", name);
+            assert_eq!(a.nlink(), 2, "{}", name);
+            assert_eq!(c.nlink(), 1, "{}", name);
+            assert_eq!(fs::read_to_string(target.join("sub/b")).unwrap(), "shared");
+
+            let sub = fs::metadata(target.join("sub")).unwrap();
+            assert_eq!(sub.mode() & 0o777, 0o750, "{}", name);
+            for metadata in [&a, &c, &sub] {
+                assert_eq!(FileTime::from_last_modification_time(metadata), old, "{}", name);
+            }
+        }
+
+        // Without links the copies are separate files
+        let target = tmp.path().join("plain");
+        let cp_args = args(&["-r", "-p", &path_arg(&source), &path_arg(&target)]);
+        assert_eq!(handle_cp(&cp_args, DryRun(false), Verbosity::Normal).unwrap(), 0);
+        assert_eq!(fs::metadata(target.join("a")).unwrap().nlink(), 1);
+
+        // -p adds to the attributes already chosen instead of replacing them
+        let target = tmp.path().join("combined");
+        let cp_args =
+            args(&["-r", "--preserve=links", "-p", &path_arg(&source), &path_arg(&target)]);
+        assert_eq!(handle_cp(&cp_args, DryRun(false), Verbosity::Normal).unwrap(), 0);
+        let a = fs::metadata(target.join("a")).unwrap();
+        assert_eq!(a.nlink(), 2);
+        assert_eq!(FileTime::from_last_modification_time(&a), old);
+        assert!(handle_cp(&args(&["--preserve=bogus", "x", "y"]), DryRun(false), Verbosity::Normal)
+            .is_err());
+    }
+
+    #[test]
+    fn du_jobs_charges_shared_hard_links_to_the_first_directory() {
+        let tmp = tempfile::tempdir().unwrap();
+        let top = tmp.path().join("top");
+        for dir in ["a", "b", "c/deep"] {
+            fs::create_dir_all(top.join(dir)).unwrap();
+        }
+        fs::write(top.join("a/big"), vec![1u8; 256 * 1024]).unwrap();
+        fs::hard_link(top.join("a/big"), top.join("b/big")).unwrap();
+        fs::hard_link(top.join("a/big"), top.join("c/deep/big")).unwrap();
+        let big = fs::metadata(top.join("a/big")).unwrap().blocks() * 512;
+
+        let du = |path: &Path, jobs: usize| {
+            let options = DuOptions { jobs, ..DuOptions::default() };
+            let mut tally = DuTally::default();
+            let shared = DuShared { idle: AtomicUsize::new(jobs - 1), ..DuShared::default() };
+            du_walk(path, 0, options, &mut tally, &shared, &mut WalkGuard::new(false)).unwrap();
+            tally.rows
+        };
+        let serial = du(tmp.path(), 1);
+        let size = |dir: &str| serial.iter().find(|(_, path)| *path == top.join(dir)).unwrap().0;
+        assert!(size("a") >= big);
+        assert!(size("b") < big);
+        assert!(size("c") < big);
+
+        // The operand has a single entry, so the threads are only put to use
+        // further down; either way every run has to match the serial walk
+        for _ in 0..20 {
+            assert_eq!(du(tmp.path(), 4), serial);
+            assert_eq!(du(&top, 4), serial[..serial.len() - 1]);
+        }
+    }
+
+    #[test]
+    fn unknown_commands_map_to_command_not_found() {
+        let error = dispatch_command("no-such-command", &[]).unwrap_err();
+        assert_eq!(error_exit_code(&error), COMMAND_NOT_FOUND);
+        let error = dispatch_command("--dry-run", &args(&["no-such-command"])).unwrap_err();
+        assert_eq!(error_exit_code(&error), COMMAND_NOT_FOUND);
+
+        // Other errors keep the generic code
+        let error = dispatch_command("pwd", &args(&["extra"])).unwrap_err();
+        assert_eq!(error_exit_code(&error), -1);
+    }
+
+    #[test]
+    fn echo_pwd_and_cat_write_to_the_given_sink() {
+        let mut out = Vec::new();
+        assert_eq!(handle_echo(&args(&["-n", "a", "b"]), &mut out).unwrap(), 0);
+        assert_eq!(out, b"a b");
+
+        let mut out = Vec::new();
+        assert_eq!(handle_pwd(&[], &mut out).unwrap(), 0);
+        let cwd = std::env::current_dir().unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), format!("{}\n", cwd.display()));
+
+        // `-` is the input stream, the other operands are files
+        let tmp = tempfile::tempdir().unwrap();
+        let file = tmp.path().join("file");
+        fs::write(&file, "one\ntwo\n").unwrap();
+        let mut input: &[u8] = b"piped\n";
+        let mut out = Vec::new();
+        let cat_args = args(&["-n", &path_arg(&file), "-"]);
+        assert_eq!(handle_cat(&cat_args, &mut input, &mut out).unwrap(), 0);
+        assert_eq!(out, b"     1\tone\n     2\ttwo\n     3\tpiped\n");
+    }
+
+    #[test]
+    fn ls_writes_listings_to_the_given_sink() {
+        let tmp = tempfile::tempdir().unwrap();
+        fs::create_dir(tmp.path().join("sub")).unwrap();
+        fs::write(tmp.path().join("b.txt"), "").unwrap();
+        fs::write(tmp.path().join("sub/a.txt"), "").unwrap();
+        let root = path_arg(tmp.path());
+
+        let mut out = Vec::new();
+        assert_eq!(handle_ls(&args(&[&root]), Verbosity::Normal, &mut out, false).unwrap(), 0);
+        assert_eq!(out, b"b.txt\nsub\n");
+
+        let mut out = Vec::new();
+        let status = handle_ls(&args(&["-R", &root]), Verbosity::Normal, &mut out, false);
+        assert_eq!(status.unwrap(), 0);
+        let expected = format!("{root}:\nb.txt\nsub\n\n{root}/sub:\na.txt\n");
+        assert_eq!(String::from_utf8(out).unwrap(), expected);
+    }
+
+    #[test]
+    fn ls_json_skips_symlink_loops_and_keeps_listing() {
+        let tmp = tempfile::tempdir().unwrap();
+        fs::create_dir(tmp.path().join("sub")).unwrap();
+        fs::write(tmp.path().join("sub/a.txt"), "").unwrap();
+        symlink("..", tmp.path().join("sub/up")).unwrap();
+        let root = path_arg(tmp.path());
+
+        let mut out = Vec::new();
+        let ls_args = args(&["-R", "-L", "--json", &root]);
+        let status = handle_ls(&ls_args, Verbosity::Quiet, &mut out, false);
+        assert_eq!(status.unwrap(), -80);
+        let records: serde_json::Value = serde_json::from_slice(&out).unwrap();
+        let names: Vec<&str> = records
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|record| record["name"].as_str().unwrap())
+            .collect();
+        assert_eq!(names, ["sub", "a.txt", "up"]);
+    }
+
+    #[test]
+    fn ls_all_lists_dot_entries_and_almost_all_does_not() {
+        let tmp = tempfile::tempdir().unwrap();
+        fs::write(tmp.path().join(".hidden"), "").unwrap();
+        fs::write(tmp.path().join("shown"), "").unwrap();
+        let root = path_arg(tmp.path());
+
+        let list = |flag: &str| {
+            let mut out = Vec::new();
+            let status = handle_ls(&args(&[flag, &root]), Verbosity::Normal, &mut out, false);
+            assert_eq!(status.unwrap(), 0);
+            String::from_utf8(out).unwrap()
+        };
+        assert_eq!(list("-a"), ".\n..\n.hidden\nshown\n");
+        assert_eq!(list("-A"), ".hidden\nshown\n");
+    }
+
+    #[test]
+    fn mkdir_parents_mode_only_applies_to_the_last_level() {
+        let tmp = tempfile::tempdir().unwrap();
+        let leaf = tmp.path().join("a/b/c");
+        let mkdir_args = args(&["-p", "-m", "1750", &path_arg(&leaf)]);
+        assert_eq!(handle_mkdir(&mkdir_args, DryRun(false)).unwrap(), 0);
+
+        let mode = |path: &Path| fs::metadata(path).unwrap().permissions().mode() & 0o7777;
+        assert_eq!(mode(&leaf), 0o1750);
+        // The parents follow the umask, but can always be descended into
+        for parent in [tmp.path().join("a"), tmp.path().join("a/b")] {
+            assert_eq!(mode(&parent) & 0o7000, 0);
+            assert_eq!(mode(&parent) & 0o300, 0o300);
+        }
+    }
+
+    fn chmod_mode(path: &Path, mode: &str) -> u32 {
+        let chmod_args = args(&[mode, &path_arg(path)]);
+        let code = handle_chmod(&chmod_args, DryRun(false), Verbosity::Normal).unwrap();
+        assert_eq!(code, 0);
+        fs::metadata(path).unwrap().permissions().mode() & 0o7777
+    }
+
+    #[test]
+    fn chmod_numeric_sets_special_bits() {
+        let tmp = tempfile::tempdir().unwrap();
+        let file = tmp.path().join("file");
+        fs::write(&file, "").unwrap();
+
+        assert_eq!(chmod_mode(&file, "4755"), 0o4755);
+        assert_eq!(chmod_mode(&file, "2755"), 0o2755);
+        assert_eq!(chmod_mode(&file, "1777"), 0o1777);
+        assert_eq!(chmod_mode(&file, "0644"), 0o644);
+        let chmod_args = args(&["17777", &path_arg(&file)]);
+        assert!(handle_chmod(&chmod_args, DryRun(false), Verbosity::Normal).is_err());
+    }
+
+    #[test]
+    fn chmod_symbolic_sets_special_bits() {
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = tmp.path().join("shared");
+        fs::create_dir(&dir).unwrap();
+        fs::set_permissions(&dir, fs::Permissions::from_mode(0o777)).unwrap();
+
+        assert_eq!(chmod_mode(&dir, "+t"), 0o1777);
+        assert_eq!(chmod_mode(&dir, "g+s"), 0o3777);
+        assert_eq!(chmod_mode(&dir, "u+s"), 0o7777);
+        assert_eq!(chmod_mode(&dir, "u-s"), 0o3777);
+        assert_eq!(chmod_mode(&dir, "o-t"), 0o2777);
+    }
+
+    #[test]
+    fn shuf_is_reproducible_with_a_seed() {
+        let original: Vec<String> = (0..50).map(|n| n.to_string()).collect();
+
+        let mut first = original.clone();
+        shuffle_lines(&mut first, &mut StdRng::seed_from_u64(42));
+        let mut second = original.clone();
+        shuffle_lines(&mut second, &mut StdRng::seed_from_u64(42));
+
+        assert_eq!(first, second);
+        assert_ne!(first, original);
+        let mut sorted = first.clone();
+        sorted.sort_by_key(|n| n.parse::<u32>().unwrap());
+        assert_eq!(sorted, original);
+    }
+
+    #[test]
+    fn relative_path_walks_up_to_the_common_ancestor() {
+        assert_eq!(relative_path(Path::new("/a/b/c"), Path::new("/a/x/y")), PathBuf::from("../../x/y"));
+        assert_eq!(relative_path(Path::new("/a"), Path::new("/a/b")), PathBuf::from("b"));
+        assert_eq!(relative_path(Path::new("/a/b"), Path::new("/a/b")), PathBuf::from("."));
+    }
+
+    #[test]
+    fn cat_streams_large_and_binary_files() {
+        let dir = tempfile::tempdir().unwrap();
+
+        let large = dir.path().join("large.txt");
+        let line = "0123456789abcdefghijklmnopqrstuvwxyz\n";
+        let content = line.repeat(4 * 1024 * 1024 / line.len());
+        fs::write(&large, &content).unwrap();
+        let mut out = Vec::new();
+        cat_file(&large, CatOptions::default(), &mut CatLines::default(), &mut out).unwrap();
+        assert_eq!(out, content.as_bytes());
+
+        let binary = dir.path().join("binary.bin");
+        let bytes = [0x66, 0xff, 0xfe, 0x00, 0xc3, 0x28, b'\n'];
+        fs::write(&binary, bytes).unwrap();
+        let mut out = Vec::new();
+        cat_file(&binary, CatOptions::default(), &mut CatLines::default(), &mut out).unwrap();
+        assert_eq!(out, bytes);
+
+        let mut out = Vec::new();
+        let options = CatOptions {
+            show_nonprinting: true,
+            show_ends: true,
+            ..CatOptions::default()
+        };
+        cat_file(&binary, options, &mut CatLines::default(), &mut out).unwrap();
+        assert_eq!(out, b"fM-^?M-~^@M-C($\n");
+    }
+
+    #[test]
+    fn crlf_endings_count_as_one_terminator() {
+        let input: &[u8] = b"one\r\ntwo\r\nlast";
+        let counts = wc_count(input, true).unwrap();
+        assert_eq!((counts.lines, counts.chars, counts.bytes), (2, 12, 14));
+
+        let mut out = Vec::new();
+        write_span(input, Span::Last(2), false, true, &mut out).unwrap();
+        assert_eq!(out, b"two\nlast");
+
+        let tmp = tempfile::tempdir().unwrap();
+        let file = tmp.path().join("crlf.txt");
+        fs::write(&file, input).unwrap();
+        let options = CatOptions { number: true, crlf: true, ..CatOptions::default() };
+        let mut out = Vec::new();
+        cat_file(&file, options, &mut CatLines::default(), &mut out).unwrap();
+        assert_eq!(out, b"     1\tone\n     2\ttwo\n     3\tlast");
+    }
+
+    #[test]
+    fn chmod_reference_copies_mode() {
+        let tmp = tempfile::tempdir().unwrap();
+        let reference = tmp.path().join("reference");
+        let target = tmp.path().join("target");
+        fs::write(&reference, "").unwrap();
+        fs::write(&target, "").unwrap();
+        fs::set_permissions(&reference, fs::Permissions::from_mode(0o2751)).unwrap();
+        fs::set_permissions(&target, fs::Permissions::from_mode(0o600)).unwrap();
+
+        let reference_arg = format!("--reference={}", path_arg(&reference));
+        let chmod_args = args(&[&reference_arg, &path_arg(&target)]);
+        let code = handle_chmod(&chmod_args, DryRun(false), Verbosity::Normal).unwrap();
+        assert_eq!(code, 0);
+        assert_eq!(fs::metadata(&target).unwrap().permissions().mode() & 0o7777, 0o2751);
+    }
+
+    #[test]
+    fn chmod_recursive_capital_x_only_marks_directories_and_executables() {
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = tmp.path().join("dir");
+        fs::create_dir_all(dir.join("sub")).unwrap();
+        fs::write(dir.join("data"), "").unwrap();
+        fs::write(dir.join("script"), "").unwrap();
+        let mode_of =
+            |name: &str| fs::metadata(dir.join(name)).unwrap().permissions().mode() & 0o777;
+        for (name, mode) in [("", 0o700), ("sub", 0o700), ("data", 0o600), ("script", 0o700)] {
+            fs::set_permissions(dir.join(name), fs::Permissions::from_mode(mode)).unwrap();
+        }
+
+        let chmod_args = args(&["-R", "a+X", &path_arg(&dir)]);
+        let code = handle_chmod(&chmod_args, DryRun(false), Verbosity::Normal).unwrap();
+        assert_eq!(code, 0);
+        assert_eq!(mode_of(""), 0o711);
+        assert_eq!(mode_of("sub"), 0o711);
+        assert_eq!(mode_of("data"), 0o600);
+        assert_eq!(mode_of("script"), 0o711);
+    }
+
+    #[test]
+    fn touch_updates_directories_and_combined_flags() {
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = tmp.path().join("dir");
+        fs::create_dir(&dir).unwrap();
+        let old = FileTime::from_unix_time(1_000_000_000, 0);
+        set_file_times(&dir, old, old).unwrap();
+
+        assert_eq!(handle_touch(&args(&["-a", "-m", &path_arg(&dir)])).unwrap(), 0);
+        let metadata = fs::metadata(&dir).unwrap();
+        assert!(FileTime::from_last_modification_time(&metadata) > old);
+        assert!(FileTime::from_last_access_time(&metadata) > old);
+
+        set_file_times(&dir, old, old).unwrap();
+        assert_eq!(handle_touch(&args(&["-cm", &path_arg(&dir)])).unwrap(), 0);
+        let metadata = fs::metadata(&dir).unwrap();
+        assert!(FileTime::from_last_modification_time(&metadata) > old);
+        assert_eq!(FileTime::from_last_access_time(&metadata), old);
+
+        let missing = tmp.path().join("missing");
+        assert_eq!(handle_touch(&args(&["-c", &path_arg(&missing)])).unwrap(), 0);
+        assert!(!missing.exists());
+    }
+
+    #[test]
+    fn sort_spills_runs_and_merges_them() {
+        let tmp = tempfile::tempdir().unwrap();
+        let input = tmp.path().join("input");
+        let output = tmp.path().join("output");
+        let numbers: Vec<String> = (0..500).map(|n| ((n * 7919) % 1000).to_string()).collect();
+        fs::write(&input, numbers.join("\n") + "\n").unwrap();
+
+        // A 64-byte buffer forces dozens of runs through the merge
+        let sort_args = args(&["-n", "-S", "64b", "-o", &path_arg(&output), &path_arg(&input)]);
+        let code = handle_sort(&sort_args).unwrap();
+        assert_eq!(code, 0);
+
+        let mut expected: Vec<u32> = numbers.iter().map(|n| n.parse().unwrap()).collect();
+        expected.sort();
+        let sorted: Vec<u32> = fs::read_to_string(&output)
+            .unwrap()
+            .lines()
+            .map(|line| line.parse().unwrap())
+            .collect();
+        assert_eq!(sorted, expected);
+    }
+
+    #[test]
+    fn cp_jobs_copies_large_tree() {
+        let tmp = tempfile::tempdir().unwrap();
+        let src = tmp.path().join("src");
+        for dir in 0..20 {
+            let nested = src.join(format!("dir{}", dir)).join("nested");
+            fs::create_dir_all(&nested).unwrap();
+            for file in 0..25 {
+                let content: Vec<u8> =
+                    (0..(dir * 97 + file * 13)).map(|n| (n * 31 + file) as u8).collect();
+                let top = src.join(format!("dir{}", dir)).join(format!("top{}", file));
+                fs::write(nested.join(format!("file{}", file)), &content).unwrap();
+                fs::write(top, &content[..content.len() / 2]).unwrap();
+            }
+        }
+
+        let dst = tmp.path().join("dst");
+        let cp_args = args(&["-r", "--jobs", "8", &path_arg(&src), &path_arg(&dst)]);
+        let code = handle_cp(&cp_args, DryRun(false), Verbosity::Normal).unwrap();
+        assert_eq!(code, 0);
+
+        let mut pending = vec![PathBuf::new()];
+        let mut compared = 0;
+        while let Some(relative) = pending.pop() {
+            for entry in fs::read_dir(src.join(&relative)).unwrap() {
+                let entry = entry.unwrap();
+                let relative = relative.join(entry.file_name());
+                if entry.file_type().unwrap().is_dir() {
+                    assert!(dst.join(&relative).is_dir());
+                    pending.push(relative);
+                } else {
+                    let original = fs::read(src.join(&relative)).unwrap();
+                    assert_eq!(original, fs::read(dst.join(&relative)).unwrap());
+                    compared += 1;
+                }
+            }
+        }
+        assert_eq!(compared, 20 * 25 * 2);
+    }
+}
@@ -0,0 +1,168 @@
+use std::fs;
+use std::path::Path;
+
+/// Returns true if `text` contains any of the wildcard characters `*`, `?` or `[`.
+pub fn has_wildcards(text: &str) -> bool {
+    text.contains(['*', '?', '['])
+}
+
+/// Matches a single file name against a shell wildcard pattern.
+///
+/// Supports `*`, `?`, bracket classes (`[abc]`, `[a-z]`, `[!a]` / `[^a]`)
+/// and backslash escapes. An unterminated `[` is matched literally.
+pub fn matches(pattern: &str, name: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let name: Vec<char> = name.chars().collect();
+    match_from(&pattern, &name)
+}
+
+fn match_from(pattern: &[char], name: &[char]) -> bool {
+    let Some(&first) = pattern.first() else {
+        return name.is_empty();
+    };
+
+    match first {
+        '*' => (0..=name.len()).any(|skip| match_from(&pattern[1..], &name[skip..])),
+        '?' => !name.is_empty() && match_from(&pattern[1..], &name[1..]),
+        '[' => match parse_class(pattern) {
+            Some(class) => match name.first() {
+                Some(c) => {
+                    let inside = class.ranges.iter().any(|(low, high)| (*low..=*high).contains(c));
+                    inside != class.negated && match_from(&pattern[class.len..], &name[1..])
+                }
+                None => false,
+            },
+            None => name.first() == Some(&'[') && match_from(&pattern[1..], &name[1..]),
+        },
+        '\\' if pattern.len() > 1 => name.first() == Some(&pattern[1]) && match_from(&pattern[2..], &name[1..]),
+        c => name.first() == Some(&c) && match_from(&pattern[1..], &name[1..]),
+    }
+}
+
+// A parsed `[...]` bracket expression
+struct CharClass {
+    len: usize,
+    negated: bool,
+    ranges: Vec<(char, char)>,
+}
+
+// Parses a `[...]` class at the start of `pattern`
+fn parse_class(pattern: &[char]) -> Option<CharClass> {
+    let mut i = 1;
+    let negated = matches!(pattern.get(i), Some('!') | Some('^'));
+    if negated {
+        i += 1;
+    }
+
+    let mut ranges = Vec::new();
+    let start = i;
+    while i < pattern.len() {
+        // A `]` right after the opening bracket is a literal member
+        if pattern[i] == ']' && i > start {
+            return Some(CharClass { len: i + 1, negated, ranges });
+        }
+        let low = pattern[i];
+        if pattern.get(i + 1) == Some(&'-') && pattern.get(i + 2).is_some_and(|c| *c != ']') {
+            ranges.push((low, pattern[i + 2]));
+            i += 3;
+        } else {
+            ranges.push((low, low));
+            i += 1;
+        }
+    }
+    None
+}
+
+/// Expands a path pattern against the filesystem, one component at a time.
+///
+/// Results are sorted. Names starting with `.` only match components whose
+/// pattern also starts with `.`, as in the shell. If nothing matches, the
+/// pattern is returned unchanged.
+pub fn expand(pattern: &str) -> Vec<String> {
+    if !has_wildcards(pattern) {
+        return vec![pattern.to_string()];
+    }
+
+    let dirs_only = pattern.ends_with('/');
+    let mut prefixes = vec![if pattern.starts_with('/') { "/".to_string() } else { String::new() }];
+
+    for component in pattern.split('/').filter(|c| !c.is_empty()) {
+        let mut next = Vec::new();
+        for prefix in &prefixes {
+            if !has_wildcards(component) {
+                next.push(join(prefix, component));
+                continue;
+            }
+
+            let dir = if prefix.is_empty() { "." } else { prefix.as_str() };
+            let Ok(entries) = fs::read_dir(dir) else { continue };
+            let mut names: Vec<String> = entries
+                .filter_map(|entry| entry.ok())
+                .map(|entry| entry.file_name().to_string_lossy().into_owned())
+                .filter(|name| !name.starts_with('.') || component.starts_with('.'))
+                .filter(|name| matches(component, name))
+                .collect();
+            names.sort();
+            next.extend(names.iter().map(|name| join(prefix, name)));
+        }
+        prefixes = next;
+    }
+
+    let found: Vec<String> = prefixes
+        .into_iter()
+        .filter(|path| fs::symlink_metadata(path).is_ok())
+        .filter(|path| !dirs_only || Path::new(path).is_dir())
+        .map(|path| if dirs_only { path + "/" } else { path })
+        .collect();
+
+    if found.is_empty() {
+        vec![pattern.to_string()]
+    } else {
+        found
+    }
+}
+
+fn join(prefix: &str, name: &str) -> String {
+    if prefix.is_empty() {
+        name.to_string()
+    } else if prefix.ends_with('/') {
+        format!("{}{}", prefix, name)
+    } else {
+        format!("{}/{}", prefix, name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matches_wildcards_and_classes() {
+        assert!(matches("*.log", "app.log"));
+        assert!(!matches("*.log", "app.log.1"));
+        assert!(matches("file?.txt", "file1.txt"));
+        assert!(matches("[a-c]x", "bx"));
+        assert!(!matches("[!a-c]x", "bx"));
+        assert!(matches("[]]", "]"));
+        assert!(matches("a\\*", "a*"));
+        assert!(!matches("a\\*", "ab"));
+        assert!(matches("[oops", "[oops"));
+    }
+
+    #[test]
+    fn expand_skips_hidden_files_and_keeps_unmatched_patterns() {
+        let tmp = tempfile::tempdir().unwrap();
+        let root = tmp.path().to_string_lossy().into_owned();
+        for name in ["b.log", "a.log", ".hidden.log", "notes.txt"] {
+            fs::write(tmp.path().join(name), "").unwrap();
+        }
+        fs::create_dir(tmp.path().join("sub")).unwrap();
+        fs::write(tmp.path().join("sub").join("c.log"), "").unwrap();
+
+        assert_eq!(expand(&format!("{}/*.log", root)), vec![format!("{}/a.log", root), format!("{}/b.log", root)]);
+        assert_eq!(expand(&format!("{}/.*.log", root)), vec![format!("{}/.hidden.log", root)]);
+        assert_eq!(expand(&format!("{}/*/c.log", root)), vec![format!("{}/sub/c.log", root)]);
+        assert_eq!(expand(&format!("{}/*/", root)), vec![format!("{}/sub/", root)]);
+        assert_eq!(expand(&format!("{}/*.md", root)), vec![format!("{}/*.md", root)]);
+    }
+}
@@ -1,6 +1,9 @@
 use std::{env, process};
 
 mod commands; // This will contain the individual command implementations
+mod glob;
+mod shell;
+mod timestamp;
 
 fn main() {
     let args: Vec<String> = env::args().collect();
@@ -17,7 +20,8 @@ fn main() {
         Ok(code) => code,
         Err(e) => {
             eprintln!("Error: {}", e);
-            -1 // Generic error for now, specific command errors will override
+            // 127 for an unknown command, -1 for any other error
+            commands::error_exit_code(&e)
         }
     };
 
@@ -0,0 +1,329 @@
+use std::io::{self, BufRead, IsTerminal, Write};
+use std::path::PathBuf;
+
+use anyhow::{anyhow, Result};
+
+use crate::commands::{self, DryRun, Verbosity};
+
+/// Splits a command line into the words of each command in a pipeline, the
+/// way a POSIX shell quotes them.
+///
+/// Single quotes keep everything literally, double quotes let a backslash
+/// escape `"`, `\` and `$`, and outside quotes a backslash escapes any
+/// character. An unquoted `|` separates commands. A `#` at the start of a
+/// word comments out the rest of the line. A blank line has no commands.
+pub fn tokenize(line: &str) -> Result<Vec<Vec<String>>> {
+    let mut commands = Vec::new();
+    let mut words = Vec::new();
+    let mut word = String::new();
+    // Quotes can make an empty word, so an empty `word` alone does not mean none
+    let mut in_word = false;
+    let mut chars = line.chars();
+
+    while let Some(c) = chars.next() {
+        match c {
+            _ if c.is_whitespace() => {
+                if in_word {
+                    words.push(std::mem::take(&mut word));
+                    in_word = false;
+                }
+            }
+            '#' if !in_word => break,
+            '|' => {
+                if in_word {
+                    words.push(std::mem::take(&mut word));
+                    in_word = false;
+                }
+                if words.is_empty() {
+                    return Err(anyhow!("syntax error near unexpected token '|'"));
+                }
+                commands.push(std::mem::take(&mut words));
+            }
+            '\'' => {
+                in_word = true;
+                loop {
+                    match chars.next() {
+                        Some('\'') => break,
+                        Some(c) => word.push(c),
+                        None => return Err(anyhow!("unterminated single quote")),
+                    }
+                }
+            }
+            '"' => {
+                in_word = true;
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => match chars.next() {
+                            Some(c @ ('"' | '\\' | '$')) => word.push(c),
+                            Some(c) => {
+                                word.push('\\');
+                                word.push(c);
+                            }
+                            None => return Err(anyhow!("unterminated double quote")),
+                        },
+                        Some(c) => word.push(c),
+                        None => return Err(anyhow!("unterminated double quote")),
+                    }
+                }
+            }
+            '\\' => {
+                in_word = true;
+                // A trailing backslash stands for itself
+                word.push(chars.next().unwrap_or('\\'));
+            }
+            _ => {
+                in_word = true;
+                word.push(c);
+            }
+        }
+    }
+    if in_word {
+        words.push(word);
+    }
+    if !words.is_empty() {
+        commands.push(words);
+    } else if !commands.is_empty() {
+        return Err(anyhow!("syntax error: a pipeline cannot end with '|'"));
+    }
+    Ok(commands)
+}
+
+/// `rustybox --shell`: reads command lines from stdin and runs each one in
+/// this process until `exit` or end of input. The global flags given before
+/// `--shell` apply to every command. Returns the status of the last command.
+pub fn handle_shell(args: &[String], dry_run: DryRun, verbosity: Verbosity) -> Result<i32> {
+    if !args.is_empty() {
+        return Err(anyhow!("shell: too many arguments"));
+    }
+    // Like sh, only prompt when someone is typing
+    let interactive = io::stdin().is_terminal();
+    let mut session = Session::default();
+    let mut status = 0;
+    let mut line = String::new();
+
+    loop {
+        if interactive {
+            print!("rustybox$ ");
+            io::stdout().flush()?;
+        }
+        line.clear();
+        // The lock is only held for the read, so commands can use stdin too
+        if io::stdin().lock().read_line(&mut line)? == 0 {
+            if interactive {
+                println!();
+            }
+            return Ok(status);
+        }
+
+        let pipeline = match tokenize(&line) {
+            Ok(pipeline) => pipeline,
+            Err(e) => {
+                eprintln!("shell: {}", e);
+                status = 2;
+                continue;
+            }
+        };
+        let words = match pipeline.as_slice() {
+            [] => continue,
+            [words] => words,
+            _ => {
+                status = run_pipeline(&pipeline);
+                continue;
+            }
+        };
+        status = match words[0].as_str() {
+            "exit" => return exit_status(&words[1..], status),
+            "cd" => session.cd(&words[1..]),
+            _ => run(words, dry_run, verbosity),
+        };
+    }
+}
+
+// `exit [N]` ends the session with N, or with the last status
+fn exit_status(args: &[String], status: i32) -> Result<i32> {
+    match args {
+        [] => Ok(status),
+        [code] => code
+            .parse()
+            .map_err(|_| anyhow!("exit: {}: numeric argument required", code)),
+        _ => Err(anyhow!("exit: too many arguments")),
+    }
+}
+
+/// What the shell remembers between command lines. The current directory
+/// itself is the process's, so every command resolves relative paths
+/// against wherever the last `cd` went.
+#[derive(Debug, Default)]
+struct Session {
+    /// Where `cd -` goes back to
+    previous: Option<PathBuf>,
+}
+
+impl Session {
+    // `cd [DIR]` changes to DIR or $HOME, and `cd -` to the previous
+    // directory, printing it as sh does. This has to happen in this process,
+    // so it cannot be an ordinary command.
+    fn cd(&mut self, args: &[String]) -> i32 {
+        let target = match args {
+            [] => match std::env::var_os("HOME") {
+                Some(home) => PathBuf::from(home),
+                None => {
+                    eprintln!("cd: HOME not set");
+                    return 1;
+                }
+            },
+            [dir] if dir == "-" => match &self.previous {
+                Some(previous) => {
+                    println!("{}", previous.display());
+                    previous.clone()
+                }
+                None => {
+                    eprintln!("cd: no previous directory");
+                    return 1;
+                }
+            },
+            [dir] => PathBuf::from(dir),
+            _ => {
+                eprintln!("cd: too many arguments");
+                return 1;
+            }
+        };
+
+        let current = std::env::current_dir().ok();
+        match std::env::set_current_dir(&target) {
+            Ok(()) => {
+                self.previous = current;
+                0
+            }
+            Err(e) => {
+                eprintln!("cd: {}: {}", target.display(), e);
+                1
+            }
+        }
+    }
+}
+
+// Runs one command and reports a failure the way main does
+fn run(words: &[String], dry_run: DryRun, verbosity: Verbosity) -> i32 {
+    let result = commands::dispatch_nested(words, dry_run, verbosity);
+    // Whatever the command printed has to show before the next prompt
+    let _ = io::stdout().flush();
+    match result {
+        Ok(code) => code,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            commands::error_exit_code(&e)
+        }
+    }
+}
+
+// Runs every command of a pipeline on its own thread, each one reading what
+// the one before it writes through an OS pipe. The first reads the shell's
+// stdin and the last writes its stdout. Returns the last command's status,
+// as sh does.
+fn run_pipeline(pipeline: &[Vec<String>]) -> i32 {
+    let mut handlers = Vec::new();
+    for words in pipeline {
+        match commands::stream_command(&words[0]) {
+            Some(handler) => handlers.push(handler),
+            None if commands::supported_commands().contains(&words[0].as_str()) => {
+                eprintln!("shell: {}: cannot be used in a pipeline", words[0]);
+                return 1;
+            }
+            None => {
+                eprintln!("Error: {}", commands::UnknownCommand(words[0].clone()));
+                return commands::COMMAND_NOT_FOUND;
+            }
+        }
+    }
+    let mut pipes = Vec::new();
+    for _ in 1..pipeline.len() {
+        match io::pipe() {
+            Ok(pipe) => pipes.push(pipe),
+            Err(e) => {
+                eprintln!("shell: cannot create a pipe: {}", e);
+                return 1;
+            }
+        }
+    }
+
+    let (readers, writers): (Vec<_>, Vec<_>) = pipes.into_iter().unzip();
+    let inputs = std::iter::once(None).chain(readers.into_iter().map(Some));
+    let outputs = writers.into_iter().map(Some).chain(std::iter::once(None));
+    std::thread::scope(|scope| {
+        let stages: Vec<_> = pipeline
+            .iter()
+            .zip(handlers)
+            .zip(inputs.zip(outputs))
+            .map(|((words, handler), (input, output))| {
+                scope.spawn(move || run_stage(handler, words, input, output))
+            })
+            .collect();
+        // Every stage has to finish, not only the last one
+        let statuses: Vec<i32> =
+            stages.into_iter().map(|stage| stage.join().unwrap_or(-1)).collect();
+        statuses.last().copied().unwrap_or(0)
+    })
+}
+
+// One command of a pipeline. Its pipe ends are dropped when it returns, which
+// is what lets the next command see the end of its input.
+fn run_stage(
+    handler: commands::StreamHandler,
+    words: &[String],
+    input: Option<io::PipeReader>,
+    output: Option<io::PipeWriter>,
+) -> i32 {
+    let mut input: Box<dyn BufRead> = match input {
+        Some(reader) => Box::new(io::BufReader::new(reader)),
+        None => Box::new(io::stdin().lock()),
+    };
+    let mut output: Box<dyn Write> = match output {
+        Some(writer) => Box::new(io::BufWriter::new(writer)),
+        None => Box::new(io::stdout().lock()),
+    };
+    let result = handler(&words[1..], &mut input, &mut output);
+    let _ = output.flush();
+    match result {
+        Ok(code) => code,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            commands::error_exit_code(&e)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn words(line: &str) -> Vec<String> {
+        tokenize(line).unwrap().concat()
+    }
+
+    #[test]
+    fn tokenize_respects_quotes_and_escapes() {
+        assert_eq!(words("  ls   -l  dir "), ["ls", "-l", "dir"]);
+        assert_eq!(words("echo 'a  b' \"c d\" e\\ f"), ["echo", "a  b", "c d", "e f"]);
+        assert_eq!(words("echo '' \"\" x"), ["echo", "", "", "x"]);
+        assert_eq!(words("echo \"say \\\"hi\\\" \\n\""), ["echo", "say \"hi\" \\n"]);
+        assert_eq!(words("echo 'it'\"'\"'s'"), ["echo", "it's"]);
+        assert_eq!(words("echo a#b # comment"), ["echo", "a#b"]);
+        assert!(words("# only a comment").is_empty());
+
+        assert!(tokenize("echo 'open").is_err());
+        assert!(tokenize("echo \"open").is_err());
+    }
+
+    #[test]
+    fn tokenize_splits_pipelines_on_unquoted_bars() {
+        let pipeline = tokenize("cat f|grep 'a|b' | wc -l").unwrap();
+        assert_eq!(pipeline, [vec!["cat", "f"], vec!["grep", "a|b"], vec!["wc", "-l"]]);
+        assert!(tokenize("   ").unwrap().is_empty());
+
+        for invalid in ["| wc", "cat f |", "cat f || wc"] {
+            assert!(tokenize(invalid).is_err(), "{}", invalid);
+        }
+    }
+}
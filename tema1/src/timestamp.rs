@@ -0,0 +1,117 @@
+use chrono::{DateTime, Datelike, Local, NaiveDate, NaiveDateTime, TimeZone, Utc};
+
+/// Parses a `touch -t` stamp, `[[CC]YY]MMDDhhmm[.ss]`, as local time.
+///
+/// Without a century, years 69-99 mean 19xx and 00-68 mean 20xx, as POSIX
+/// specifies. Without a year, the current one is used.
+pub fn parse_stamp(stamp: &str) -> Option<DateTime<Utc>> {
+    let (digits, seconds) = match stamp.split_once('.') {
+        Some((digits, seconds)) => (digits, Some(seconds)),
+        None => (stamp, None),
+    };
+    let all_digits = |text: &str| text.bytes().all(|b| b.is_ascii_digit());
+    if !all_digits(digits) {
+        return None;
+    }
+    let seconds = match seconds {
+        Some(seconds) if seconds.len() == 2 && all_digits(seconds) => seconds.parse().ok()?,
+        Some(_) => return None,
+        None => 0,
+    };
+
+    let number = |start: usize, len: usize| digits[start..start + len].parse::<u32>().ok();
+    let (year, rest) = match digits.len() {
+        8 => (Local::now().year(), 0),
+        10 => {
+            let short = number(0, 2)? as i32;
+            (if short >= 69 { 1900 + short } else { 2000 + short }, 2)
+        }
+        12 => (number(0, 4)? as i32, 4),
+        _ => return None,
+    };
+    let date = NaiveDate::from_ymd_opt(year, number(rest, 2)?, number(rest + 2, 2)?)?;
+    let time = date.and_hms_opt(number(rest + 4, 2)?, number(rest + 6, 2)?, seconds)?;
+    from_local(&time)
+}
+
+/// Parses a date as `date -d`, `touch -d` and `find -newermt` accept it.
+///
+/// Understands `now`, `@SECONDS` since the epoch, and ISO 8601 dates with an
+/// optional time: `2024-03-01`, `2024-03-01 12:30`, `2024-03-01T12:30:15.5`.
+/// A trailing `Z` or `+hh:mm` offset pins the zone; otherwise the time is
+/// local.
+pub fn parse_date(text: &str) -> Option<DateTime<Utc>> {
+    let text = text.trim();
+    if text == "now" {
+        return Some(Utc::now());
+    }
+    if let Some(seconds) = text.strip_prefix('@') {
+        return DateTime::from_timestamp(seconds.parse().ok()?, 0);
+    }
+
+    let text = match text.strip_suffix('Z') {
+        Some(stripped) => format!("{}+00:00", stripped),
+        None => text.to_string(),
+    };
+    for separator in ['T', ' '] {
+        for time in ["%H:%M:%S%.f", "%H:%M"] {
+            let format = format!("%Y-%m-%d{}{}", separator, time);
+            if let Ok(zoned) = DateTime::parse_from_str(&text, &format!("{}%#z", format)) {
+                return Some(zoned.with_timezone(&Utc));
+            }
+            if let Ok(naive) = NaiveDateTime::parse_from_str(&text, &format) {
+                return from_local(&naive);
+            }
+        }
+    }
+    let date = NaiveDate::parse_from_str(&text, "%Y-%m-%d").ok()?;
+    from_local(&date.and_hms_opt(0, 0, 0)?)
+}
+
+// A local time that falls in a DST gap does not exist and is rejected; one
+// that repeats takes the earlier instant
+fn from_local(time: &NaiveDateTime) -> Option<DateTime<Utc>> {
+    Local.from_local_datetime(time).earliest().map(|local| local.with_timezone(&Utc))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn local(year: i32, month: u32, day: u32, hour: u32, minute: u32, second: u32) -> DateTime<Utc> {
+        Local
+            .with_ymd_and_hms(year, month, day, hour, minute, second)
+            .unwrap()
+            .with_timezone(&Utc)
+    }
+
+    #[test]
+    fn parse_stamp_handles_every_length() {
+        assert_eq!(parse_stamp("202403011230.15"), Some(local(2024, 3, 1, 12, 30, 15)));
+        assert_eq!(parse_stamp("2403011230"), Some(local(2024, 3, 1, 12, 30, 0)));
+        assert_eq!(parse_stamp("6903011230"), Some(local(1969, 3, 1, 12, 30, 0)));
+        let this_year = Local::now().year();
+        assert_eq!(parse_stamp("03011230"), Some(local(this_year, 3, 1, 12, 30, 0)));
+
+        for invalid in ["0301123", "202413011230", "2403011230.5", "24030112x0", ""] {
+            assert_eq!(parse_stamp(invalid), None, "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn parse_date_accepts_iso_and_epoch_forms() {
+        assert_eq!(parse_date("2024-03-01"), Some(local(2024, 3, 1, 0, 0, 0)));
+        assert_eq!(parse_date("2024-03-01 12:30"), Some(local(2024, 3, 1, 12, 30, 0)));
+        assert_eq!(parse_date("2024-03-01T12:30:15"), Some(local(2024, 3, 1, 12, 30, 15)));
+        let utc = Utc.with_ymd_and_hms(2024, 3, 1, 12, 30, 15).unwrap();
+        assert_eq!(parse_date("2024-03-01T12:30:15Z"), Some(utc));
+        assert_eq!(parse_date("2024-03-01T14:30:15+02:00"), Some(utc));
+        assert_eq!(parse_date(&format!("@{}", utc.timestamp())), Some(utc));
+        let fraction = parse_date("2024-03-01T12:30:15.5Z").unwrap();
+        assert_eq!(fraction.timestamp_subsec_millis(), 500);
+
+        for invalid in ["yesterday", "2024-02-30", "2024-03-01T25:00", "@x"] {
+            assert_eq!(parse_date(invalid), None, "{}", invalid);
+        }
+    }
+}
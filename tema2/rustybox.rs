@@ -5,6 +5,10 @@ use std::os::unix::fs::{MetadataExt, PermissionsExt, symlink};
 use std::path::{Path, PathBuf};
 use std::process;
 
+// Codul de iesire pentru o comanda necunoscuta, 127 ca la shell-uri
+// ("command not found"); tema1 foloseste acelasi cod
+const COMMAND_NOT_FOUND: i32 = 127;
+
 fn main() {
     let args: Vec<String> = env::args().collect();
 
@@ -31,7 +35,7 @@ fn main() {
         "chmod" => cmd_chmod(params),
         _ => {
             println!("Invalid command");
-            Err(-1)
+            Err(COMMAND_NOT_FOUND)
         }
     };
 